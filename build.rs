@@ -1,9 +1,38 @@
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
+use ed25519_compact::{KeyPair, Seed};
 use futures::executor::block_on;
 
-async fn build_initium_as_efi(out_dir: &Path) -> PathBuf {
+/// Returns the paths of the signing key seed and the matching public key.
+///
+/// The seed is read from `LIFE_SIGNING_KEY` if set, otherwise one is generated
+/// once per build directory.
+fn load_signing_key(out_dir: &Path) -> (PathBuf, PathBuf) {
+    println!("cargo:rerun-if-env-changed=LIFE_SIGNING_KEY");
+
+    let seed_path = match std::env::var_os("LIFE_SIGNING_KEY") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let path = out_dir.join("signing.key");
+            if !path.exists() {
+                std::fs::write(&path, Seed::generate().as_ref()).expect("failed to write signing key");
+            }
+            path
+        }
+    };
+
+    let seed = std::fs::read(&seed_path).expect("failed to read signing key");
+    let seed = Seed::from_slice(&seed).expect("signing key must be a raw 32 byte Ed25519 seed");
+    let key_pair = KeyPair::from_seed(seed);
+
+    let public_key_path = out_dir.join("signing.pub");
+    std::fs::write(&public_key_path, key_pair.pk.as_ref()).expect("failed to write public key");
+
+    (seed_path, public_key_path)
+}
+
+async fn build_initium_as_efi(out_dir: &Path, public_key: &Path) -> PathBuf {
     let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".into());
     let mut cmd = Command::new(cargo);
     cmd.arg("install").arg("initium");
@@ -15,6 +44,7 @@ async fn build_initium_as_efi(out_dir: &Path) -> PathBuf {
         .arg("-Zbuild-std-features=compiler-builtins-mem");
 
     cmd.arg("--root").arg(out_dir);
+    cmd.env("LIFE_PUBLIC_KEY", public_key);
     cmd.env_remove("RUSTFLAGS");
     cmd.env_remove("CARGO_ENCODED_RUSTFLAGS");
 
@@ -37,11 +67,13 @@ async fn build_initium_as_efi(out_dir: &Path) -> PathBuf {
 async fn build() {
     let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
     let nukleus_file = PathBuf::from(std::env::var_os("CARGO_BIN_FILE_NUKLEUS_nukleus").unwrap());
-    let initium_file = build_initium_as_efi(&out_dir).await;
+    let (signing_key, public_key) = load_signing_key(&out_dir);
+    let initium_file = build_initium_as_efi(&out_dir, &public_key).await;
 
     println!("cargo:rustc-env=OUT_DIR={}", out_dir.display());
     println!("cargo:rustc-env=CARGO_BIN_FILE_NUKLEUS_nukleus={}", nukleus_file.display());
     println!("cargo:rustc-env=CARGO_BIN_FILE_INITIUM_initium={}", initium_file.display());
+    println!("cargo:rustc-env=LIFE_SIGNING_KEY={}", signing_key.display());
 }
 
 fn main() {
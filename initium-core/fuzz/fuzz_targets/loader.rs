@@ -0,0 +1,47 @@
+//! Loads arbitrary bytes as a kernel into a simulated page table, run with
+//! `cargo +nightly fuzz run loader` in `initium-core`.
+
+#![no_main]
+
+#[path = "../../tests/common/mod.rs"]
+mod common;
+
+use common::{Arena, PAGE_SIZE};
+use initium_core::{
+    entries::Entries,
+    kernel::{load_kernel, Kernel},
+};
+use libfuzzer_sys::fuzz_target;
+use x86_64::structures::paging::PageTable;
+
+/// Frames available to the loader, running out must be an error as well.
+const ARENA_PAGES: usize = 1024;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() > ARENA_PAGES / 2 * PAGE_SIZE as usize {
+        return;
+    }
+
+    let mut arena = Arena::new(ARENA_PAGES);
+    let kernel = arena.store(data);
+    let mut page_table = arena.page_table();
+
+    let Ok(kernel) = Kernel::parse(kernel) else {
+        return;
+    };
+    if load_kernel(kernel, &mut page_table, &mut arena, &mut Entries::new()).is_ok() {
+        check_frames(&arena, page_table.level_4_table(), 4);
+    }
+});
+
+/// Checks that the page table only maps simulated physical memory.
+fn check_frames(arena: &Arena, table: &PageTable, level: u8) {
+    for entry in table.iter().filter(|entry| !entry.is_unused()) {
+        let address = entry.addr().as_u64();
+        assert!(arena.contains(address), "{address:#x} is outside of physical memory");
+
+        if level > 1 {
+            check_frames(arena, unsafe { &*(address as *const PageTable) }, level - 1);
+        }
+    }
+}
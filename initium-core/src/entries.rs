@@ -0,0 +1,208 @@
+use core::mem::MaybeUninit;
+
+use x86_64::{
+    structures::paging::{PageSize, Size4KiB},
+    VirtAddr,
+};
+
+use synapse::memory::{VirtualRegion, VirtualRegionKind};
+use xmas_elf::program::ProgramHeader;
+
+use crate::kernel::VirtualAddressOffset;
+
+/// Maximum number of reserved ranges, enough for one stack per application processor.
+const MAX_RANGES: usize = 512;
+
+/// Unmapped space left between two reserved ranges.
+const GUARD_GAP: u64 = Size4KiB::SIZE;
+
+/// Size of the address space covered by a single level 4 entry.
+const LEVEL_4_SIZE: u64 = 4096 * 512 * 512 * 512;
+
+/// Canonical halves of the address space, without the last page.
+const ADDRESS_SPACES: [(u64, u64); 2] = [
+    (0, 0x0000_8000_0000_0000),
+    (0xffff_8000_0000_0000, 0xffff_ffff_ffff_f000),
+];
+
+fn align_up(value: u64, alignment: u64) -> Option<u64> {
+    Some(value.checked_add(alignment - 1)? & !(alignment - 1))
+}
+
+/// Allocator for the virtual address space of the kernel.
+///
+/// Keeps the reserved ranges sorted by start address, at page granularity.
+pub struct Entries {
+    ranges: [VirtualRegion; MAX_RANGES],
+    len: usize,
+}
+
+impl Default for Entries {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Entries {
+    pub fn new() -> Self {
+        let mut used = Entries {
+            ranges: [VirtualRegion {
+                start: 0,
+                end: 0,
+                kind: VirtualRegionKind::Identity,
+            }; MAX_RANGES],
+            len: 0,
+        };
+
+        // the first level 4 entry holds identity mappings of the bootloader
+        used.insert(0, LEVEL_4_SIZE, VirtualRegionKind::Identity);
+
+        used
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn regions(&self) -> &[VirtualRegion] {
+        &self.ranges[..self.len]
+    }
+
+    /// Reserves the segments of the kernel, which must not overlap with earlier reservations
+    /// of other kinds.
+    ///
+    /// Called before any other allocation, so fixed-address kernels are only rejected when
+    /// they are linked into the first level 4 entry.
+    pub fn mark_segments<'a>(
+        &mut self,
+        segments: impl Iterator<Item=ProgramHeader<'a>>,
+        virtual_address_offset: VirtualAddressOffset,
+    ) -> Result<(), &'static str> {
+        for segment in segments.filter(|s| s.mem_size() > 0) {
+            let address = virtual_address_offset
+                .checked_add(segment.virtual_addr())
+                .ok_or("kernel segment address is not canonical")?;
+            self.mark_range_as_used(address, segment.mem_size(), VirtualRegionKind::Kernel)?;
+        }
+
+        Ok(())
+    }
+
+    fn mark_range_as_used(
+        &mut self,
+        address: u64,
+        size: u64,
+        kind: VirtualRegionKind,
+    ) -> Result<(), &'static str> {
+        let start = VirtAddr::try_new(address)
+            .map_err(|_| "kernel segment address is not canonical")?
+            .align_down(Size4KiB::SIZE)
+            .as_u64();
+        let end = address
+            .checked_add(size)
+            .ok_or("kernel segment end address overflows")?;
+        VirtAddr::try_new(end - 1).map_err(|_| "kernel segment end address is not canonical")?;
+        let end = align_up(end, Size4KiB::SIZE).ok_or("kernel segment end address overflows")?;
+
+        for range in self.regions() {
+            if range.kind != kind && range.start < end && start < range.end {
+                return Err(match range.kind {
+                    VirtualRegionKind::Identity => {
+                        "kernel is linked into the first 512 GiB, which the bootloader reserves for \
+                         identity mappings; link it in the higher half, e.g. at 0xffffffff80000000"
+                    }
+                    _ => "kernel segment overlaps a region reserved by the bootloader",
+                });
+            }
+        }
+
+        self.insert(start, end, kind);
+        Ok(())
+    }
+
+    /// Inserts a range, merging it with overlapping or adjacent ranges of the same kind.
+    fn insert(&mut self, mut start: u64, mut end: u64, kind: VirtualRegionKind) {
+        let mut i = 0;
+        while i < self.len {
+            let range = self.ranges[i];
+            if range.kind == kind && range.start <= end && start <= range.end {
+                start = start.min(range.start);
+                end = end.max(range.end);
+                self.ranges.copy_within(i + 1..self.len, i);
+                self.len -= 1;
+            } else {
+                i += 1;
+            }
+        }
+
+        assert!(self.len < MAX_RANGES, "too many virtual address ranges");
+
+        let index = self.ranges[..self.len]
+            .iter()
+            .position(|range| range.start > start)
+            .unwrap_or(self.len);
+        self.ranges.copy_within(index..self.len, index + 1);
+        self.ranges[index] = VirtualRegion { start, end, kind };
+        self.len += 1;
+    }
+
+    /// Returns the lowest free address of `size` bytes with the given alignment and reserves it.
+    ///
+    /// Allocations are page-aligned at least and keep a guard gap to their neighbours.
+    pub fn get_free_address(&mut self, size: u64, alignment: u64, kind: VirtualRegionKind) -> VirtAddr {
+        self.try_get_free_address(size, alignment, kind).unwrap_or_else(|| {
+            panic!("no free virtual address range for {kind:?} ({size:#x} bytes, alignment {alignment:#x})")
+        })
+    }
+
+    /// Like `get_free_address`, but `None` if no range is large enough.
+    pub fn try_get_free_address(&mut self, size: u64, alignment: u64, kind: VirtualRegionKind) -> Option<VirtAddr> {
+        assert!(alignment.is_power_of_two());
+
+        let alignment = alignment.max(Size4KiB::SIZE);
+        let size = align_up(size.max(1), Size4KiB::SIZE)?;
+
+        for (space_start, space_end) in ADDRESS_SPACES {
+            let Some(mut candidate) = align_up(space_start, alignment) else {
+                continue;
+            };
+
+            for range in self.regions() {
+                if range.end.saturating_add(GUARD_GAP) <= candidate {
+                    continue;
+                }
+                if candidate.saturating_add(size).saturating_add(GUARD_GAP) <= range.start {
+                    break;
+                }
+                match range.end.checked_add(GUARD_GAP).and_then(|next| align_up(next, alignment)) {
+                    Some(next) => candidate = next,
+                    None => candidate = u64::MAX,
+                }
+            }
+
+            if candidate.saturating_add(size) <= space_end {
+                self.insert(candidate, candidate + size, kind);
+                return Some(VirtAddr::new(candidate));
+            }
+        }
+
+        None
+    }
+
+    /// Copies the reserved ranges into `regions`, which must hold at least `len` entries.
+    pub fn write_regions(
+        &self,
+        regions: &'static mut [MaybeUninit<VirtualRegion>],
+    ) -> &'static mut [VirtualRegion] {
+        let regions = &mut regions[..self.len];
+        for (slot, range) in regions.iter_mut().zip(self.regions()) {
+            slot.write(*range);
+        }
+
+        unsafe { &mut *(regions as *mut [MaybeUninit<VirtualRegion>] as *mut [VirtualRegion]) }
+    }
+}
@@ -0,0 +1,154 @@
+/// Uncompressed BMP or QOI image, e.g. the boot splash.
+pub enum Image<'a> {
+    Bmp {
+        width: usize,
+        height: usize,
+        top_down: bool,
+        bytes_per_pixel: usize,
+        row_len: usize,
+        pixels: &'a [u8],
+    },
+    Qoi {
+        width: usize,
+        height: usize,
+        chunks: &'a [u8],
+    },
+}
+
+impl<'a> Image<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, &'static str> {
+        let u16_le = |offset: usize| data.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+        let u32_le = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+        let u32_be = |offset: usize| data.get(offset..offset + 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()));
+
+        match data.get(..4) {
+            Some([b'B', b'M', _, _]) => {
+                let truncated = "BMP header is truncated";
+                let offset = u32_le(10).ok_or(truncated)? as usize;
+                let width = u32_le(18).ok_or(truncated)? as i32;
+                let height = u32_le(22).ok_or(truncated)? as i32;
+                let bits_per_pixel = u16_le(28).ok_or(truncated)?;
+                let compression = u32_le(30).ok_or(truncated)?;
+
+                // uncompressed, 32-bit images may declare the default BGRA masks as bitfields
+                if !matches!((bits_per_pixel, compression), (24, 0) | (32, 0) | (32, 3)) {
+                    return Err("only uncompressed 24-bit and 32-bit BMP images are supported");
+                }
+                if width <= 0 || height == 0 {
+                    return Err("BMP image is empty");
+                }
+
+                let width = width as usize;
+                let bytes_per_pixel = usize::from(bits_per_pixel / 8);
+                let row_len = (width * bytes_per_pixel + 3) & !3;
+                let pixels = data.get(offset..).ok_or("BMP pixel data is truncated")?;
+                if pixels.len() < row_len * height.unsigned_abs() as usize {
+                    return Err("BMP pixel data is truncated");
+                }
+
+                Ok(Image::Bmp {
+                    width,
+                    height: height.unsigned_abs() as usize,
+                    top_down: height < 0,
+                    bytes_per_pixel,
+                    row_len,
+                    pixels,
+                })
+            }
+            Some(b"qoif") => {
+                let width = u32_be(4).ok_or("QOI header is truncated")? as usize;
+                let height = u32_be(8).ok_or("QOI header is truncated")? as usize;
+                if width == 0 || height == 0 {
+                    return Err("QOI image is empty");
+                }
+
+                Ok(Image::Qoi {
+                    width,
+                    height,
+                    chunks: data.get(14..).ok_or("QOI header is truncated")?,
+                })
+            }
+            _ => Err("the splash image is neither a BMP nor a QOI image"),
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        match *self {
+            Image::Bmp { width, height, .. } | Image::Qoi { width, height, .. } => (width, height),
+        }
+    }
+
+    /// Calls `put_pixel` with the coordinates and the RGB color of every pixel.
+    pub fn draw(&self, mut put_pixel: impl FnMut(usize, usize, [u8; 3])) {
+        match *self {
+            Image::Bmp {
+                width,
+                height,
+                top_down,
+                bytes_per_pixel,
+                row_len,
+                pixels,
+            } => {
+                for row in 0..height {
+                    let y = if top_down { row } else { height - 1 - row };
+                    let row_pixels = &pixels[row * row_len..];
+                    for x in 0..width {
+                        let bgr = &row_pixels[x * bytes_per_pixel..];
+                        put_pixel(x, y, [bgr[2], bgr[1], bgr[0]]);
+                    }
+                }
+            }
+            Image::Qoi { width, height, chunks } => {
+                let mut index = [[0u8; 4]; 64];
+                let mut pixel = [0, 0, 0, 255u8];
+                let mut run = 0;
+                let mut position = 0;
+
+                for i in 0..width * height {
+                    if run > 0 {
+                        run -= 1;
+                    } else if let Some(&tag) = chunks.get(position) {
+                        position += 1;
+                        let mut next = || {
+                            let byte = chunks.get(position).copied().unwrap_or(0);
+                            position += 1;
+                            byte
+                        };
+
+                        match tag {
+                            0xfe => pixel[..3].copy_from_slice(&[next(), next(), next()]),
+                            0xff => pixel = [next(), next(), next(), next()],
+                            _ => match tag >> 6 {
+                                0 => pixel = index[usize::from(tag & 0x3f)],
+                                1 => {
+                                    pixel[0] = pixel[0].wrapping_add((tag >> 4) & 3).wrapping_sub(2);
+                                    pixel[1] = pixel[1].wrapping_add((tag >> 2) & 3).wrapping_sub(2);
+                                    pixel[2] = pixel[2].wrapping_add(tag & 3).wrapping_sub(2);
+                                }
+                                2 => {
+                                    let green = (tag & 0x3f).wrapping_sub(32);
+                                    let byte = next();
+                                    pixel[0] = pixel[0].wrapping_add(green.wrapping_sub(8).wrapping_add(byte >> 4));
+                                    pixel[1] = pixel[1].wrapping_add(green);
+                                    pixel[2] = pixel[2].wrapping_add(green.wrapping_sub(8).wrapping_add(byte & 0xf));
+                                }
+                                _ => run = tag & 0x3f,
+                            },
+                        }
+
+                        let [r, g, b, a] = pixel.map(usize::from);
+                        index[(r * 3 + g * 5 + b * 7 + a * 11) % 64] = pixel;
+                    } else {
+                        // a truncated stream leaves the rest of the image black
+                        break;
+                    }
+
+                    // blend transparent pixels over the black background
+                    let alpha = u16::from(pixel[3]);
+                    let blend = |c: u8| (u16::from(c) * alpha / 255) as u8;
+                    put_pixel(i % width, i / width, [blend(pixel[0]), blend(pixel[1]), blend(pixel[2])]);
+                }
+            }
+        }
+    }
+}
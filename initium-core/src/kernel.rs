@@ -1,7 +1,6 @@
 use core::{cmp, iter::Step, mem::size_of, ops::Add};
 
 use x86_64::{
-    align_up,
     structures::paging::{
         mapper::{MappedFrame, MapperAllSizes, TranslateResult},
         FrameAllocator, Page, PageSize, PageTableFlags as Flags, PhysFrame, Size4KiB, Translate,
@@ -10,12 +9,14 @@ use x86_64::{
 };
 
 use xmas_elf::{
-    dynamic, header,
-    program::{self, ProgramHeader, SegmentData, Type},
+    dynamic,
+    header::{self, Class, Machine},
+    program::{ProgramHeader, ProgramHeader32, ProgramHeader64, SegmentData, Type},
     sections::Rela,
     ElfFile,
 };
 
+use synapse::memory::VirtualRegionKind;
 use synapse::tls_template::TlsTemplate;
 use crate::entries::Entries;
 
@@ -40,6 +41,11 @@ impl VirtualAddressOffset {
     pub fn virtual_address_offset(&self) -> i128 {
         self.virtual_address_offset
     }
+
+    /// Like `+`, but `None` if the result does not fit in 64 bits.
+    pub fn checked_add(self, offset: u64) -> Option<u64> {
+        u64::try_from(self.virtual_address_offset + i128::from(offset)).ok()
+    }
 }
 
 impl Add<u64> for VirtualAddressOffset {
@@ -60,18 +66,104 @@ pub struct Kernel<'a> {
 }
 
 impl<'a> Kernel<'a> {
-    pub fn parse(kernel_slice: &'a [u8]) -> Self {
-        let kernel_elf = ElfFile::new(kernel_slice).unwrap();
-        Kernel {
+    /// Parses the kernel, which must be page-aligned, and checks everything about it that does not
+    /// depend on the memory it is loaded to.
+    pub fn parse(kernel_slice: &'a [u8]) -> Result<Self, &'static str> {
+        if kernel_slice.as_ptr() as u64 % PAGE_SIZE != 0 {
+            return Err("Loaded kernel ELF file is not sufficiently aligned");
+        }
+
+        let kernel_elf = ElfFile::new(kernel_slice)?;
+        check_headers(&kernel_elf)?;
+        check_load_segments(&kernel_elf)?;
+        check_is_in_load(&kernel_elf, kernel_elf.header.pt2.entry_point(), 1)
+            .map_err(|_| "entry point is not in a load segment")?;
+        Ok(Kernel {
             elf: kernel_elf,
             start_address: kernel_slice.as_ptr(),
             len: kernel_slice.len(),
+        })
+    }
+}
+
+/// Checks the ELF header and the program headers.
+fn check_headers(elf_file: &ElfFile) -> Result<(), &'static str> {
+    if elf_file.header.pt1.class() != Class::SixtyFour {
+        return Err("kernel is not a 64-bit ELF file");
+    }
+    if elf_file.header.pt2.machine().as_machine() != Machine::X86_64 {
+        return Err("kernel is not an x86_64 ELF file");
+    }
+    if !matches!(
+        elf_file.header.pt2.type_().as_type(),
+        header::Type::Executable | header::Type::SharedObject
+    ) {
+        return Err("kernel is neither an executable nor a position-independent executable");
+    }
+
+    check_bounds(elf_file)
+}
+
+/// Checks that the header tables and the segments of an ELF file of either class lie within the
+/// file.
+///
+/// xmas-elf indexes the file with the offsets and sizes of the headers and panics if they are out
+/// of range or misaligned, so they are checked with overflow-checked arithmetic first.
+pub fn check_bounds(elf_file: &ElfFile) -> Result<(), &'static str> {
+    let len = elf_file.input.len() as u64;
+    let header = &elf_file.header.pt2;
+
+    let (ph_entry_size, ph_align) = match elf_file.header.pt1.class() {
+        Class::SixtyFour => (size_of::<ProgramHeader64>(), 8),
+        Class::ThirtyTwo => (size_of::<ProgramHeader32>(), 4),
+        _ => return Err("invalid ELF class"),
+    };
+    if usize::from(header.ph_entry_size()) != ph_entry_size {
+        return Err("program header size mismatch");
+    }
+    if header.ph_offset() % ph_align != 0 {
+        return Err("program header table is not aligned");
+    }
+    let ph_end = header
+        .ph_offset()
+        .checked_add(u64::from(header.ph_entry_size()) * u64::from(header.ph_count()));
+    if ph_end.map_or(true, |end| end > len) {
+        return Err("program header table out of range");
+    }
+    let sh_end = header
+        .sh_offset()
+        .checked_add(u64::from(header.sh_entry_size()) * u64::from(header.sh_count()));
+    if sh_end.map_or(true, |end| end > len) {
+        return Err("section header table out of range");
+    }
+    header::sanity_check(elf_file)?;
+
+    for program_header in elf_file.program_iter() {
+        let end = program_header.offset().checked_add(program_header.file_size());
+        if end.map_or(true, |end| end > len) {
+            return Err("kernel segment lies outside of the file");
+        }
+        // the checks of `program::sanity_check`, which also rejects segments ending at the end of the file
+        if matches!(program_header.get_type()?, Type::ShLib) {
+            return Err("kernel has a PT_SHLIB segment");
+        }
+        let align = program_header.align();
+        if align > 1 && program_header.virtual_addr() % align != program_header.offset() % align {
+            return Err("kernel segment address and offset are not congruent modulo its alignment");
+        }
+
+        if program_header.file_size() > program_header.mem_size() {
+            return Err("kernel segment is larger in the file than in memory");
         }
     }
+
+    Ok(())
 }
 
 const COPIED: Flags = Flags::BIT_9;
 
+const OUT_OF_MEMORY: &str = "out of memory while loading the kernel";
+
 struct Loader<'a, M, F> {
     elf_file: ElfFile<'a>,
     inner: Inner<'a, M, F>,
@@ -84,12 +176,55 @@ struct Inner<'a, M, F> {
     frame_allocator: &'a mut F,
 }
 
-fn check_is_in_load(elf_file: &ElfFile, virt_offset: u64) -> Result<(), &'static str> {
+/// Checks that the load segments do not overlap, lie in canonical memory and have a valid alignment.
+fn check_load_segments(elf_file: &ElfFile) -> Result<(), &'static str> {
+    let segments = || {
+        elf_file
+            .program_iter()
+            .filter(|h| matches!(h.get_type(), Ok(Type::Load)) && h.mem_size() > 0)
+    };
+
+    for segment in segments() {
+        let end = segment
+            .virtual_addr()
+            .checked_add(segment.mem_size())
+            .ok_or("kernel segment end address overflows")?;
+        // both ends must be in the same canonical half
+        let canonical = VirtAddr::try_new(segment.virtual_addr()).is_ok() && VirtAddr::try_new(end - 1).is_ok();
+        if !canonical || (segment.virtual_addr() ^ (end - 1)) >> 47 != 0 {
+            return Err("kernel segment address is not canonical");
+        }
+    }
+
+    let misaligned = elf_file
+        .program_iter()
+        .any(|h| matches!(h.get_type(), Ok(Type::Load)) && h.align() > 1 && !h.align().is_power_of_two());
+    if misaligned {
+        return Err("kernel segment alignment is not a power of two");
+    }
+
+    for (i, segment) in segments().enumerate() {
+        let end = segment.virtual_addr() + segment.mem_size();
+        for other in segments().skip(i + 1) {
+            let overlaps = segment.virtual_addr() < other.virtual_addr() + other.mem_size()
+                && other.virtual_addr() < end;
+            if overlaps {
+                return Err("kernel segments overlap");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn check_is_in_load(elf_file: &ElfFile, virt_offset: u64, len: u64) -> Result<(), &'static str> {
     for program_header in elf_file.program_iter() {
         if let Type::Load = program_header.get_type()? {
             if program_header.virtual_addr() <= virt_offset {
                 let offset_in_segment = virt_offset - program_header.virtual_addr();
-                if offset_in_segment < program_header.mem_size() {
+                if offset_in_segment < program_header.mem_size()
+                    && len <= program_header.mem_size() - offset_in_segment
+                {
                     return Ok(());
                 }
             }
@@ -103,13 +238,21 @@ impl<'a, M, F> Inner<'a, M, F>
         M: MapperAllSizes + Translate,
         F: FrameAllocator<Size4KiB>,
 {
+    /// Virtual address that `address` of the ELF file is loaded at.
+    fn loaded_address(&self, address: u64) -> Result<VirtAddr, &'static str> {
+        self.virtual_address_offset
+            .checked_add(address)
+            .and_then(|address| VirtAddr::try_new(address).ok())
+            .ok_or("kernel address lies outside of the virtual address space")
+    }
+
     fn handle_load_segment(&mut self, segment: ProgramHeader) -> Result<(), &'static str> {
         let phys_start_addr = self.kernel_offset + segment.offset();
         let start_frame: PhysFrame = PhysFrame::containing_address(phys_start_addr);
         let end_frame: PhysFrame =
             PhysFrame::containing_address(phys_start_addr + segment.file_size() - 1u64);
 
-        let virt_start_addr = VirtAddr::new(self.virtual_address_offset + segment.virtual_addr());
+        let virt_start_addr = self.loaded_address(segment.virtual_addr())?;
         let start_page: Page = Page::containing_address(virt_start_addr);
 
         let mut segment_flags = Flags::PRESENT;
@@ -146,13 +289,13 @@ impl<'a, M, F> Inner<'a, M, F>
         segment: &ProgramHeader,
         segment_flags: Flags,
     ) -> Result<(), &'static str> {
-        let virt_start_addr = VirtAddr::new(self.virtual_address_offset + segment.virtual_addr());
+        let virt_start_addr = self.loaded_address(segment.virtual_addr())?;
         let mem_size = segment.mem_size();
         let file_size = segment.file_size();
 
         // calculate virtual memory region that must be zeroed
         let zero_start = virt_start_addr + file_size;
-        let zero_end = virt_start_addr + mem_size;
+        let zero_end_inclusive = virt_start_addr + (mem_size - 1);
 
         type PageArray = [u64; Size4KiB::SIZE as usize / 8];
         const ZERO_ARRAY: PageArray = [0; Size4KiB::SIZE as usize / 8];
@@ -160,7 +303,7 @@ impl<'a, M, F> Inner<'a, M, F>
         let data_bytes_before_zero = zero_start.as_u64() & 0xfff;
         if data_bytes_before_zero != 0 {
             let last_page = Page::containing_address(virt_start_addr + file_size - 1u64);
-            let new_frame = unsafe { self.make_mut(last_page) };
+            let new_frame = unsafe { self.make_mut(last_page)? };
             let new_bytes_ptr = new_frame.start_address().as_u64() as *mut u8;
 
             unsafe {
@@ -172,12 +315,12 @@ impl<'a, M, F> Inner<'a, M, F>
             }
         }
 
-        let start_page: Page =
-            Page::containing_address(VirtAddr::new(align_up(zero_start.as_u64(), Size4KiB::SIZE)));
-        let end_page = Page::containing_address(zero_end - 1u64);
+        // a partial first page was zeroed above
+        let start_page: Page = Page::containing_address(zero_start);
+        let end_page = Page::containing_address(zero_end_inclusive);
 
-        for page in Page::range_inclusive(start_page, end_page) {
-            let frame = self.frame_allocator.allocate_frame().unwrap();
+        for page in Page::range_inclusive(start_page, end_page).skip(usize::from(data_bytes_before_zero != 0)) {
+            let frame = self.frame_allocator.allocate_frame().ok_or(OUT_OF_MEMORY)?;
 
             let frame_ptr = frame.start_address().as_u64() as *mut PageArray;
             unsafe { frame_ptr.write(ZERO_ARRAY) };
@@ -194,9 +337,9 @@ impl<'a, M, F> Inner<'a, M, F>
         Ok(())
     }
 
-    fn copy_from(&self, addr: VirtAddr, buf: &mut [u8]) {
+    fn copy_from(&self, addr: VirtAddr, buf: &mut [u8]) -> Result<(), &'static str> {
         let end_inclusive_addr = Step::forward_checked(addr, buf.len() - 1)
-            .expect("end address outside of the virtual address space");
+            .ok_or("end address outside of the virtual address space")?;
         let start_page = Page::<Size4KiB>::containing_address(addr);
         let end_inclusive_page = Page::<Size4KiB>::containing_address(end_inclusive_addr);
 
@@ -204,7 +347,7 @@ impl<'a, M, F> Inner<'a, M, F>
             let phys_addr = self
                 .page_table
                 .translate_page(page)
-                .expect("address is not mapped to the kernel's memory space");
+                .map_err(|_| "address is not mapped to the kernel's memory space")?;
 
             let page_start = page.start_address();
             let page_end_inclusive = page.start_address() + 4095u64;
@@ -230,17 +373,19 @@ impl<'a, M, F> Inner<'a, M, F>
 
             dest.copy_from_slice(src);
         }
+
+        Ok(())
     }
 
-    unsafe fn copy_to(&mut self, addr: VirtAddr, buf: &[u8]) {
+    unsafe fn copy_to(&mut self, addr: VirtAddr, buf: &[u8]) -> Result<(), &'static str> {
         let end_inclusive_addr = Step::forward_checked(addr, buf.len() - 1)
-            .expect("the end address should be in the virtual address space");
+            .ok_or("end address outside of the virtual address space")?;
         let start_page = Page::<Size4KiB>::containing_address(addr);
         let end_inclusive_page = Page::<Size4KiB>::containing_address(end_inclusive_addr);
 
         for page in start_page..=end_inclusive_page {
             let phys_addr = unsafe {
-                self.make_mut(page)
+                self.make_mut(page)?
             };
 
             let page_start = page.start_address();
@@ -267,16 +412,18 @@ impl<'a, M, F> Inner<'a, M, F>
 
             dest.copy_from_slice(src);
         }
+
+        Ok(())
     }
 
-    unsafe fn make_mut(&mut self, page: Page) -> PhysFrame {
+    unsafe fn make_mut(&mut self, page: Page) -> Result<PhysFrame, &'static str> {
         let (frame, flags) = match self.page_table.translate(page.start_address()) {
             TranslateResult::Mapped {
                 frame,
                 offset: _,
                 flags,
             } => (frame, flags),
-            TranslateResult::NotMapped => panic!("{:?} is not mapped", page),
+            TranslateResult::NotMapped => return Err("address is not mapped to the kernel's memory space"),
             TranslateResult::InvalidFrameAddress(_) => unreachable!(),
         };
         let frame = if let MappedFrame::Size4KiB(frame) = frame {
@@ -286,10 +433,10 @@ impl<'a, M, F> Inner<'a, M, F>
         };
 
         if flags.contains(COPIED) {
-            return frame;
+            return Ok(frame);
         }
 
-        let new_frame = self.frame_allocator.allocate_frame().unwrap();
+        let new_frame = self.frame_allocator.allocate_frame().ok_or(OUT_OF_MEMORY)?;
         let frame_ptr = frame.start_address().as_u64() as *const u8;
         let new_frame_ptr = new_frame.start_address().as_u64() as *mut u8;
 
@@ -297,28 +444,34 @@ impl<'a, M, F> Inner<'a, M, F>
             core::ptr::copy_nonoverlapping(frame_ptr, new_frame_ptr, Size4KiB::SIZE as usize);
         }
 
-        self.page_table.unmap(page).unwrap().1.ignore();
+        self.page_table
+            .unmap(page)
+            .map_err(|_| "failed to unmap a kernel page")?
+            .1
+            .ignore();
         let new_flags = flags | COPIED;
 
         unsafe {
             self.page_table
                 .map_to(page, new_frame, new_flags, self.frame_allocator)
-                .unwrap()
+                .map_err(|_| "failed to map a copied kernel page")?
                 .ignore();
         }
 
-        new_frame
+        Ok(new_frame)
     }
 
     fn remove_copied_flags(&mut self, elf_file: &ElfFile) -> Result<(), &'static str> {
         for program_header in elf_file.program_iter() {
             if let Type::Load = program_header.get_type()? {
-                let start = self.virtual_address_offset + program_header.virtual_addr();
-                let end = start + program_header.mem_size();
-                let start = VirtAddr::new(start);
-                let end = VirtAddr::new(end);
+                if program_header.mem_size() == 0 {
+                    continue;
+                }
+
+                let start = self.loaded_address(program_header.virtual_addr())?;
+                let end = start + (program_header.mem_size() - 1);
                 let start_page = Page::containing_address(start);
-                let end_page = Page::containing_address(end - 1u64);
+                let end_page = Page::containing_address(end);
                 for page in Page::<Size4KiB>::range_inclusive(start_page, end_page) {
                     // Translate the page and get the flags.
                     let res = self.page_table.translate(page.start_address());
@@ -329,7 +482,7 @@ impl<'a, M, F> Inner<'a, M, F>
                             flags,
                         } => flags,
                         TranslateResult::NotMapped | TranslateResult::InvalidFrameAddress(_) => {
-                            unreachable!("has the elf file not been mapped correctly?")
+                            return Err("kernel segment is not mapped");
                         }
                     };
 
@@ -337,7 +490,7 @@ impl<'a, M, F> Inner<'a, M, F>
                         unsafe {
                             self.page_table
                                 .update_flags(page, flags & !COPIED)
-                                .unwrap()
+                                .map_err(|_| "failed to update the flags of a kernel page")?
                                 .ignore();
                         }
                     }
@@ -347,11 +500,24 @@ impl<'a, M, F> Inner<'a, M, F>
         Ok(())
     }
 
-    fn handle_tls_segment(&mut self, segment: ProgramHeader) -> Result<TlsTemplate, &'static str> {
+    fn handle_tls_segment(
+        &mut self,
+        segment: ProgramHeader,
+        elf_file: &ElfFile,
+    ) -> Result<TlsTemplate, &'static str> {
+        if segment.align() > 1 && !segment.align().is_power_of_two() {
+            return Err("TLS segment alignment is not a power of two");
+        }
+        if segment.mem_size() > 0 {
+            check_is_in_load(elf_file, segment.virtual_addr(), segment.mem_size())
+                .map_err(|_| "TLS segment is not in a load segment")?;
+        }
+
         Ok(TlsTemplate {
-            start_address: self.virtual_address_offset + segment.virtual_addr(),
+            start_address: self.loaded_address(segment.virtual_addr())?.as_u64(),
             mem_size: segment.mem_size(),
             file_size: segment.file_size(),
+            align: segment.align(),
         })
     }
 
@@ -360,11 +526,15 @@ impl<'a, M, F> Inner<'a, M, F>
         segment: ProgramHeader,
         elf_file: &ElfFile,
     ) -> Result<(), &'static str> {
+        // xmas-elf reads the entries in place
+        if segment.offset() % 8 != 0 || segment.file_size() % size_of::<dynamic::Dynamic<u64>>() as u64 != 0 {
+            return Err("dynamic segment is malformed");
+        }
         let data = segment.get_data(elf_file)?;
         let data = if let SegmentData::Dynamic64(data) = data {
             data
         } else {
-            panic!("expected Dynamic64 segment")
+            return Err("expected Dynamic64 segment");
         };
 
         // Find the `Rela`, `RelaSize` and `RelaEnt` entries.
@@ -410,32 +580,35 @@ impl<'a, M, F> Inner<'a, M, F>
         let total_size = rela_size.ok_or("RelaSize entry is missing")?;
         let entry_size = rela_ent.ok_or("RelaEnt entry is missing")?;
 
-        assert_eq!(
-            entry_size,
-            size_of::<Rela<u64>>() as u64,
-            "unsupported entry size: {entry_size}"
-        );
+        if entry_size != size_of::<Rela<u64>>() as u64 {
+            return Err("unsupported relocation entry size");
+        }
 
+        // the table must be loaded, which also bounds the number of entries
         let num_entries = total_size / entry_size;
+        if num_entries == 0 {
+            return Ok(());
+        }
+        check_is_in_load(elf_file, offset, num_entries * entry_size)
+            .map_err(|_| "relocation table is not in a load segment")?;
         for idx in 0..num_entries {
-            let rela = self.read_relocation(offset, idx);
+            let rela = self.read_relocation(offset, idx)?;
             self.apply_relocation(rela, elf_file)?;
         }
 
         Ok(())
     }
 
-    fn read_relocation(&self, relocation_table: u64, idx: u64) -> Rela<u64> {
+    fn read_relocation(&self, relocation_table: u64, idx: u64) -> Result<Rela<u64>, &'static str> {
         let offset = relocation_table + size_of::<Rela<u64>>() as u64 * idx;
-        let value = self.virtual_address_offset + offset;
-        let addr = VirtAddr::try_new(value).expect("relocation table is outside the address space");
+        let addr = self.loaded_address(offset)?;
 
         let mut buf = [0; 24];
-        self.copy_from(addr, &mut buf);
+        self.copy_from(addr, &mut buf)?;
 
-        unsafe {
+        Ok(unsafe {
             core::ptr::read_unaligned(&buf as *const u8 as *const Rela<u64>)
-        }
+        })
     }
 
     fn apply_relocation(
@@ -443,38 +616,42 @@ impl<'a, M, F> Inner<'a, M, F>
         rela: Rela<u64>,
         elf_file: &ElfFile,
     ) -> Result<(), &'static str> {
-        let symbol_idx = rela.get_symbol_table_index();
-        assert_eq!(
-            symbol_idx, 0,
-            "relocations using the symbol table are not supported"
-        );
+        if rela.get_symbol_table_index() != 0 {
+            return Err("relocations using the symbol table are not supported");
+        }
 
         match rela.get_type() {
             8 => {
-                check_is_in_load(elf_file, rela.get_offset())?;
+                check_is_in_load(elf_file, rela.get_offset(), size_of::<u64>() as u64)?;
 
-                let addr = self.virtual_address_offset + rela.get_offset();
-                let addr = VirtAddr::new(addr);
+                let addr = self.loaded_address(rela.get_offset())?;
 
-                let value = self.virtual_address_offset + rela.get_addend();
+                let value = self
+                    .virtual_address_offset
+                    .checked_add(rela.get_addend())
+                    .ok_or("relocated address overflows")?;
 
                 unsafe {
-                    self.copy_to(addr, &value.to_ne_bytes());
+                    self.copy_to(addr, &value.to_ne_bytes())?;
                 }
             }
-            ty => unimplemented!("relocation type {:x} not supported", ty),
+            _ => return Err("unsupported relocation type"),
         }
 
         Ok(())
     }
 
-    fn handle_relro_segment(&mut self, program_header: ProgramHeader) {
-        let start = self.virtual_address_offset + program_header.virtual_addr();
-        let end = start + program_header.mem_size();
-        let start = VirtAddr::new(start);
-        let end = VirtAddr::new(end);
+    fn handle_relro_segment(&mut self, program_header: ProgramHeader, elf_file: &ElfFile) -> Result<(), &'static str> {
+        if program_header.mem_size() == 0 {
+            return Ok(());
+        }
+        check_is_in_load(elf_file, program_header.virtual_addr(), program_header.mem_size())
+            .map_err(|_| "RELRO segment is not in a load segment")?;
+
+        let start = self.loaded_address(program_header.virtual_addr())?;
+        let end = start + (program_header.mem_size() - 1);
         let start_page = Page::containing_address(start);
-        let end_page = Page::containing_address(end - 1u64);
+        let end_page = Page::containing_address(end);
         for page in Page::<Size4KiB>::range_inclusive(start_page, end_page) {
             // Translate the page and get the flags.
             let res = self.page_table.translate(page.start_address());
@@ -485,7 +662,7 @@ impl<'a, M, F> Inner<'a, M, F>
                     flags,
                 } => flags,
                 TranslateResult::NotMapped | TranslateResult::InvalidFrameAddress(_) => {
-                    unreachable!("has the elf file not been mapped correctly?")
+                    return Err("kernel segment is not mapped");
                 }
             };
 
@@ -493,11 +670,13 @@ impl<'a, M, F> Inner<'a, M, F>
                 unsafe {
                     self.page_table
                         .update_flags(page, flags & !Flags::WRITABLE)
-                        .unwrap()
+                        .map_err(|_| "failed to update the flags of a kernel page")?
                         .ignore();
                 }
             }
         }
+
+        Ok(())
     }
 }
 
@@ -512,20 +691,10 @@ impl<'a, M, F> Loader<'a, M, F>
         frame_allocator: &'a mut F,
         used_entries: &mut Entries,
     ) -> Result<Self, &'static str> {
-        let kernel_offset = PhysAddr::new(&kernel.elf.input[0] as *const u8 as u64);
-        if !kernel_offset.is_aligned(PAGE_SIZE) {
-            return Err("Loaded kernel ELF file is not sufficiently aligned");
-        }
-
+        let kernel_offset = PhysAddr::new(kernel.elf.input.as_ptr() as u64);
         let elf_file = kernel.elf;
-        for program_header in elf_file.program_iter() {
-            program::sanity_check(program_header, &elf_file)?;
-        }
 
         let virtual_address_offset = match elf_file.header.pt2.type_().as_type() {
-            header::Type::None => unimplemented!(),
-            header::Type::Relocatable => unimplemented!(),
-            header::Type::Executable => VirtualAddressOffset::zero(),
             header::Type::SharedObject => {
                 let max_addr = elf_file
                     .program_iter()
@@ -540,21 +709,26 @@ impl<'a, M, F> Loader<'a, M, F>
                     .min()
                     .unwrap_or(0);
 
-                let size = max_addr - min_addr;
                 let align = elf_file
                     .program_iter()
-                    .filter(|h| matches!(h.get_type(), Ok(Type::Load))).map(|h| h.align()).max().unwrap_or(1);
+                    .filter(|h| matches!(h.get_type(), Ok(Type::Load))).map(|h| h.align()).max().unwrap_or(1)
+                    .max(PAGE_SIZE);
+
+                // keep the segments congruent to their alignment, so they stay page-aligned in memory
+                let min_addr = min_addr & !(align - 1);
+                let size = max_addr - min_addr;
 
-                let offset = used_entries.get_free_address(size, align).as_u64();
+                let offset = used_entries
+                    .try_get_free_address(size, align, VirtualRegionKind::Kernel)
+                    .ok_or("kernel does not fit in the virtual address space")?
+                    .as_u64();
                 VirtualAddressOffset::new(i128::from(offset) - i128::from(min_addr))
             }
-            header::Type::Core => unimplemented!(),
-            header::Type::ProcessorSpecific(_) => unimplemented!(),
+            _ => VirtualAddressOffset::zero(),
         };
 
-        used_entries.mark_segments(elf_file.program_iter(), virtual_address_offset);
+        used_entries.mark_segments(elf_file.program_iter(), virtual_address_offset)?;
 
-        header::sanity_check(&elf_file)?;
         let loader = Loader {
             elf_file,
             inner: Inner {
@@ -572,10 +746,11 @@ impl<'a, M, F> Loader<'a, M, F>
         let mut tls_template = None;
         for program_header in self.elf_file.program_iter() {
             match program_header.get_type()? {
+                Type::Load if program_header.mem_size() == 0 => {}
                 Type::Load => self.inner.handle_load_segment(program_header)?,
                 Type::Tls => {
                     if tls_template.is_none() {
-                        tls_template = Some(self.inner.handle_tls_segment(program_header)?);
+                        tls_template = Some(self.inner.handle_tls_segment(program_header, &self.elf_file)?);
                     } else {
                         return Err("multiple TLS segments not supported");
                     }
@@ -601,17 +776,17 @@ impl<'a, M, F> Loader<'a, M, F>
 
         for program_header in self.elf_file.program_iter() {
             if let Type::GnuRelro = program_header.get_type()? {
-                self.inner.handle_relro_segment(program_header);
+                self.inner.handle_relro_segment(program_header, &self.elf_file)?;
             }
         }
 
-        self.inner.remove_copied_flags(&self.elf_file).unwrap();
+        self.inner.remove_copied_flags(&self.elf_file)?;
 
         Ok(tls_template)
     }
 
-    fn entry_point(&self) -> VirtAddr {
-        VirtAddr::new(self.inner.virtual_address_offset + self.elf_file.header.pt2.entry_point())
+    fn entry_point(&self) -> Result<VirtAddr, &'static str> {
+        self.inner.loaded_address(self.elf_file.header.pt2.entry_point())
     }
 }
 
@@ -624,5 +799,5 @@ pub fn load_kernel(
     let mut loader = Loader::new(kernel, page_table, frame_allocator, used_entries)?;
     let tls_template = loader.load_segments()?;
 
-    Ok((loader.entry_point(), tls_template))
+    Ok((loader.entry_point()?, tls_template))
 }
@@ -0,0 +1,13 @@
+//! Target-independent parts of initium: the frame allocator over the firmware memory map, the
+//! virtual address space allocator, the ELF loader and the splash image decoder.
+//!
+//! The loader and the frame allocator access physical memory through identity mapped pointers,
+//! so tests hand out frames from host memory.
+
+#![cfg_attr(not(test), no_std)]
+#![feature(step_trait)]
+
+pub mod entries;
+pub mod image;
+pub mod kernel;
+pub mod memory;
@@ -17,6 +17,9 @@ pub trait LegacyMemoryRegion: Copy {
     fn kind(&self) -> MemoryRegionKind;
 
     fn usable_after_bootloader_exit(&self) -> bool;
+
+    /// Whether the firmware needs the region for its runtime services.
+    fn is_runtime(&self) -> bool;
 }
 
 pub struct LegacyFrameAllocator<I, D> {
@@ -87,6 +90,20 @@ impl<I, D> LegacyFrameAllocator<I, D>
             .unwrap()
     }
 
+    pub fn runtime_regions(&self) -> impl Iterator<Item=D> {
+        self.original.clone().filter(|r| r.is_runtime())
+    }
+
+    /// End of the highest region that is usable after the bootloader exits.
+    pub fn max_usable_address(&self) -> PhysAddr {
+        self.original
+            .clone()
+            .filter(|r| r.usable_after_bootloader_exit())
+            .map(|r| r.start() + r.len())
+            .max()
+            .unwrap()
+    }
+
     fn add_region(
         region: MemoryRegion,
         regions: &mut [MaybeUninit<MemoryRegion>],
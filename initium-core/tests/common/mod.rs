@@ -0,0 +1,152 @@
+#![allow(dead_code)]
+
+use std::alloc::{alloc_zeroed, dealloc, Layout};
+
+use x86_64::{
+    structures::paging::{FrameAllocator, OffsetPageTable, PageTable, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+
+pub const PAGE_SIZE: u64 = 4096;
+
+pub fn align_up(value: u64) -> u64 {
+    (value + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+/// Host memory standing in for physical memory, frame addresses are host addresses so the
+/// loader can access them like identity mapped memory.
+pub struct Arena {
+    memory: *mut u8,
+    pages: usize,
+    next: usize,
+}
+
+impl Arena {
+    pub fn new(pages: usize) -> Self {
+        let memory = unsafe { alloc_zeroed(Self::layout(pages)) };
+        assert!(!memory.is_null());
+        Self {
+            memory,
+            pages,
+            next: 0,
+        }
+    }
+
+    fn layout(pages: usize) -> Layout {
+        Layout::from_size_align(pages * PAGE_SIZE as usize, PAGE_SIZE as usize).unwrap()
+    }
+
+    /// Copies `data` to the next free pages, page aligned.
+    pub fn store(&mut self, data: &[u8]) -> &'static [u8] {
+        let pages = (data.len() + PAGE_SIZE as usize - 1) / PAGE_SIZE as usize;
+        assert!(self.next + pages <= self.pages, "arena is full");
+
+        let start = unsafe { self.memory.add(self.next * PAGE_SIZE as usize) };
+        self.next += pages;
+        unsafe {
+            start.copy_from_nonoverlapping(data.as_ptr(), data.len());
+            std::slice::from_raw_parts(start, data.len())
+        }
+    }
+
+    pub fn contains(&self, address: u64) -> bool {
+        let start = self.memory as u64;
+        (start..start + self.pages as u64 * PAGE_SIZE).contains(&address)
+    }
+
+    /// Page table with an offset of 0, which maps physical frames to their host address.
+    pub fn page_table(&mut self) -> OffsetPageTable<'static> {
+        let frame = self.allocate_frame().unwrap();
+        let table = frame.start_address().as_u64() as *mut PageTable;
+        unsafe {
+            table.write(PageTable::new());
+            OffsetPageTable::new(&mut *table, VirtAddr::new(0))
+        }
+    }
+}
+
+unsafe impl FrameAllocator<Size4KiB> for Arena {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if self.next == self.pages {
+            return None;
+        }
+
+        let address = self.memory as u64 + self.next as u64 * PAGE_SIZE;
+        self.next += 1;
+        Some(PhysFrame::from_start_address(PhysAddr::new(address)).unwrap())
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.memory, Self::layout(self.pages)) };
+    }
+}
+
+pub const PF_X: u32 = 1;
+pub const PF_W: u32 = 2;
+pub const PF_R: u32 = 4;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+
+pub const ET_EXEC: u16 = 2;
+pub const ET_DYN: u16 = 3;
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub ty: u32,
+    pub vaddr: u64,
+    pub data: Vec<u8>,
+    pub mem_size: u64,
+    pub flags: u32,
+}
+
+impl Segment {
+    pub fn load(vaddr: u64, data: Vec<u8>, mem_size: u64, flags: u32) -> Self {
+        Self {
+            ty: PT_LOAD,
+            vaddr,
+            data,
+            mem_size,
+            flags,
+        }
+    }
+}
+
+/// Builds a 64-bit ELF file, the data of every segment starts on its own page at an offset
+/// congruent to its virtual address.
+pub fn build_elf(ty: u16, entry: u64, segments: &[Segment]) -> Vec<u8> {
+    const HEADER_SIZE: usize = 64;
+    const PH_SIZE: usize = 56;
+
+    let mut elf = vec![0; HEADER_SIZE + PH_SIZE * segments.len()];
+    elf[..16].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    elf[16..18].copy_from_slice(&ty.to_le_bytes());
+    elf[18..20].copy_from_slice(&0x3eu16.to_le_bytes());
+    elf[20..24].copy_from_slice(&1u32.to_le_bytes());
+    elf[24..32].copy_from_slice(&entry.to_le_bytes());
+    elf[32..40].copy_from_slice(&(HEADER_SIZE as u64).to_le_bytes());
+    elf[52..54].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+    elf[54..56].copy_from_slice(&(PH_SIZE as u16).to_le_bytes());
+    elf[56..58].copy_from_slice(&(segments.len() as u16).to_le_bytes());
+    elf[58..60].copy_from_slice(&64u16.to_le_bytes());
+
+    for (i, segment) in segments.iter().enumerate() {
+        let offset = align_up(elf.len() as u64) + segment.vaddr % PAGE_SIZE;
+        elf.resize(offset as usize, 0);
+        elf.extend_from_slice(&segment.data);
+
+        let header = &mut elf[HEADER_SIZE + PH_SIZE * i..][..PH_SIZE];
+        header[0..4].copy_from_slice(&segment.ty.to_le_bytes());
+        header[4..8].copy_from_slice(&segment.flags.to_le_bytes());
+        header[8..16].copy_from_slice(&offset.to_le_bytes());
+        header[16..24].copy_from_slice(&segment.vaddr.to_le_bytes());
+        header[24..32].copy_from_slice(&segment.vaddr.to_le_bytes());
+        header[32..40].copy_from_slice(&(segment.data.len() as u64).to_le_bytes());
+        header[40..48].copy_from_slice(&segment.mem_size.to_le_bytes());
+        header[48..56].copy_from_slice(&PAGE_SIZE.to_le_bytes());
+    }
+
+    elf
+}
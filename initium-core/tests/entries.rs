@@ -0,0 +1,85 @@
+use initium_core::entries::Entries;
+use proptest::prelude::*;
+use synapse::memory::VirtualRegionKind;
+
+const PAGE_SIZE: u64 = 4096;
+const LEVEL_4_SIZE: u64 = 512 << 30;
+
+fn kind() -> impl Strategy<Value = VirtualRegionKind> {
+    prop_oneof![
+        Just(VirtualRegionKind::KernelStack),
+        Just(VirtualRegionKind::ApStack),
+        Just(VirtualRegionKind::TlsBlock),
+        Just(VirtualRegionKind::Framebuffer),
+        Just(VirtualRegionKind::Ramdisk),
+    ]
+}
+
+#[test]
+fn reserves_identity_range() {
+    let entries = Entries::default();
+
+    assert_eq!(entries.len(), 1);
+    assert!(!entries.is_empty());
+    assert_eq!(entries.regions()[0].start, 0);
+    assert_eq!(entries.regions()[0].end, LEVEL_4_SIZE);
+    assert_eq!(entries.regions()[0].kind, VirtualRegionKind::Identity);
+}
+
+#[test]
+fn keeps_guard_gap() {
+    let mut entries = Entries::new();
+    let first = entries.get_free_address(1, 1, VirtualRegionKind::KernelStack);
+    let second = entries.get_free_address(PAGE_SIZE, 1, VirtualRegionKind::KernelStack);
+
+    assert_eq!(first.as_u64(), LEVEL_4_SIZE + PAGE_SIZE);
+    assert_eq!(second.as_u64(), first.as_u64() + 2 * PAGE_SIZE);
+}
+
+#[test]
+fn falls_back_to_higher_half() {
+    let mut entries = Entries::new();
+    let address = entries.get_free_address(0x0000_7000_0000_0000, 1, VirtualRegionKind::Ramdisk);
+    assert_eq!(address.as_u64(), LEVEL_4_SIZE + PAGE_SIZE);
+
+    let address = entries.get_free_address(0x0000_1000_0000_0000, 1, VirtualRegionKind::Ramdisk);
+    assert_eq!(address.as_u64(), 0xffff_8000_0000_0000);
+}
+
+proptest! {
+    #[test]
+    fn allocations_are_disjoint(
+        allocations in prop::collection::vec((1..1u64 << 32, 0..32u32, kind()), 1..64),
+    ) {
+        let mut entries = Entries::new();
+        let mut allocated = Vec::new();
+
+        for (size, alignment, kind) in allocations {
+            let alignment = 1 << alignment;
+            let start = entries.get_free_address(size, alignment, kind).as_u64();
+            let end = start + size;
+
+            prop_assert_eq!(start % alignment.max(PAGE_SIZE), 0);
+            prop_assert!(start >= LEVEL_4_SIZE);
+            for &(other_start, other_end) in &allocated {
+                prop_assert!(end + PAGE_SIZE <= other_start || other_end + PAGE_SIZE <= start);
+            }
+            allocated.push((start, (end + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)));
+        }
+
+        let regions = entries.regions();
+        for pair in regions.windows(2) {
+            prop_assert!(pair[0].end <= pair[1].start);
+        }
+        for region in regions {
+            prop_assert!(region.start < region.end);
+            prop_assert_eq!(region.start % PAGE_SIZE, 0);
+            prop_assert_eq!(region.end % PAGE_SIZE, 0);
+        }
+        for &(start, end) in &allocated {
+            prop_assert!(regions
+                .iter()
+                .any(|region| region.start <= start && end <= region.end));
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use initium_core::image::Image;
+
+fn qoi(width: u32, height: u32, chunks: &[u8]) -> Vec<u8> {
+    let mut data = b"qoif".to_vec();
+    data.extend_from_slice(&width.to_be_bytes());
+    data.extend_from_slice(&height.to_be_bytes());
+    // channels and colorspace
+    data.extend_from_slice(&[4, 0]);
+    data.extend_from_slice(chunks);
+    data
+}
+
+fn pixels(image: &Image) -> Vec<(usize, usize, [u8; 3])> {
+    let mut pixels = Vec::new();
+    image.draw(|x, y, color| pixels.push((x, y, color)));
+    pixels
+}
+
+#[test]
+fn rejects_truncated_qoi_header() {
+    let data = qoi(1, 1, &[]);
+    for len in 4..data.len() {
+        assert_eq!(Image::parse(&data[..len]).err(), Some("QOI header is truncated"), "{len} bytes");
+    }
+}
+
+#[test]
+fn decodes_qoi() {
+    // an RGB pixel, then a run of one
+    let data = qoi(2, 1, &[0xfe, 10, 20, 30, 0xc0]);
+    let image = Image::parse(&data).unwrap();
+
+    assert_eq!(image.size(), (2, 1));
+    assert_eq!(pixels(&image), [(0, 0, [10, 20, 30]), (1, 0, [10, 20, 30])]);
+}
+
+#[test]
+fn rejects_truncated_bmp() {
+    let mut data = vec![0; 54];
+    data[..2].copy_from_slice(b"BM");
+    data[10..14].copy_from_slice(&54u32.to_le_bytes());
+    data[18..22].copy_from_slice(&2u32.to_le_bytes());
+    data[22..26].copy_from_slice(&2u32.to_le_bytes());
+    data[28..30].copy_from_slice(&24u16.to_le_bytes());
+
+    assert_eq!(Image::parse(&data[..20]).err(), Some("BMP header is truncated"));
+    assert_eq!(Image::parse(&data).err(), Some("BMP pixel data is truncated"));
+
+    // two rows of two pixels, padded to four bytes
+    data.resize(54 + 16, 0xff);
+    let image = Image::parse(&data).unwrap();
+    assert_eq!(image.size(), (2, 2));
+    assert_eq!(pixels(&image).len(), 4);
+}
@@ -0,0 +1,257 @@
+mod common;
+
+use common::*;
+use initium_core::{
+    entries::Entries,
+    kernel::{load_kernel, Kernel},
+};
+use proptest::prelude::*;
+use synapse::memory::VirtualRegionKind;
+use x86_64::{
+    structures::paging::{mapper::TranslateResult, OffsetPageTable, PageTableFlags, Translate},
+    VirtAddr,
+};
+
+const HIGHER_HALF: u64 = 0xffff_ffff_8000_0000;
+const LEVEL_4_SIZE: u64 = 512 << 30;
+
+/// Layout of a load segment: pages skipped before it, offset in its first page, file size,
+/// bss size and flags.
+type SegmentLayout = (u64, u64, usize, u64, u32);
+
+fn segment_layouts() -> impl Strategy<Value = Vec<SegmentLayout>> {
+    let offset = prop_oneof![Just(0), 0..PAGE_SIZE];
+    let segment = (0..3u64, offset, 0..3 * PAGE_SIZE as usize, 0..3 * PAGE_SIZE, 0..8u32);
+    prop::collection::vec(segment, 1..5)
+}
+
+/// Places the segments at `base`, each on its own pages, with deterministic contents.
+fn segments(base: u64, layouts: &[SegmentLayout], seed: u8) -> Vec<Segment> {
+    let mut page = base;
+    layouts
+        .iter()
+        .enumerate()
+        .map(|(i, &(gap, offset, file_size, bss_size, flags))| {
+            let vaddr = page + gap * PAGE_SIZE + offset;
+            let data = (0..file_size)
+                .map(|j| (j as u8).wrapping_mul(31).wrapping_add(seed).wrapping_add(i as u8) | 1)
+                .collect::<Vec<_>>();
+            let mem_size = (file_size as u64 + bss_size).max(1);
+            page = align_up(vaddr + mem_size);
+            Segment::load(vaddr, data, mem_size, flags)
+        })
+        .collect()
+}
+
+fn load(
+    arena: &mut Arena,
+    elf: &[u8],
+    entries: &mut Entries,
+) -> (Result<VirtAddr, &'static str>, OffsetPageTable<'static>) {
+    let kernel = arena.store(elf);
+    let mut page_table = arena.page_table();
+    let result = Kernel::parse(kernel)
+        .and_then(|kernel| load_kernel(kernel, &mut page_table, arena, entries))
+        .map(|(entry_point, _)| entry_point);
+    (result, page_table)
+}
+
+fn read(page_table: &OffsetPageTable, address: u64) -> Option<u8> {
+    let physical = page_table.translate_addr(VirtAddr::new(address))?;
+    Some(unsafe { *(physical.as_u64() as *const u8) })
+}
+
+fn flags(page_table: &OffsetPageTable, address: u64) -> Option<PageTableFlags> {
+    match page_table.translate(VirtAddr::new(address)) {
+        TranslateResult::Mapped { flags, .. } => Some(flags),
+        _ => None,
+    }
+}
+
+/// Checks the contents and flags of the loaded segments, which were linked at `offset` less
+/// than where they were loaded.
+fn check_segments(
+    page_table: &OffsetPageTable,
+    segments: &[Segment],
+    offset: u64,
+) -> Result<(), TestCaseError> {
+    for segment in segments {
+        let start = segment.vaddr + offset;
+        for i in 0..segment.mem_size {
+            let expected = segment.data.get(i as usize).copied().unwrap_or(0);
+            prop_assert_eq!(read(page_table, start + i), Some(expected), "byte {:#x}", start + i);
+        }
+
+        let mut page = start & !(PAGE_SIZE - 1);
+        while page < start + segment.mem_size {
+            let flags = flags(page_table, page).unwrap();
+            prop_assert!(flags.contains(PageTableFlags::PRESENT));
+            prop_assert_eq!(flags.contains(PageTableFlags::NO_EXECUTE), segment.flags & PF_X == 0);
+            prop_assert_eq!(flags.contains(PageTableFlags::WRITABLE), segment.flags & PF_W != 0);
+            prop_assert!(!flags.contains(PageTableFlags::BIT_9), "copied flag left on {:#x}", page);
+            page += PAGE_SIZE;
+        }
+    }
+
+    // nothing is mapped between the segments
+    for pair in segments.windows(2) {
+        let mut page = align_up(pair[0].vaddr + pair[0].mem_size);
+        while page < pair[1].vaddr & !(PAGE_SIZE - 1) {
+            prop_assert!(flags(page_table, page + offset).is_none(), "stray mapping at {:#x}", page);
+            page += PAGE_SIZE;
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn applies_relative_relocations() {
+    let mut data = vec![0; 64];
+    // R_X86_64_RELATIVE for the pointer at 0x0
+    data[8..16].copy_from_slice(&0u64.to_le_bytes());
+    data[16..24].copy_from_slice(&8u64.to_le_bytes());
+    data[24..32].copy_from_slice(&0x1234u64.to_le_bytes());
+
+    let mut dynamic = Vec::new();
+    for (tag, value) in [(7u64, 8u64), (8, 24), (9, 24), (0, 0)] {
+        dynamic.extend_from_slice(&tag.to_le_bytes());
+        dynamic.extend_from_slice(&value.to_le_bytes());
+    }
+    let dynamic = Segment {
+        ty: PT_DYNAMIC,
+        vaddr: 0x800,
+        mem_size: dynamic.len() as u64,
+        data: dynamic,
+        flags: PF_R | PF_W,
+    };
+
+    let segments = [Segment::load(0, data, PAGE_SIZE, PF_R | PF_W), dynamic];
+    let elf = build_elf(ET_DYN, 0x10, &segments);
+
+    let mut arena = Arena::new(64);
+    let mut entries = Entries::new();
+    let (result, page_table) = load(&mut arena, &elf, &mut entries);
+    let offset = result.unwrap().as_u64() - 0x10;
+
+    let pointer: Vec<_> = (0..8).map(|i| read(&page_table, offset + i).unwrap()).collect();
+    assert_eq!(u64::from_le_bytes(pointer.try_into().unwrap()), offset + 0x1234);
+}
+
+#[test]
+fn keeps_position_independent_segments_page_aligned() {
+    let segments = [Segment::load(0x123, vec![7; 16], 0x10, PF_R | PF_X)];
+    let elf = build_elf(ET_DYN, 0x123, &segments);
+
+    let mut arena = Arena::new(32);
+    let (result, page_table) = load(&mut arena, &elf, &mut Entries::new());
+    let offset = result.unwrap().as_u64() - 0x123;
+
+    assert_eq!(offset % PAGE_SIZE, 0);
+    assert_eq!(read(&page_table, 0x123 + offset), Some(7));
+}
+
+#[test]
+fn rejects_overlapping_segments() {
+    let segments = [
+        Segment::load(HIGHER_HALF, vec![1; 16], 0x2000, PF_R),
+        Segment::load(HIGHER_HALF + 0x1000, vec![2; 16], 0x10, PF_R),
+    ];
+    let elf = build_elf(ET_EXEC, HIGHER_HALF, &segments);
+
+    let mut arena = Arena::new(32);
+    let (result, _) = load(&mut arena, &elf, &mut Entries::new());
+    assert_eq!(result, Err("kernel segments overlap"));
+}
+
+#[test]
+fn rejects_kernel_in_identity_range() {
+    let segments = [Segment::load(0x20_0000, vec![1; 16], 0x10, PF_R | PF_X)];
+    let elf = build_elf(ET_EXEC, 0x20_0000, &segments);
+
+    let mut arena = Arena::new(32);
+    let (result, _) = load(&mut arena, &elf, &mut Entries::new());
+    assert!(result.unwrap_err().contains("first 512 GiB"));
+}
+
+#[test]
+fn rejects_unaligned_kernel() {
+    let segments = [Segment::load(HIGHER_HALF, vec![1; 16], 0x10, PF_R | PF_X)];
+    let mut elf = vec![0; 8];
+    elf.extend(build_elf(ET_EXEC, HIGHER_HALF, &segments));
+
+    let mut arena = Arena::new(32);
+    let kernel = &arena.store(&elf)[8..];
+    assert_eq!(Kernel::parse(kernel).err(), Some("Loaded kernel ELF file is not sufficiently aligned"));
+}
+
+#[test]
+fn loads_segment_ending_at_end_of_file() {
+    let segments = [Segment::load(HIGHER_HALF, vec![1; 16], 0x10, PF_R | PF_X)];
+    let elf = build_elf(ET_EXEC, HIGHER_HALF, &segments);
+
+    let mut arena = Arena::new(32);
+    let (result, page_table) = load(&mut arena, &elf, &mut Entries::new());
+    assert_eq!(result, Ok(VirtAddr::new(HIGHER_HALF)));
+    assert_eq!(read(&page_table, HIGHER_HALF + 15), Some(1));
+
+    let truncated = &elf[..elf.len() - 1];
+    let (result, _) = load(&mut arena, truncated, &mut Entries::new());
+    assert_eq!(result, Err("kernel segment lies outside of the file"));
+}
+
+proptest! {
+    #[test]
+    fn loads_executable(layouts in segment_layouts(), seed in any::<u8>()) {
+        let segments = segments(HIGHER_HALF, &layouts, seed);
+        let entry = segments[0].vaddr;
+        let elf = build_elf(ET_EXEC, entry, &segments);
+
+        let mut arena = Arena::new(256);
+        let mut entries = Entries::new();
+        let (result, page_table) = load(&mut arena, &elf, &mut entries);
+
+        prop_assert_eq!(result, Ok(VirtAddr::new(entry)));
+        check_segments(&page_table, &segments, 0)?;
+        for segment in &segments {
+            prop_assert!(entries.regions().iter().any(|region| region.kind == VirtualRegionKind::Kernel
+                && region.start <= segment.vaddr
+                && segment.vaddr + segment.mem_size <= region.end));
+        }
+    }
+
+    #[test]
+    fn loads_position_independent_executable(layouts in segment_layouts(), seed in any::<u8>()) {
+        let segments = segments(0, &layouts, seed);
+        let entry = segments[0].vaddr;
+        let elf = build_elf(ET_DYN, entry, &segments);
+
+        let mut arena = Arena::new(256);
+        let mut entries = Entries::new();
+        let (result, page_table) = load(&mut arena, &elf, &mut entries);
+
+        let offset = result.unwrap().as_u64() - entry;
+        prop_assert_eq!(offset % PAGE_SIZE, 0);
+        prop_assert!(segments[0].vaddr + offset >= LEVEL_4_SIZE);
+        check_segments(&page_table, &segments, offset)?;
+    }
+
+    #[test]
+    fn corrupted_kernel_is_an_error(
+        layouts in segment_layouts(),
+        dynamic in any::<bool>(),
+        corruptions in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 1..16),
+    ) {
+        let ty = if dynamic { ET_DYN } else { ET_EXEC };
+        let segments = segments(if dynamic { 0 } else { HIGHER_HALF }, &layouts, 0);
+        let mut elf = build_elf(ty, segments[0].vaddr, &segments);
+        // mostly corrupt the headers, where the interesting values are
+        for (index, byte) in corruptions {
+            let len = if byte % 4 == 0 { elf.len() } else { elf.len().min(64 + 56 * segments.len()) };
+            elf[index.index(len)] = byte;
+        }
+
+        let mut arena = Arena::new(256);
+        let _ = load(&mut arena, &elf, &mut Entries::new());
+    }
+}
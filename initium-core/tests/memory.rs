@@ -0,0 +1,275 @@
+use std::mem::MaybeUninit;
+
+use initium_core::memory::{LegacyFrameAllocator, LegacyMemoryRegion};
+use proptest::prelude::*;
+use synapse::memory::{MemoryRegion, MemoryRegionKind};
+use x86_64::{structures::paging::FrameAllocator, PhysAddr};
+
+const PAGE_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    start: u64,
+    len: u64,
+    kind: MemoryRegionKind,
+    usable_after_bootloader_exit: bool,
+}
+
+impl LegacyMemoryRegion for Region {
+    fn start(&self) -> PhysAddr {
+        PhysAddr::new(self.start)
+    }
+
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    fn kind(&self) -> MemoryRegionKind {
+        self.kind
+    }
+
+    fn usable_after_bootloader_exit(&self) -> bool {
+        self.usable_after_bootloader_exit
+    }
+
+    fn is_runtime(&self) -> bool {
+        false
+    }
+}
+
+fn usable(start: u64, len: u64) -> Region {
+    Region {
+        start,
+        len,
+        kind: MemoryRegionKind::Usable,
+        usable_after_bootloader_exit: true,
+    }
+}
+
+/// Loader data, which is reclaimed after the bootloader exits and holds the kernel file.
+fn loader_data(start: u64, len: u64) -> Region {
+    Region {
+        start,
+        len,
+        kind: MemoryRegionKind::UnknownUefi(2),
+        usable_after_bootloader_exit: true,
+    }
+}
+
+/// Sorted, non-overlapping regions starting at the first page.
+fn memory_map() -> impl Strategy<Value = Vec<Region>> {
+    let region = (
+        0..4u64,
+        1..64u64,
+        prop_oneof![
+            3 => Just((MemoryRegionKind::Usable, true)),
+            1 => Just((MemoryRegionKind::UnknownUefi(2), true)),
+            1 => Just((MemoryRegionKind::UnknownUefi(5), false)),
+            1 => Just((MemoryRegionKind::Bad, false)),
+        ],
+    );
+
+    prop::collection::vec(region, 1..16).prop_map(|regions| {
+        let mut start = 0;
+        regions
+            .into_iter()
+            .map(|(gap, pages, (kind, usable_after_bootloader_exit))| {
+                start += gap * PAGE_SIZE;
+                let region = Region {
+                    start,
+                    len: pages * PAGE_SIZE,
+                    kind,
+                    usable_after_bootloader_exit,
+                };
+                start += region.len;
+                region
+            })
+            .collect()
+    })
+}
+
+fn construct(
+    regions: &[Region],
+    frames: usize,
+    kernel: (u64, u64),
+) -> (Vec<PhysAddr>, Vec<MemoryRegion>) {
+    let mut allocator = LegacyFrameAllocator::new(regions.iter().copied());
+    let allocated = (0..frames)
+        .map_while(|_| allocator.allocate_frame())
+        .map(|frame| frame.start_address())
+        .collect();
+
+    let mut buf = vec![MaybeUninit::uninit(); regions.len() * 3 + 1];
+    let map = allocator.construct_memory_map(&mut buf, kernel.0, kernel.1).to_vec();
+    (allocated, map)
+}
+
+fn overlaps(region: &MemoryRegion, start: u64, end: u64) -> bool {
+    region.start < end && start < region.end
+}
+
+#[test]
+fn skips_frame_zero() {
+    let regions = [usable(0, 4 * PAGE_SIZE)];
+    let (allocated, map) = construct(&regions, 2, (0, 0));
+
+    assert_eq!(allocated, [PhysAddr::new(0x1000), PhysAddr::new(0x2000)]);
+    assert_eq!(
+        map,
+        [
+            MemoryRegion {
+                start: 0,
+                end: 0x3000,
+                kind: MemoryRegionKind::Bootloader,
+            },
+            MemoryRegion {
+                start: 0x3000,
+                end: 0x4000,
+                kind: MemoryRegionKind::Usable,
+            },
+        ]
+    );
+}
+
+#[test]
+fn splits_region_around_kernel() {
+    let regions = [usable(0x1000, 0x1000), loader_data(0x2000, 0x8000)];
+    let (_, map) = construct(&regions, 0, (0x4000, 0x2000));
+
+    assert_eq!(
+        map,
+        [
+            MemoryRegion {
+                start: 0x1000,
+                end: 0x2000,
+                kind: MemoryRegionKind::Usable,
+            },
+            MemoryRegion {
+                start: 0x2000,
+                end: 0x4000,
+                kind: MemoryRegionKind::Usable,
+            },
+            MemoryRegion {
+                start: 0x4000,
+                end: 0x6000,
+                kind: MemoryRegionKind::Bootloader,
+            },
+            MemoryRegion {
+                start: 0x6000,
+                end: 0xa000,
+                kind: MemoryRegionKind::Usable,
+            },
+        ]
+    );
+}
+
+#[test]
+#[should_panic(expected = "region ends before kernel")]
+fn rejects_kernel_spanning_regions() {
+    let regions = [loader_data(0x1000, 0x2000), loader_data(0x3000, 0x2000)];
+    construct(&regions, 0, (0x2000, 0x2000));
+}
+
+#[test]
+fn allocator_never_returns_unusable_frames() {
+    let regions = [
+        usable(0x1000, 0x1000),
+        loader_data(0x2000, 0x1000),
+        usable(0x4000, 0x1000),
+    ];
+    let mut allocator = LegacyFrameAllocator::new(regions.iter().copied());
+
+    let frames: Vec<_> = std::iter::from_fn(|| allocator.allocate_frame())
+        .map(|frame| frame.start_address().as_u64())
+        .collect();
+    assert_eq!(frames, [0x1000, 0x4000]);
+}
+
+proptest! {
+    #[test]
+    fn memory_map_is_consistent(
+        regions in memory_map(),
+        frames in 0..256usize,
+        kernel in (any::<prop::sample::Index>(), 0..16u64, 0..16u64),
+    ) {
+        // the kernel file lies in loader data, or nowhere if there is none
+        let (index, offset, pages) = kernel;
+        let loader_data: Vec<_> = regions
+            .iter()
+            .filter(|region| region.kind == MemoryRegionKind::UnknownUefi(2))
+            .collect();
+        let kernel = (!loader_data.is_empty())
+            .then(|| loader_data[index.index(loader_data.len())])
+            .map(|region| {
+                let len = region.len / PAGE_SIZE;
+                let offset = offset % len;
+                let pages = pages % (len - offset + 1);
+                (region.start + offset * PAGE_SIZE, pages * PAGE_SIZE)
+            })
+            .unwrap_or((0, 0));
+        let (kernel_start, kernel_len) = kernel;
+        let kernel_end = kernel_start + kernel_len;
+
+        let (allocated, map) = construct(&regions, frames, kernel);
+
+        // frames are unique and come from usable memory only
+        for pair in allocated.windows(2) {
+            prop_assert!(pair[0] < pair[1]);
+        }
+        for &frame in &allocated {
+            prop_assert!(frame.as_u64() != 0 && frame.is_aligned(PAGE_SIZE));
+            prop_assert!(regions.iter().any(|region| region.kind == MemoryRegionKind::Usable
+                && region.start <= frame.as_u64()
+                && frame.as_u64() + PAGE_SIZE <= region.start + region.len));
+        }
+
+        // the output is sorted, has no empty regions and covers exactly the input regions
+        for pair in map.windows(2) {
+            prop_assert!(pair[0].end <= pair[1].start);
+        }
+        prop_assert!(map.iter().all(|region| region.start < region.end));
+        let input_len: u64 = regions.iter().map(|region| region.len).sum();
+        let output_len: u64 = map.iter().map(|region| region.end - region.start).sum();
+        prop_assert_eq!(input_len, output_len);
+        for region in &map {
+            prop_assert!(regions
+                .iter()
+                .any(|input| input.start <= region.start && region.end <= input.start + input.len));
+        }
+
+        // allocated frames and the kernel are never handed out as usable memory
+        for region in map.iter().filter(|region| region.kind == MemoryRegionKind::Usable) {
+            for &frame in &allocated {
+                prop_assert!(!overlaps(region, frame.as_u64(), frame.as_u64() + PAGE_SIZE));
+            }
+            prop_assert!(!overlaps(region, kernel_start, kernel_end));
+        }
+        for &frame in &allocated {
+            prop_assert!(map.iter().any(|region| region.kind == MemoryRegionKind::Bootloader
+                && region.start <= frame.as_u64()
+                && frame.as_u64() + PAGE_SIZE <= region.end));
+        }
+        if kernel_len > 0 {
+            let kernel = MemoryRegion {
+                start: kernel_start,
+                end: kernel_end,
+                kind: MemoryRegionKind::Bootloader,
+            };
+            prop_assert!(map.contains(&kernel));
+        }
+
+        // regions the kernel may reclaim are reported as usable
+        for input in regions.iter().filter(|region| region.kind != MemoryRegionKind::Usable) {
+            let kind = if input.usable_after_bootloader_exit {
+                MemoryRegionKind::Usable
+            } else {
+                input.kind
+            };
+            let end = input.start + input.len;
+            prop_assert!(map
+                .iter()
+                .filter(|region| region.start >= input.start && region.end <= end)
+                .all(|region| region.kind == kind || region.kind == MemoryRegionKind::Bootloader));
+        }
+    }
+}
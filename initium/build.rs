@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+// The public key the boot files must be signed with, see `verify.rs`.
+fn main() {
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").unwrap());
+    let public_key_file = out_dir.join("public_key");
+
+    println!("cargo:rerun-if-env-changed=LIFE_PUBLIC_KEY");
+
+    let public_key = match std::env::var_os("LIFE_PUBLIC_KEY") {
+        Some(path) => {
+            println!("cargo:rerun-if-changed={}", PathBuf::from(&path).display());
+            std::fs::read(&path).expect("failed to read LIFE_PUBLIC_KEY")
+        }
+        None => {
+            println!("cargo:warning=LIFE_PUBLIC_KEY is not set, initium will refuse to boot any kernel");
+            vec![0; 32]
+        }
+    };
+
+    assert_eq!(public_key.len(), 32, "LIFE_PUBLIC_KEY must contain a raw 32 byte Ed25519 public key");
+    std::fs::write(public_key_file, public_key).unwrap();
+}
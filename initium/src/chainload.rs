@@ -0,0 +1,71 @@
+use core::mem::MaybeUninit;
+
+use synapse::boot::BootEntry;
+use uefi::{
+    prelude::{Boot, Handle, SystemTable},
+    proto::device_path::{
+        build::{media::FilePath, DevicePathBuilder},
+        DevicePath,
+    },
+    table::boot::{LoadImageSource, OpenProtocolAttributes, OpenProtocolParams},
+};
+
+use crate::fs;
+
+/// Loads an EFI application from the boot partition, or the volume its path names, and runs it
+/// until it exits.
+pub fn start_efi_application(
+    image: Handle,
+    system_table: &SystemTable<Boot>,
+    entry: &BootEntry,
+) -> Result<(), &'static str> {
+    let (volume, path) = fs::split_volume(entry.path())?;
+    let mut path_buf = [0u16; fs::MAX_PATH_LEN + 2];
+    let path_name = fs::to_uefi_path(path, &mut path_buf)?;
+
+    // the loaded image needs a complete device path to find files relative to itself
+    let mut device_path_buf = [MaybeUninit::uninit(); 1024];
+    let file_path = {
+        let handle = fs::open_volume(volume, image, system_table)?.handle;
+        let device_path = unsafe {
+            system_table.boot_services().open_protocol::<DevicePath>(
+                OpenProtocolParams {
+                    handle,
+                    agent: image,
+                    controller: None,
+                },
+                OpenProtocolAttributes::GetProtocol,
+            )
+        }
+        .map_err(|_| "failed to open the device path of the volume")?;
+
+        let mut builder = DevicePathBuilder::with_buf(&mut device_path_buf);
+        for node in device_path.node_iter() {
+            builder = builder.push(&node).map_err(|_| "device path is too long")?;
+        }
+        builder
+            .push(&FilePath { path_name })
+            .and_then(|builder| builder.finalize())
+            .map_err(|_| "device path is too long")?
+    };
+
+    let data = fs::load_file(entry.path(), image, system_table)?.ok_or("EFI application not found")?;
+
+    let boot_services = system_table.boot_services();
+    let child = boot_services.load_image(
+        image,
+        LoadImageSource::FromBuffer {
+            buffer: data,
+            file_path: Some(file_path),
+        },
+    );
+    fs::free_file(data, system_table);
+    let child = child.map_err(|_| "failed to load the EFI application")?;
+
+    // the firmware watchdog would reset the machine after five minutes
+    let _ = boot_services.set_watchdog_timer(0, 0x10000, None);
+
+    boot_services
+        .start_image(child)
+        .map_err(|_| "the EFI application exited with an error")
+}
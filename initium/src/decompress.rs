@@ -0,0 +1,127 @@
+use core::{iter, slice};
+
+use synapse::compression::{Compression, CompressionHeader, COMPRESSION_HEADER_LEN};
+
+use uefi::{
+    prelude::{Boot, SystemTable},
+    table::boot::{AllocateType, MemoryType},
+};
+
+fn read_lz4_length(input: &[u8], position: &mut usize, mut len: usize) -> Result<usize, &'static str> {
+    if len == 15 {
+        loop {
+            let byte = *input.get(*position).ok_or("LZ4 length is truncated")?;
+            *position += 1;
+            len += usize::from(byte);
+
+            if byte != 255 {
+                break;
+            }
+        }
+    }
+
+    Ok(len)
+}
+
+/// Decodes an LZ4 block into `output` and returns the number of bytes written.
+fn decompress_lz4(input: &[u8], output: &mut [u8]) -> Result<usize, &'static str> {
+    let mut input_position = 0;
+    let mut output_position = 0;
+
+    while input_position < input.len() {
+        let token = input[input_position];
+        input_position += 1;
+
+        let literal_len = read_lz4_length(input, &mut input_position, usize::from(token >> 4))?;
+        let literal_end = input_position
+            .checked_add(literal_len)
+            .ok_or("LZ4 literal length overflows")?;
+        let literals = input
+            .get(input_position..literal_end)
+            .ok_or("LZ4 literals are truncated")?;
+        output
+            .get_mut(output_position..output_position + literal_len)
+            .ok_or("LZ4 literals exceed the uncompressed size")?
+            .copy_from_slice(literals);
+        input_position = literal_end;
+        output_position += literal_len;
+
+        // the last sequence of a block only contains literals
+        if input_position == input.len() {
+            break;
+        }
+
+        let offset = input
+            .get(input_position..input_position + 2)
+            .map(|bytes| usize::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+            .ok_or("LZ4 match offset is truncated")?;
+        input_position += 2;
+
+        if offset == 0 || offset > output_position {
+            return Err("LZ4 match offset is out of bounds");
+        }
+
+        let match_len = read_lz4_length(input, &mut input_position, usize::from(token & 0xf))? + 4;
+        if output_position + match_len > output.len() {
+            return Err("LZ4 match exceeds the uncompressed size");
+        }
+
+        // matches may overlap with their own output, so copy byte by byte
+        for i in output_position..output_position + match_len {
+            output[i] = output[i - offset];
+        }
+        output_position += match_len;
+    }
+
+    Ok(output_position)
+}
+
+fn decompress_deflate(input: &[u8], output: &mut [u8]) -> Result<usize, &'static str> {
+    miniz_oxide::inflate::decompress_slice_iter_to_slice(output, iter::once(input), false, true)
+        .map_err(|_| "invalid deflate stream")
+}
+
+/// Decompresses `data` into newly allocated pages if it starts with a compression header,
+/// and frees the pages of the compressed data.
+///
+/// Data without a compression header is returned unchanged.
+pub fn decompress_file(
+    data: &'static mut [u8],
+    system_table: &SystemTable<Boot>,
+) -> Result<&'static mut [u8], &'static str> {
+    let Some(header) = CompressionHeader::parse(data) else {
+        return Ok(data);
+    };
+
+    let compressed_len =
+        usize::try_from(header.compressed_len).map_err(|_| "compressed size is too large")?;
+    let payload = data[COMPRESSION_HEADER_LEN..]
+        .get(..compressed_len)
+        .ok_or("compressed file is truncated")?;
+
+    let len = usize::try_from(header.uncompressed_len).map_err(|_| "uncompressed size is too large")?;
+    if len == 0 {
+        return Err("compressed file is empty");
+    }
+
+    let output_ptr = system_table
+        .boot_services()
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, ((len - 1) / 4096) + 1)
+        .map_err(|_| "failed to allocate pages for the decompressed file")? as *mut u8;
+    let output = unsafe { slice::from_raw_parts_mut(output_ptr, len) };
+
+    let written = match header.compression {
+        Compression::Lz4 => decompress_lz4(payload, output)?,
+        Compression::Deflate => decompress_deflate(payload, output)?,
+    };
+
+    if written != len {
+        return Err("decompressed size does not match the compression header");
+    }
+
+    let _ = system_table
+        .boot_services()
+        .free_pages(data.as_ptr() as u64, ((data.len() - 1) / 4096) + 1);
+
+    Ok(output)
+}
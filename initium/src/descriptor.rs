@@ -1,8 +1,9 @@
 use crate::memory::LegacyMemoryRegion;
+use crate::memtest::BAD_MEMORY_TYPE;
 
 use synapse::memory::MemoryRegionKind;
 
-use uefi::table::boot::{MemoryDescriptor, MemoryType};
+use uefi::table::boot::{MemoryAttribute, MemoryDescriptor, MemoryType};
 use x86_64::PhysAddr;
 
 #[derive(Copy, Clone)]
@@ -22,6 +23,7 @@ impl LegacyMemoryRegion for UefiMemoryDescriptor {
     fn kind(&self) -> MemoryRegionKind {
         match self.0.ty {
             MemoryType::CONVENTIONAL => MemoryRegionKind::Usable,
+            BAD_MEMORY_TYPE => MemoryRegionKind::Bad,
             other => MemoryRegionKind::UnknownUefi(other.0),
         }
     }
@@ -41,4 +43,8 @@ impl LegacyMemoryRegion for UefiMemoryDescriptor {
             _ => false,
         }
     }
+
+    fn is_runtime(&self) -> bool {
+        self.0.att.contains(MemoryAttribute::RUNTIME)
+    }
 }
\ No newline at end of file
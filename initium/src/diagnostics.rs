@@ -0,0 +1,309 @@
+use core::{
+    fmt::{self, Write},
+    slice,
+};
+
+use synapse::boot::DIAGNOSTICS_FILE_NAME;
+use uefi::{
+    prelude::{Boot, Handle, SystemTable},
+    proto::{
+        console::gop::GraphicsOutput,
+        device_path::DevicePath,
+        loaded_image::LoadedImage,
+        media::{
+            block::BlockIO,
+            file::{File, FileAttribute, FileMode, FileSystemInfo},
+            fs::SimpleFileSystem,
+        },
+        ProtocolPointer,
+    },
+    table::{
+        boot::{AllocateType, MemoryType, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, SearchType},
+        cfg,
+    },
+    CStr16, Guid, Identify,
+};
+
+use crate::{locate_and_open_protocol, menu, with_memory_map};
+
+/// Pages of the report buffer, longer reports are truncated.
+const REPORT_PAGES: usize = 64;
+/// Width of the memory type column of the memory map.
+const TYPE_COLUMN_WIDTH: usize = 24;
+
+/// Report text in a fixed buffer, writes beyond its end are dropped.
+struct Report {
+    buf: &'static mut [u8],
+    len: usize,
+}
+
+impl Report {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for Report {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let available = self.buf.len() - self.len;
+        let mut len = s.len().min(available);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+fn protocol_name(guid: &Guid) -> &'static str {
+    match *guid {
+        LoadedImage::GUID => "LoadedImage",
+        DevicePath::GUID => "DevicePath",
+        SimpleFileSystem::GUID => "SimpleFileSystem",
+        BlockIO::GUID => "BlockIO",
+        GraphicsOutput::GUID => "GraphicsOutput",
+        uefi::proto::console::text::Input::GUID => "SimpleTextInput",
+        uefi::proto::console::text::Output::GUID => "SimpleTextOutput",
+        uefi::proto::media::disk::DiskIo::GUID => "DiskIo",
+        uefi::proto::media::partition::PartitionInfo::GUID => "PartitionInfo",
+        uefi::proto::console::serial::Serial::GUID => "SerialIo",
+        uefi::proto::pi::mp::MpServices::GUID => "MpServices",
+        uefi::proto::rng::Rng::GUID => "Rng",
+        _ => "",
+    }
+}
+
+fn config_table_name(guid: &Guid) -> &'static str {
+    match *guid {
+        cfg::ACPI_GUID => "ACPI 1.0",
+        cfg::ACPI2_GUID => "ACPI 2.0",
+        cfg::SMBIOS_GUID => "SMBIOS",
+        cfg::SMBIOS3_GUID => "SMBIOS 3",
+        cfg::PROPERTIES_TABLE_GUID => "UEFI properties",
+        cfg::HAND_OFF_BLOCK_LIST_GUID => "HOB list",
+        cfg::MEMORY_TYPE_INFORMATION_GUID => "memory type information",
+        cfg::MEMORY_STATUS_CODE_RECORD_GUID => "memory status code record",
+        cfg::DXE_SERVICES_GUID => "DXE services",
+        cfg::LZMA_COMPRESS_GUID => "LZMA compression",
+        cfg::TIANO_COMPRESS_GUID => "Tiano compression",
+        cfg::DEBUG_IMAGE_INFO_GUID => "debug image info",
+        _ => "",
+    }
+}
+
+/// Opens a protocol without taking it from its current users, e.g. the GOP from the console.
+fn get_protocol<P: ProtocolPointer + ?Sized>(
+    image: Handle,
+    handle: Handle,
+    system_table: &SystemTable<Boot>,
+) -> Option<ScopedProtocol<P>> {
+    unsafe {
+        system_table
+            .boot_services()
+            .open_protocol::<P>(
+                OpenProtocolParams {
+                    handle,
+                    agent: image,
+                    controller: None,
+                },
+                OpenProtocolAttributes::GetProtocol,
+            )
+            .ok()
+    }
+}
+
+fn write_memory_map(report: &mut Report, system_table: &SystemTable<Boot>) {
+    let _ = writeln!(report, "memory map");
+    with_memory_map(system_table, |memory_map| {
+        for descriptor in memory_map.entries() {
+            let _ = write!(
+                report,
+                "  {:#014x}-{:#014x} ",
+                descriptor.phys_start,
+                descriptor.phys_start + descriptor.page_count * 4096,
+            );
+            // `Debug` of the memory type ignores the width, so the column is padded by hand
+            let type_start = report.len;
+            let _ = write!(report, "{:?}", descriptor.ty);
+            let padding = TYPE_COLUMN_WIDTH.saturating_sub(report.len - type_start);
+            let _ = writeln!(report, "{:padding$} {:?}", "", descriptor.att);
+        }
+    });
+}
+
+fn write_gop_modes(report: &mut Report, image: Handle, system_table: &SystemTable<Boot>) {
+    let _ = writeln!(report, "\ngraphics modes");
+    let Ok(handle) = system_table.boot_services().get_handle_for_protocol::<GraphicsOutput>() else {
+        let _ = writeln!(report, "  no graphics output");
+        return;
+    };
+    let Some(gop) = get_protocol::<GraphicsOutput>(image, handle, system_table) else {
+        let _ = writeln!(report, "  failed to open the graphics output");
+        return;
+    };
+
+    let current = gop.current_mode_info();
+    for mode in gop.modes() {
+        let info = mode.info();
+        let (width, height) = info.resolution();
+        let _ = writeln!(
+            report,
+            "  {}x{} {:?} stride {}{}",
+            width,
+            height,
+            info.pixel_format(),
+            info.stride(),
+            if info.resolution() == current.resolution() { " (current)" } else { "" },
+        );
+    }
+}
+
+fn write_handles(report: &mut Report, system_table: &SystemTable<Boot>) {
+    let _ = writeln!(report, "\nhandles");
+    let boot_services = system_table.boot_services();
+    let Ok(handles) = boot_services.locate_handle_buffer(SearchType::AllHandles) else {
+        let _ = writeln!(report, "  failed to list the handles");
+        return;
+    };
+
+    for &handle in handles.iter() {
+        let _ = writeln!(report, "  {:?}", handle);
+        let Ok(protocols) = boot_services.protocols_per_handle(handle) else {
+            continue;
+        };
+        for guid in protocols.iter() {
+            let _ = writeln!(report, "    {} {}", guid, protocol_name(guid));
+        }
+    }
+}
+
+fn write_config_tables(report: &mut Report, system_table: &SystemTable<Boot>) {
+    let _ = writeln!(report, "\nconfiguration tables");
+    for entry in system_table.config_table() {
+        let _ = writeln!(
+            report,
+            "  {} {:#014x} {}",
+            entry.guid,
+            entry.address as u64,
+            config_table_name(&entry.guid)
+        );
+    }
+}
+
+fn write_devices(report: &mut Report, image: Handle, system_table: &SystemTable<Boot>) {
+    let boot_services = system_table.boot_services();
+
+    let _ = writeln!(report, "\nblock devices");
+    if let Ok(handles) = boot_services.locate_handle_buffer(SearchType::from_proto::<BlockIO>()) {
+        for &handle in handles.iter() {
+            let Some(block_io) = get_protocol::<BlockIO>(image, handle, system_table) else {
+                continue;
+            };
+            let media = block_io.media();
+            let _ = writeln!(
+                report,
+                "  {:?} media {} {} blocks of {} bytes{}{}{}{}",
+                handle,
+                media.media_id(),
+                media.last_block() + 1,
+                media.block_size(),
+                if media.is_logical_partition() { ", partition" } else { "" },
+                if media.is_removable_media() { ", removable" } else { "" },
+                if media.is_read_only() { ", read-only" } else { "" },
+                if media.is_media_present() { "" } else { ", no media" },
+            );
+        }
+    }
+
+    let _ = writeln!(report, "\nfile systems");
+    if let Ok(handles) = boot_services.locate_handle_buffer(SearchType::from_proto::<SimpleFileSystem>()) {
+        for &handle in handles.iter() {
+            let Some(mut file_system) = get_protocol::<SimpleFileSystem>(image, handle, system_table) else {
+                continue;
+            };
+            let Ok(mut root) = file_system.open_volume() else {
+                continue;
+            };
+            let mut buf = [0; 500];
+            let Ok(info) = root.get_info::<FileSystemInfo>(&mut buf) else {
+                continue;
+            };
+            let _ = writeln!(
+                report,
+                "  {:?} \"{}\" {} KiB, {} KiB free{}",
+                handle,
+                info.volume_label(),
+                info.volume_size() / 1024,
+                info.free_space() / 1024,
+                if info.read_only() { ", read-only" } else { "" },
+            );
+        }
+    }
+}
+
+fn write_report_file(report: &Report, image: Handle, system_table: &SystemTable<Boot>) -> Result<(), &'static str> {
+    let mut file_system = locate_and_open_protocol::<SimpleFileSystem>(image, system_table)
+        .ok_or("failed to open the boot partition")?;
+    let mut root = file_system.open_volume().map_err(|_| "failed to open the boot partition")?;
+
+    let mut buf = [0u16; 64];
+    let name = CStr16::from_str_with_buf(DIAGNOSTICS_FILE_NAME, &mut buf).unwrap();
+
+    // replace the report of an earlier run
+    if let Ok(old) = root.open(name, FileMode::ReadWrite, FileAttribute::empty()) {
+        let _ = old.delete();
+    }
+
+    let mut file = root
+        .open(name, FileMode::CreateReadWrite, FileAttribute::empty())
+        .ok()
+        .and_then(|handle| handle.into_regular_file())
+        .ok_or("failed to create the report file")?;
+    file.write(report.as_str().as_bytes())
+        .map_err(|_| "failed to write the report file")?;
+    file.flush().map_err(|_| "failed to write the report file")
+}
+
+/// Prints what the firmware provides and writes it to `DIAGNOSTICS_FILE_NAME` on the boot partition.
+pub fn run(image: Handle, system_table: &mut SystemTable<Boot>) {
+    let buffer_ptr = system_table
+        .boot_services()
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, REPORT_PAGES)
+        .expect("failed to allocate pages for the diagnostics report") as *mut u8;
+    let mut report = Report {
+        buf: unsafe { slice::from_raw_parts_mut(buffer_ptr, REPORT_PAGES * 4096) },
+        len: 0,
+    };
+
+    let _ = writeln!(
+        report,
+        "firmware {} revision {:#x}, UEFI {}\n",
+        system_table.firmware_vendor(),
+        system_table.firmware_revision(),
+        system_table.uefi_revision(),
+    );
+    write_memory_map(&mut report, system_table);
+    write_gop_modes(&mut report, image, system_table);
+    write_handles(&mut report, system_table);
+    write_config_tables(&mut report, system_table);
+    write_devices(&mut report, image, system_table);
+
+    let stdout = system_table.stdout();
+    let _ = stdout.clear();
+    let _ = stdout.write_str(report.as_str());
+    match write_report_file(&report, image, system_table) {
+        Ok(()) => {
+            let _ = writeln!(system_table.stdout(), "\nreport written to {DIAGNOSTICS_FILE_NAME}");
+        }
+        Err(err) => {
+            let _ = writeln!(system_table.stdout(), "\n{err}");
+        }
+    }
+
+    let _ = system_table.boot_services().free_pages(buffer_ptr as u64, REPORT_PAGES);
+
+    let _ = writeln!(system_table.stdout(), "press any key to return to the menu");
+    menu::wait_for_key(system_table, false);
+}
@@ -0,0 +1,80 @@
+use core::slice;
+
+use synapse::framebuffer::{Edid, EDID_BLOCK_LEN};
+
+use uefi::{
+    prelude::{Boot, Handle, SystemTable},
+    proto::{unsafe_protocol, ProtocolPointer},
+    table::boot::{OpenProtocolAttributes, OpenProtocolParams},
+};
+
+/// EDID of the monitor as overridden by the platform, see UEFI specification 12.9.2.
+#[repr(C)]
+#[unsafe_protocol("bd8c1056-9f36-44ec-92a8-a6337f817986")]
+pub struct EdidActive {
+    size_of_edid: u32,
+    edid: *const u8,
+}
+
+/// EDID of the monitor as read by the graphics driver.
+#[repr(C)]
+#[unsafe_protocol("1c0c34f6-d380-41fa-a049-8ad06c1a66aa")]
+pub struct EdidDiscovered {
+    size_of_edid: u32,
+    edid: *const u8,
+}
+
+trait EdidProtocol: ProtocolPointer {
+    fn raw(&self) -> (u32, *const u8);
+}
+
+impl EdidProtocol for EdidActive {
+    fn raw(&self) -> (u32, *const u8) {
+        (self.size_of_edid, self.edid)
+    }
+}
+
+impl EdidProtocol for EdidDiscovered {
+    fn raw(&self) -> (u32, *const u8) {
+        (self.size_of_edid, self.edid)
+    }
+}
+
+fn read_edid<P: EdidProtocol>(
+    image: Handle,
+    gop_handle: Handle,
+    system_table: &SystemTable<Boot>,
+) -> Option<Edid> {
+    let protocol = unsafe {
+        system_table
+            .boot_services()
+            .open_protocol::<P>(
+                OpenProtocolParams {
+                    handle: gop_handle,
+                    agent: image,
+                    controller: None,
+                },
+                OpenProtocolAttributes::GetProtocol,
+            )
+            .ok()?
+    };
+
+    let (size, ptr) = protocol.raw();
+    if ptr.is_null() || (size as usize) < EDID_BLOCK_LEN {
+        return None;
+    }
+
+    let mut bytes = [0; EDID_BLOCK_LEN];
+    bytes.copy_from_slice(unsafe { slice::from_raw_parts(ptr, EDID_BLOCK_LEN) });
+
+    Some(Edid::new(bytes)).filter(Edid::is_valid)
+}
+
+pub fn load_edid(
+    image: Handle,
+    gop_handle: Handle,
+    system_table: &SystemTable<Boot>,
+) -> Option<Edid> {
+    read_edid::<EdidActive>(image, gop_handle, system_table)
+        .or_else(|| read_edid::<EdidDiscovered>(image, gop_handle, system_table))
+}
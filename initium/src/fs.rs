@@ -0,0 +1,278 @@
+use core::{ptr, slice};
+
+use uefi::{
+    data_types::EqStrUntilNul,
+    prelude::{Boot, Handle, SystemTable},
+    proto::{
+        device_path::{media::PartitionSignature, DevicePath, DevicePathNodeEnum},
+        media::{
+            file::{Directory, File, FileAttribute, FileInfo, FileMode, FileSystemVolumeLabel, FileType, RegularFile},
+            fs::SimpleFileSystem,
+        },
+    },
+    table::boot::{AllocateType, MemoryType, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol, SearchType},
+    CStr16, Guid,
+};
+
+use crate::open_device_path_protocol;
+
+/// Longest path, including the volume prefix, that can be loaded.
+pub const MAX_PATH_LEN: usize = 255;
+
+/// Volume a path refers to, selected by a `(label:NAME)` or `(guid:PARTITION-GUID)` prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Volume<'a> {
+    /// The volume initium was loaded from, used for paths without a prefix.
+    Boot,
+    Label(&'a str),
+    /// Unique GUID of a GPT partition.
+    PartitionGuid(Guid),
+}
+
+/// Splits `path` into its volume and the path on that volume, e.g.
+/// `(label:DATA)/life/kernels/nukleus-debug` into `Volume::Label("DATA")` and `/life/kernels/nukleus-debug`.
+pub fn split_volume(path: &str) -> Result<(Volume<'_>, &str), &'static str> {
+    let Some(rest) = path.strip_prefix('(') else {
+        return Ok((Volume::Boot, path));
+    };
+    let (volume, path) = rest.split_once(')').ok_or("missing `)` after the volume of the path")?;
+
+    let volume = match volume.split_once(':') {
+        Some(("label", label)) if !label.is_empty() => Volume::Label(label),
+        Some(("guid", guid)) => Volume::PartitionGuid(parse_guid(guid).ok_or("invalid partition GUID")?),
+        _ => return Err("the volume of a path must be `(label:NAME)` or `(guid:PARTITION-GUID)`"),
+    };
+
+    Ok((volume, path))
+}
+
+/// Parses a GUID in its canonical `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx` form.
+fn parse_guid(guid: &str) -> Option<Guid> {
+    let mut groups = guid.split('-');
+    let mut group = |len: usize| {
+        let group = groups.next().filter(|group| group.len() == len)?;
+        u64::from_str_radix(group, 16).ok()
+    };
+
+    let guid = Guid::from_values(
+        group(8)? as u32,
+        group(4)? as u16,
+        group(4)? as u16,
+        group(4)? as u16,
+        group(12)?,
+    );
+    groups.next().is_none().then_some(guid)
+}
+
+/// Root directory of a volume, the file system protocol stays open as long as the directory is used.
+pub struct OpenVolume<'a> {
+    pub handle: Handle,
+    pub root: Directory,
+    _file_system: ScopedProtocol<'a, SimpleFileSystem>,
+}
+
+pub fn open_volume<'a>(
+    volume: Volume,
+    image: Handle,
+    system_table: &'a SystemTable<Boot>,
+) -> Result<OpenVolume<'a>, &'static str> {
+    let boot_services = system_table.boot_services();
+
+    let handle = match volume {
+        Volume::Boot => {
+            let device_path = open_device_path_protocol(image, system_table)
+                .ok_or("failed to open the device path of the boot partition")?;
+            let mut device_path = &*device_path;
+            boot_services
+                .locate_device_path::<SimpleFileSystem>(&mut device_path)
+                .map_err(|_| "the boot partition has no file system")?
+        }
+        _ => {
+            let handles = boot_services
+                .locate_handle_buffer(SearchType::from_proto::<SimpleFileSystem>())
+                .map_err(|_| "failed to list the file systems")?;
+            handles
+                .iter()
+                .copied()
+                .find(|&handle| is_volume(handle, volume, image, system_table))
+                .ok_or("volume not found")?
+        }
+    };
+
+    let mut file_system = unsafe {
+        boot_services.open_protocol::<SimpleFileSystem>(
+            OpenProtocolParams {
+                handle,
+                agent: image,
+                controller: None,
+            },
+            OpenProtocolAttributes::Exclusive,
+        )
+    }
+    .map_err(|_| "failed to open the volume")?;
+    let root = file_system.open_volume().map_err(|_| "failed to open the volume")?;
+    Ok(OpenVolume {
+        handle,
+        root,
+        _file_system: file_system,
+    })
+}
+
+fn is_volume(handle: Handle, volume: Volume, image: Handle, system_table: &SystemTable<Boot>) -> bool {
+    let boot_services = system_table.boot_services();
+    let params = OpenProtocolParams {
+        handle,
+        agent: image,
+        controller: None,
+    };
+
+    match volume {
+        Volume::Boot => false,
+        Volume::Label(label) => {
+            let Ok(mut file_system) =
+                (unsafe { boot_services.open_protocol::<SimpleFileSystem>(params, OpenProtocolAttributes::GetProtocol) })
+            else {
+                return false;
+            };
+            let Ok(mut root) = file_system.open_volume() else {
+                return false;
+            };
+
+            let mut buf = [0; 256];
+            root.get_info::<FileSystemVolumeLabel>(&mut buf)
+                .map_or(false, |info| info.volume_label().eq_str_until_nul(&label))
+        }
+        Volume::PartitionGuid(guid) => {
+            let Ok(device_path) =
+                (unsafe { boot_services.open_protocol::<DevicePath>(params, OpenProtocolAttributes::GetProtocol) })
+            else {
+                return false;
+            };
+
+            let matches = device_path.node_iter().any(|node| match node.as_enum() {
+                Ok(DevicePathNodeEnum::MediaHardDrive(hard_drive)) => {
+                    hard_drive.partition_signature() == PartitionSignature::Guid(guid)
+                }
+                _ => false,
+            });
+            matches
+        }
+    }
+}
+
+/// Converts a path on a volume to an absolute UEFI path, which is separated by backslashes.
+pub fn to_uefi_path<'a>(path: &str, buf: &'a mut [u16; MAX_PATH_LEN + 2]) -> Result<&'a CStr16, &'static str> {
+    let mut utf8 = [0u8; MAX_PATH_LEN + 1];
+    utf8[0] = b'\\';
+    let mut len = 1;
+    for byte in path.trim_start_matches(['/', '\\']).bytes() {
+        *utf8.get_mut(len).ok_or("path is too long")? = if byte == b'/' { b'\\' } else { byte };
+        len += 1;
+    }
+    let path = core::str::from_utf8(&utf8[..len]).unwrap();
+
+    CStr16::from_str_with_buf(path, buf).map_err(|_| "path is not valid UCS-2")
+}
+
+/// Joins `name` to the directory `dir`, which may start with a volume.
+pub fn join<'a>(dir: &str, name: &str, buf: &'a mut [u8; MAX_PATH_LEN]) -> Result<&'a str, &'static str> {
+    let dir = dir.trim_end_matches(['/', '\\']);
+    let len = dir.len() + 1 + name.len();
+    if len > MAX_PATH_LEN {
+        return Err("path is too long");
+    }
+
+    buf[..dir.len()].copy_from_slice(dir.as_bytes());
+    buf[dir.len()] = b'/';
+    buf[dir.len() + 1..len].copy_from_slice(name.as_bytes());
+    Ok(core::str::from_utf8(&buf[..len]).unwrap())
+}
+
+/// Reads a file into newly allocated pages, `Ok(None)` if it does not exist.
+///
+/// The pages stay allocated unless the file is passed to `free_file`.
+pub fn load_file(
+    path: &str,
+    image: Handle,
+    system_table: &SystemTable<Boot>,
+) -> Result<Option<&'static mut [u8]>, &'static str> {
+    load_file_with(path, AllocateType::AnyPages, image, system_table)
+}
+
+/// Like `load_file`, but the file ends below `max_address`.
+pub fn load_file_below(
+    path: &str,
+    max_address: u64,
+    image: Handle,
+    system_table: &SystemTable<Boot>,
+) -> Result<Option<&'static mut [u8]>, &'static str> {
+    load_file_with(path, AllocateType::MaxAddress(max_address - 1), image, system_table)
+}
+
+fn load_file_with(
+    path: &str,
+    allocate_type: AllocateType,
+    image: Handle,
+    system_table: &SystemTable<Boot>,
+) -> Result<Option<&'static mut [u8]>, &'static str> {
+    with_file(path, image, system_table, |mut file| {
+        let mut buf = [0; 500];
+        let file_info: &mut FileInfo = file.get_info(&mut buf).map_err(|_| "failed to read the file size")?;
+        let file_size = usize::try_from(file_info.file_size()).map_err(|_| "the file is too large")?;
+
+        let file_ptr = system_table
+            .boot_services()
+            .allocate_pages(allocate_type, MemoryType::LOADER_DATA, pages(file_size))
+            .map_err(|_| "failed to allocate pages for the file")? as *mut u8;
+        unsafe { ptr::write_bytes(file_ptr, 0, file_size) };
+        let file_slice = unsafe { slice::from_raw_parts_mut(file_ptr, file_size) };
+
+        match file.read(file_slice) {
+            Ok(read) if read == file_size => Ok(file_slice),
+            _ => {
+                free_file(file_slice, system_table);
+                Err("failed to read the file")
+            }
+        }
+    })
+}
+
+/// Opens a regular file and passes it to `f`, `Ok(None)` if it does not exist.
+fn with_file<T>(
+    path: &str,
+    image: Handle,
+    system_table: &SystemTable<Boot>,
+    f: impl FnOnce(RegularFile) -> Result<T, &'static str>,
+) -> Result<Option<T>, &'static str> {
+    let (volume, path) = split_volume(path)?;
+    let mut volume = open_volume(volume, image, system_table)?;
+
+    let mut buf = [0u16; MAX_PATH_LEN + 2];
+    let filename = to_uefi_path(path, &mut buf)?;
+
+    let Ok(file_handle) = volume.root.open(filename, FileMode::Read, FileAttribute::empty()) else {
+        return Ok(None);
+    };
+    let file = match file_handle.into_type().map_err(|_| "failed to open the file")? {
+        FileType::Regular(file) => file,
+        FileType::Dir(_) => return Err("the path is a directory"),
+    };
+
+    f(file).map(Some)
+}
+
+/// Frees the pages of a file returned by `load_file`.
+pub fn free_file(file: &mut [u8], system_table: &SystemTable<Boot>) {
+    let _ = system_table
+        .boot_services()
+        .free_pages(file.as_ptr() as u64, pages(file.len()));
+}
+
+/// Pages allocated for a file, empty files get a page as well.
+pub fn pages(len: usize) -> usize {
+    ((len.max(1) - 1) / 4096) + 1
+}
+
+pub fn file_exists(path: &str, image: Handle, system_table: &SystemTable<Boot>) -> bool {
+    matches!(with_file(path, image, system_table, |_| Ok(())), Ok(Some(())))
+}
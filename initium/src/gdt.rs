@@ -16,6 +16,8 @@ pub fn create_and_load(frame: PhysFrame) {
     let mut gdt = GlobalDescriptorTable::new();
     let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
     let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+    // 32-bit code segment at selector 0x18, used by `la57` to leave long mode
+    gdt.add_entry(Descriptor::UserSegment(0x00cf_9a00_0000_ffff));
     let gdt = unsafe {
         ptr.write(gdt);
         &*ptr
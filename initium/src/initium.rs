@@ -1,7 +1,7 @@
 use core::alloc::Layout;
 use core::arch::asm;
-use core::mem::MaybeUninit;
-use core::slice;
+use core::mem::{size_of, MaybeUninit};
+use core::{ptr, slice};
 use usize_conversions::FromUsize;
 use x86_64::{PhysAddr, structures::{
     paging::{
@@ -12,23 +12,33 @@ use x86_64::{PhysAddr, structures::{
         OffsetPageTable,
     },
 }, VirtAddr};
-use x86_64::structures::paging::{FrameAllocator, Mapper, PageTableFlags, PageTableIndex, Size2MiB};
+use x86_64::registers::model_specific::FsBase;
+use x86_64::structures::paging::{FrameAllocator, Mapper, PageTableFlags, PageTableIndex, Size1GiB, Size2MiB, Translate};
 use x86_64::structures::paging::page_table::PageTableLevel;
 
-use synapse::boot::{BootConfig, BootInfo};
-use synapse::framebuffer::{Framebuffer, FramebufferInfo};
-use synapse::memory::MemoryRegion;
-use synapse::tls_template::TlsTemplate;
+use synapse::boot::{BootConfig, BootInfo, PagingMode, PhysicalMemoryMapping};
+use synapse::extension::{ExtensionWriter, Extensions, TAG_BOOTLOADER_NAME};
+use synapse::firmware::FirmwareInfo;
+use synapse::framebuffer::{CachingMode, Edid, Framebuffer, FramebufferInfo};
+use synapse::memory::{MemoryRegion, VirtualRegion, VirtualRegionKind};
+use synapse::slot::BootSlotInfo;
+use synapse::smp::SmpInfo;
+use synapse::timing::{BootPhase, BootTimings};
+use synapse::tls_template::{TlsBlock, TlsTemplate};
 use crate::entries::Entries;
 use crate::gdt::create_and_load;
 use crate::kernel;
 use crate::kernel::{Kernel, load_kernel};
+use crate::la57;
 use crate::memory::{LegacyFrameAllocator, LegacyMemoryRegion};
+use crate::smp::{self, ApplicationProcessors};
+use crate::timing;
 
 #[derive(Debug, Copy, Clone)]
 pub struct RawFramebufferInfo {
     pub addr: PhysAddr,
     pub info: FramebufferInfo,
+    pub edid: Option<Edid>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -36,20 +46,74 @@ pub struct SystemInfo {
     pub framebuffer: Option<RawFramebufferInfo>,
 
     pub rsdp_addr: Option<PhysAddr>,
+    pub smbios_addr: Option<PhysAddr>,
+    pub smbios3_addr: Option<PhysAddr>,
+    pub firmware: FirmwareInfo,
     pub ramdisk_addr: Option<u64>,
     pub ramdisk_len: u64,
+    pub smp: Option<ApplicationProcessors>,
+    pub timings: BootTimings,
+    pub boot_slot: Option<BootSlotInfo>,
+    pub splash_shown: bool,
 }
 
-fn enable_nxe_bit() {
+pub fn enable_nxe_bit() {
     use x86_64::registers::control::{Efer, EferFlags};
     unsafe { Efer::update(|efer| *efer |= EferFlags::NO_EXECUTE_ENABLE) }
 }
 
-fn enable_write_protect_bit() {
+pub fn enable_write_protect_bit() {
     use x86_64::registers::control::{Cr0, Cr0Flags};
     unsafe { Cr0::update(|cr0| *cr0 |= Cr0Flags::WRITE_PROTECT) };
 }
 
+/// 4 KiB page table entry flag that selects PAT entry 4 to 7, together with `WRITE_THROUGH`
+/// and `NO_CACHE`. It is the bit of `HUGE_PAGE` in the other levels.
+pub const PAGE_ATTRIBUTE_TABLE: PageTableFlags = PageTableFlags::HUGE_PAGE;
+
+/// Programs PAT entry 4, which 4 KiB pages select with `PAGE_ATTRIBUTE_TABLE` alone, as
+/// write-combining. Entries 0 to 3 keep their defaults, which `WRITE_THROUGH` and `NO_CACHE`
+/// mappings rely on.
+///
+/// Follows the procedure of the Intel SDM for changing the memory types, caches and TLBs must
+/// not hold entries of the old type. Returns `false` if the processor has no page attribute table.
+pub fn enable_write_combining() -> bool {
+    use core::arch::x86_64::__cpuid;
+    use x86_64::instructions::{interrupts, tlb};
+    use x86_64::registers::control::{Cr0, Cr0Flags, Cr4, Cr4Flags};
+    use x86_64::registers::model_specific::Msr;
+
+    const IA32_PAT: u32 = 0x277;
+    const WRITE_COMBINING: u64 = 0x01;
+    const ENTRY: u64 = 4;
+
+    if unsafe { __cpuid(1) }.edx & (1 << 16) == 0 {
+        return false;
+    }
+
+    interrupts::without_interrupts(|| unsafe {
+        let cr0 = Cr0::read();
+        let cr4 = Cr4::read();
+
+        // no-fill cache mode, then flush the caches and, with global pages, all TLB entries
+        Cr0::write((cr0 | Cr0Flags::CACHE_DISABLE) - Cr0Flags::NOT_WRITE_THROUGH);
+        asm!("wbinvd", options(nostack, preserves_flags));
+        Cr4::write(cr4 - Cr4Flags::PAGE_GLOBAL);
+        tlb::flush_all();
+
+        let mut pat = Msr::new(IA32_PAT);
+        let value = pat.read();
+        pat.write(value & !(0xff << (ENTRY * 8)) | WRITE_COMBINING << (ENTRY * 8));
+
+        asm!("wbinvd", options(nostack, preserves_flags));
+        tlb::flush_all();
+        Cr0::write(cr0);
+        Cr4::write(cr4);
+    });
+
+    true
+}
+
 struct Addresses {
     page_table: PhysFrame,
     stack_top: VirtAddr,
@@ -73,11 +137,11 @@ unsafe fn context_switch(addresses: Addresses) -> ! {
 fn mapping_addr_page_aligned(
     size: u64,
     used_entries: &mut Entries,
-    kind: &str,
+    kind: VirtualRegionKind,
 ) -> Page {
-    match mapping_addr(size, Size4KiB::SIZE, used_entries) {
+    match mapping_addr(size, Size4KiB::SIZE, used_entries, kind) {
         Ok(addr) => Page::from_start_address(addr).unwrap(),
-        Err(addr) => panic!("{kind} address must be page-aligned (is `{addr:?})`"),
+        Err(addr) => panic!("{kind:?} address must be page-aligned (is `{addr:?})`"),
     }
 }
 
@@ -85,8 +149,9 @@ fn mapping_addr(
     size: u64,
     alignment: u64,
     used_entries: &mut Entries,
+    kind: VirtualRegionKind,
 ) -> Result<VirtAddr, VirtAddr> {
-    let addr = used_entries.get_free_address(size, alignment);
+    let addr = used_entries.get_free_address(size, alignment, kind);
 
     if addr.is_aligned(alignment) {
         Ok(addr)
@@ -99,18 +164,29 @@ pub struct PageTables {
     pub bootloader: OffsetPageTable<'static>,
     pub kernel: OffsetPageTable<'static>,
     pub kernel_level_4_frame: PhysFrame,
+    /// Root of the kernel page table if it uses 5-level paging.
+    pub kernel_level_5_frame: Option<PhysFrame>,
 }
 
 pub struct Mappings {
     pub entry_point: VirtAddr,
     pub stack_top: VirtAddr,
+    /// Physical address of the trampoline the application processors enter the kernel through.
+    pub ap_trampoline: Option<PhysAddr>,
+    /// Physical address of the switch to 5-level paging, if the kernel uses it.
+    pub la57_switch: Option<PhysAddr>,
     pub used_entries: Entries,
     pub framebuffer: Option<VirtAddr>,
+    pub framebuffer_caching: CachingMode,
+    /// Whether the UEFI runtime regions are identity mapped, so the kernel can call runtime services.
+    pub runtime_services_mapped: bool,
 
     pub physical_memory_offset: Option<VirtAddr>,
 
     /// The thread local storage template of the kernel executable, if it contains one.
     pub tls_template: Option<TlsTemplate>,
+    /// The thread local storage block of the bootstrap processor, created from `tls_template`.
+    pub tls_block: Option<TlsBlock>,
 
     pub kernel_slice_start: u64,
     pub kernel_slice_len: u64,
@@ -118,12 +194,200 @@ pub struct Mappings {
     pub ramdisk_slice_len: u64,
 }
 
+const KERNEL_STACK_SIZE: u64 = 80 * 1024;
+
+/// Maps a kernel stack preceded by an unmapped guard page and returns its end address.
+fn map_stack(
+    kernel_page_table: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    used_entries: &mut Entries,
+    kind: VirtualRegionKind,
+) -> VirtAddr {
+    let stack_start = {
+        let guard_page = mapping_addr_page_aligned(
+            Size4KiB::SIZE + KERNEL_STACK_SIZE,
+            used_entries,
+            kind,
+        );
+        guard_page + 1
+    };
+
+    let stack_end_addr = stack_start.start_address() + KERNEL_STACK_SIZE;
+
+    let stack_end = Page::containing_address(stack_end_addr - 1u64);
+    for page in Page::range_inclusive(stack_start, stack_end) {
+        let frame = frame_allocator
+            .allocate_frame()
+            .expect("frame allocation failed when mapping a kernel stack");
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+            Ok(tlb) => tlb.flush(),
+            Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+        }
+    }
+
+    stack_end_addr
+}
+
+/// Returns the physical address behind `addr` in the kernel address space.
+fn kernel_ptr(kernel_page_table: &OffsetPageTable<'static>, addr: VirtAddr) -> *mut u8 {
+    kernel_page_table
+        .translate_addr(addr)
+        .unwrap_or_else(|| panic!("{:?} is not mapped in the kernel page table", addr))
+        .as_u64() as *mut u8
+}
+
+/// Copies `len` bytes between two kernel virtual addresses, through their physical frames.
+fn copy_in_kernel(kernel_page_table: &OffsetPageTable<'static>, src: VirtAddr, dst: VirtAddr, len: u64) {
+    let mut copied = 0;
+    while copied < len {
+        let src = src + copied;
+        let dst = dst + copied;
+        let chunk = (len - copied)
+            .min(Size4KiB::SIZE - u64::from(src.page_offset()))
+            .min(Size4KiB::SIZE - u64::from(dst.page_offset()));
+
+        unsafe {
+            ptr::copy_nonoverlapping(
+                kernel_ptr(kernel_page_table, src),
+                kernel_ptr(kernel_page_table, dst),
+                chunk as usize,
+            );
+        }
+        copied += chunk;
+    }
+}
+
+/// Allocates and initialises the thread local storage block of the bootstrap processor.
+fn map_tls_block(
+    template: &TlsTemplate,
+    kernel_page_table: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    used_entries: &mut Entries,
+) -> TlsBlock {
+    let align = template.align.max(1);
+    let tls_offset = (template.mem_size + align - 1) & !(align - 1);
+    let len = tls_offset + size_of::<u64>() as u64;
+
+    let start_address = mapping_addr(len, align.max(Size4KiB::SIZE), used_entries, VirtualRegionKind::TlsBlock)
+        .expect("TLS block address is not properly aligned");
+    let start_page: Page = Page::containing_address(start_address);
+    let end_page = Page::containing_address(start_address + len - 1u64);
+
+    for page in Page::range_inclusive(start_page, end_page) {
+        let frame = frame_allocator
+            .allocate_frame()
+            .expect("frame allocation failed when mapping the TLS block");
+        unsafe { ptr::write_bytes(frame.start_address().as_u64() as *mut u8, 0, Size4KiB::SIZE as usize) };
+
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+            Ok(tlb) => tlb.flush(),
+            Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
+        }
+    }
+
+    // `.tbss` follows `.tdata` and is already zeroed
+    copy_in_kernel(
+        kernel_page_table,
+        VirtAddr::new(template.start_address),
+        start_address,
+        template.file_size,
+    );
+
+    let thread_pointer = start_address + tls_offset;
+    unsafe {
+        kernel_ptr(kernel_page_table, thread_pointer)
+            .cast::<u64>()
+            .write_unaligned(thread_pointer.as_u64());
+    }
+
+    TlsBlock {
+        start_address: start_address.as_u64(),
+        len,
+        thread_pointer: thread_pointer.as_u64(),
+    }
+}
+
+fn supports_1gib_pages() -> bool {
+    let features = unsafe { core::arch::x86_64::__cpuid(0x8000_0001) };
+    features.edx & (1 << 26) != 0
+}
+
+/// Maps all physical memory below `max_phys` at an offset with pages of size `S`
+/// and returns the offset.
+fn map_physical_memory<S, A>(
+    max_phys: PhysAddr,
+    kernel_page_table: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut A,
+    used_entries: &mut Entries,
+) -> VirtAddr
+    where
+        S: PageSize + core::fmt::Debug,
+        A: FrameAllocator<Size4KiB>,
+        OffsetPageTable<'static>: Mapper<S>,
+{
+    let start_frame: PhysFrame<S> = PhysFrame::containing_address(PhysAddr::new(0));
+    let end_frame: PhysFrame<S> = PhysFrame::containing_address(max_phys - 1u64);
+
+    let size = end_frame.start_address().as_u64() + S::SIZE;
+    let offset = mapping_addr(size, S::SIZE, used_entries, VirtualRegionKind::PhysicalMemory)
+        .expect("start address for physical memory mapping must be aligned to the page size");
+
+    for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
+        let page: Page<S> = Page::containing_address(offset + frame.start_address().as_u64());
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
+            Ok(tlb) => tlb.ignore(),
+            Err(err) => panic!(
+                "failed to map page {:?} to frame {:?}: {:?}",
+                page, frame, err
+            ),
+        };
+    }
+
+    offset
+}
+
+/// Identity maps the UEFI runtime regions, which is how runtime services expect to be called
+/// without `SetVirtualAddressMap`.
+///
+/// Returns `false` if a region lies outside the first level 4 entry reserved for identity mappings.
+fn map_runtime_regions<I, D>(
+    kernel_page_table: &mut OffsetPageTable<'static>,
+    frame_allocator: &mut LegacyFrameAllocator<I, D>,
+) -> bool
+    where
+        I: ExactSizeIterator<Item=D> + Clone,
+        D: LegacyMemoryRegion,
+{
+    let identity_end = PhysAddr::new(Size1GiB::SIZE * 512);
+    if frame_allocator.runtime_regions().any(|region| region.start() + region.len() > identity_end) {
+        return false;
+    }
+
+    for region in frame_allocator.runtime_regions().filter(|region| !region.is_empty()) {
+        let start_frame: PhysFrame = PhysFrame::containing_address(region.start());
+        let end_frame = PhysFrame::containing_address(region.start() + (region.len() - 1));
+        for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
+            let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+            match unsafe { kernel_page_table.identity_map(frame, flags, frame_allocator) } {
+                Ok(tlb) => tlb.ignore(),
+                Err(err) => panic!("failed to identity map frame {:?}: {:?}", frame, err),
+            }
+        }
+    }
+
+    true
+}
+
 pub fn set_up_mappings<I, D>(
     kernel: Kernel,
     frame_allocator: &mut LegacyFrameAllocator<I, D>,
     page_tables: &mut PageTables,
     framebuffer: Option<&RawFramebufferInfo>,
     system_info: &SystemInfo,
+    config: &BootConfig,
 ) -> Mappings
     where
         I: ExactSizeIterator<Item=D> + Clone,
@@ -135,6 +399,7 @@ pub fn set_up_mappings<I, D>(
 
     enable_nxe_bit();
     enable_write_protect_bit();
+    let write_combining = enable_write_combining();
 
     let kernel_slice_start = kernel.start_address as u64;
     let kernel_slice_len = u64::try_from(kernel.len).unwrap();
@@ -145,32 +410,18 @@ pub fn set_up_mappings<I, D>(
         frame_allocator,
         &mut used_entries,
     )
-        .expect("no entry point");
-
-    let kernel_stack_size = 80 * 1024;
-
-    let stack_start = {
-        let guard_page = mapping_addr_page_aligned(
-            Size4KiB::SIZE + kernel_stack_size,
-            &mut used_entries,
-            "kernel stack start",
-        );
-        guard_page + 1
-    };
+        .unwrap_or_else(|err| panic!("failed to load the kernel: {err}"));
 
-    let stack_end_addr = stack_start.start_address() + kernel_stack_size;
+    let stack_end_addr = map_stack(
+        kernel_page_table,
+        frame_allocator,
+        &mut used_entries,
+        VirtualRegionKind::KernelStack,
+    );
 
-    let stack_end = Page::containing_address(stack_end_addr - 1u64);
-    for page in Page::range_inclusive(stack_start, stack_end) {
-        let frame = frame_allocator
-            .allocate_frame()
-            .expect("frame allocation failed when mapping a kernel stack");
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-        match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
-            Ok(tlb) => tlb.flush(),
-            Err(err) => panic!("failed to map page {:?}: {:?}", page, err),
-        }
-    }
+    let tls_block = tls_template.as_ref().map(|template| {
+        map_tls_block(template, kernel_page_table, frame_allocator, &mut used_entries)
+    });
 
     let context_switch_function = PhysAddr::new(context_switch as *const () as u64);
     let context_switch_function_start_frame: PhysFrame =
@@ -199,6 +450,26 @@ pub fn set_up_mappings<I, D>(
         Err(err) => panic!("failed to identity map frame {:?}: {:?}", gdt_frame, err),
     }
 
+    let runtime_services_mapped = map_runtime_regions(kernel_page_table, frame_allocator);
+
+    let la57_switch = page_tables
+        .kernel_level_5_frame
+        .map(|_| la57::map_switch(kernel_page_table, frame_allocator));
+
+    let ap_trampoline = system_info.smp.as_ref().map(|processors| {
+        for mailbox in processors.mailboxes().iter_mut() {
+            let stack_end_addr = map_stack(
+                kernel_page_table,
+                frame_allocator,
+                &mut used_entries,
+                VirtualRegionKind::ApStack,
+            );
+            mailbox.stack_top = stack_end_addr.align_down(16u8).as_u64();
+        }
+
+        smp::map_trampoline(processors, la57_switch, kernel_page_table, frame_allocator)
+    });
+
     let framebuffer_virt_addr = if let Some(framebuffer) = framebuffer {
         let framebuffer_start_frame: PhysFrame = PhysFrame::containing_address(framebuffer.addr);
         let framebuffer_end_frame =
@@ -206,7 +477,7 @@ pub fn set_up_mappings<I, D>(
         let start_page = mapping_addr_page_aligned(
             u64::from_usize(framebuffer.info.byte_len),
             &mut used_entries,
-            "framebuffer",
+            VirtualRegionKind::Framebuffer,
         );
         for (i, frame) in
         PhysFrame::range_inclusive(framebuffer_start_frame, framebuffer_end_frame).enumerate()
@@ -220,6 +491,13 @@ pub fn set_up_mappings<I, D>(
                     page, frame, err
                 ),
             }
+            // `map_to` refuses the bit, since it means a huge page in the other levels
+            if write_combining {
+                match unsafe { kernel_page_table.update_flags(page, flags | PAGE_ATTRIBUTE_TABLE) } {
+                    Ok(tlb) => tlb.flush(),
+                    Err(err) => panic!("failed to update the flags of page {:?}: {:?}", page, err),
+                }
+            }
         }
         let framebuffer_virt_addr = start_page.start_address();
         Some(framebuffer_virt_addr)
@@ -232,7 +510,7 @@ pub fn set_up_mappings<I, D>(
         let start_page = mapping_addr_page_aligned(
             system_info.ramdisk_len,
             &mut used_entries,
-            "ramdisk start",
+            VirtualRegionKind::Ramdisk,
         );
         let physical_address = PhysAddr::new(ramdisk_address);
         let ramdisk_physical_start_page: PhysFrame<Size4KiB> =
@@ -259,34 +537,35 @@ pub fn set_up_mappings<I, D>(
         None
     };
 
-    let start_frame = PhysFrame::containing_address(PhysAddr::new(0));
-    let max_phys = frame_allocator.max_physical_address();
-    let end_frame: PhysFrame<Size2MiB> = PhysFrame::containing_address(max_phys - 1u64);
-
-    let size = max_phys.as_u64();
-    let alignment = Size2MiB::SIZE;
-    let offset = mapping_addr(size, alignment, &mut used_entries)
-        .expect("start address for physical memory mapping must be 2MiB-page-aligned");
-
-    for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
-        let page = Page::containing_address(offset + frame.start_address().as_u64());
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
-        match unsafe { kernel_page_table.map_to(page, frame, flags, frame_allocator) } {
-            Ok(tlb) => tlb.ignore(),
-            Err(err) => panic!(
-                "failed to map page {:?} to frame {:?}: {:?}",
-                page, frame, err
-            ),
-        };
-    }
+    let max_phys = match config.physical_memory {
+        PhysicalMemoryMapping::Full => Some(frame_allocator.max_physical_address()),
+        PhysicalMemoryMapping::Usable => Some(frame_allocator.max_usable_address()),
+        PhysicalMemoryMapping::Disabled => None,
+    };
+    let offset = max_phys.map(|max_phys| {
+        if supports_1gib_pages() {
+            map_physical_memory::<Size1GiB, _>(max_phys, kernel_page_table, frame_allocator, &mut used_entries)
+        } else {
+            map_physical_memory::<Size2MiB, _>(max_phys, kernel_page_table, frame_allocator, &mut used_entries)
+        }
+    });
 
     Mappings {
         framebuffer: framebuffer_virt_addr,
+        framebuffer_caching: if write_combining {
+            CachingMode::WriteCombining
+        } else {
+            CachingMode::Default
+        },
         entry_point,
+        runtime_services_mapped,
         stack_top: stack_end_addr.align_down(16u8),
+        ap_trampoline,
+        la57_switch,
         used_entries,
-        physical_memory_offset: Some (offset),
+        physical_memory_offset: offset,
         tls_template,
+        tls_block,
 
         kernel_slice_start,
         kernel_slice_len,
@@ -306,21 +585,32 @@ pub fn create_boot_info<I, D>(
         I: ExactSizeIterator<Item=D> + Clone,
         D: LegacyMemoryRegion,
 {
-    let (boot_info, memory_regions) = {
+    const BOOTLOADER_NAME: &str = concat!("initium ", env!("CARGO_PKG_VERSION"));
+
+    let (boot_info, memory_regions, virtual_regions, extensions) = {
         let boot_info_layout = Layout::new::<BootInfo>();
         let regions = frame_allocator.len() + 4; // up to 4 regions might be split into used/unused
         let memory_regions_layout = Layout::array::<MemoryRegion>(regions).unwrap();
         let (combined, memory_regions_offset) =
             boot_info_layout.extend(memory_regions_layout).unwrap();
+        let virtual_region_count = mappings.used_entries.len() + 1; // the boot info itself
+        let virtual_regions_layout = Layout::array::<VirtualRegion>(virtual_region_count).unwrap();
+        let (combined, virtual_regions_offset) = combined.extend(virtual_regions_layout).unwrap();
+        let extensions_len = Extensions::record_size(BOOTLOADER_NAME.len());
+        let extensions_layout = Layout::from_size_align(extensions_len, 8).unwrap();
+        let (combined, extensions_offset) = combined.extend(extensions_layout).unwrap();
 
         let boot_info_addr = mapping_addr(
             u64::from_usize(combined.size()),
             u64::from_usize(combined.align()),
             &mut mappings.used_entries,
+            VirtualRegionKind::BootInfo,
         )
             .expect("boot info addr is not properly aligned");
 
         let memory_map_regions_addr = boot_info_addr + memory_regions_offset;
+        let virtual_regions_addr = boot_info_addr + virtual_regions_offset;
+        let extensions_addr = boot_info_addr + extensions_offset;
         let memory_map_regions_end = boot_info_addr + combined.size();
 
         let start_page = Page::containing_address(boot_info_addr);
@@ -353,9 +643,19 @@ pub fn create_boot_info<I, D>(
             unsafe { &mut *boot_info_addr.as_mut_ptr() };
         let memory_regions: &'static mut [MaybeUninit<MemoryRegion>] =
             unsafe { slice::from_raw_parts_mut(memory_map_regions_addr.as_mut_ptr(), regions) };
-        (boot_info, memory_regions)
+        let virtual_regions: &'static mut [MaybeUninit<VirtualRegion>] = unsafe {
+            slice::from_raw_parts_mut(virtual_regions_addr.as_mut_ptr(), virtual_region_count)
+        };
+        let extensions: &'static mut [u8] =
+            unsafe { slice::from_raw_parts_mut(extensions_addr.as_mut_ptr(), extensions_len) };
+        (boot_info, memory_regions, virtual_regions, extensions)
     };
 
+    let mut extensions = ExtensionWriter::new(extensions);
+    assert!(extensions.push(TAG_BOOTLOADER_NAME, BOOTLOADER_NAME.as_bytes()));
+
+    let virtual_regions = mappings.used_entries.write_regions(virtual_regions);
+
     let memory_regions = frame_allocator.construct_memory_map(
         memory_regions,
         mappings.kernel_slice_start,
@@ -363,30 +663,57 @@ pub fn create_boot_info<I, D>(
     );
 
     let boot_info = boot_info.write({
-        let mut info = BootInfo::new(memory_regions.into());
+        let mut info = BootInfo::new(memory_regions.into(), virtual_regions.into());
         info.framebuffer = mappings
             .framebuffer
             .map(|addr| unsafe {
-                Framebuffer::new(
-                    addr.as_u64(),
-                    system_info
-                        .framebuffer
-                        .expect(
-                            "there shouldn't be a mapping for the framebuffer if there is \
-                            no framebuffer",
-                        )
-                        .info,
-                )
+                let mut info = system_info
+                    .framebuffer
+                    .expect(
+                        "there shouldn't be a mapping for the framebuffer if there is \
+                        no framebuffer",
+                    )
+                    .info;
+                info.caching = mappings.framebuffer_caching;
+
+                Framebuffer::new(addr.as_u64(), info)
             })
             .into();
         info.physical_memory_offset = mappings.physical_memory_offset.map(VirtAddr::as_u64).into();
+        info.paging_mode = match page_tables.kernel_level_5_frame {
+            Some(_) => PagingMode::Level5,
+            None => PagingMode::Level4,
+        };
         info.rsdp_address = system_info.rsdp_addr.map(|addr| addr.as_u64()).into();
+        info.smbios_address = system_info.smbios_addr.map(|addr| addr.as_u64()).into();
+        info.smbios3_address = system_info.smbios3_addr.map(|addr| addr.as_u64()).into();
+        info.firmware = system_info.firmware;
+        info.edid = system_info.framebuffer.and_then(|framebuffer| framebuffer.edid).into();
         info.tls_template = mappings.tls_template.into();
+        info.tls_block = mappings.tls_block.into();
         info.ramdisk_address = mappings
             .ramdisk_slice_start
             .map(|addr| addr.as_u64())
             .into();
         info.ramdisk_len = mappings.ramdisk_slice_len;
+        info.smp = system_info
+            .smp
+            .map(|processors| SmpInfo {
+                bsp_apic_id: processors.bsp_apic_id,
+                mailboxes: processors.mailboxes,
+                mailbox_count: processors.count,
+            })
+            .into();
+        info.timings = system_info.timings;
+        info.boot_slot = system_info
+            .boot_slot
+            .map(|slot| BootSlotInfo {
+                runtime_services: if mappings.runtime_services_mapped { slot.runtime_services } else { 0 },
+                ..slot
+            })
+            .into();
+        info.splash_shown = system_info.splash_shown;
+        info.extensions = extensions.finish();
         info
     });
 
@@ -397,11 +724,41 @@ pub fn switch_to_kernel(
     page_tables: PageTables,
     mappings: Mappings,
     boot_info: &'static mut BootInfo,
+    smp: Option<ApplicationProcessors>,
 ) -> ! {
     let PageTables {
         kernel_level_4_frame,
+        kernel_level_5_frame,
         ..
     } = page_tables;
+
+    let root_frame = kernel_level_5_frame.unwrap_or(kernel_level_4_frame);
+
+    if let (Some(processors), Some(trampoline)) = (smp, mappings.ap_trampoline) {
+        let tsc_frequency = boot_info.timings.tsc_frequency;
+        timing::measure(&mut boot_info.timings, BootPhase::StartProcessors, || {
+            smp::start_application_processors(&processors, trampoline, root_frame, tsc_frequency)
+        });
+    }
+
+    if let Some(tls_block) = mappings.tls_block {
+        FsBase::write(VirtAddr::new(tls_block.thread_pointer));
+    }
+
+    boot_info.timings.kernel_entry = timing::timestamp();
+
+    if let (Some(switch), Some(level_5_frame)) = (mappings.la57_switch, kernel_level_5_frame) {
+        unsafe {
+            la57::switch_to_kernel(
+                switch,
+                level_5_frame,
+                mappings.stack_top,
+                mappings.entry_point,
+                boot_info as *const _ as u64,
+            );
+        }
+    }
+
     let addresses = Addresses {
         page_table: kernel_level_4_frame,
         stack_top: mappings.stack_top,
@@ -419,20 +776,26 @@ pub fn load_and_switch_to_kernel<I, D>(
     boot_config: BootConfig,
     mut frame_allocator: LegacyFrameAllocator<I, D>,
     mut page_tables: PageTables,
-    system_info: SystemInfo,
+    mut system_info: SystemInfo,
 ) -> !
     where
         I: ExactSizeIterator<Item=D> + Clone,
         D: LegacyMemoryRegion,
 {
-    let mut mappings = set_up_mappings(
-        kernel,
-        &mut frame_allocator,
-        &mut page_tables,
-        system_info.framebuffer.as_ref(),
-        &system_info,
-    );
+    let mut timings = system_info.timings;
+    let mut mappings = timing::measure(&mut timings, BootPhase::SetUpMappings, || {
+        set_up_mappings(
+            kernel,
+            &mut frame_allocator,
+            &mut page_tables,
+            system_info.framebuffer.as_ref(),
+            &system_info,
+            &boot_config,
+        )
+    });
+    system_info.timings = timings;
 
+    let create_boot_info_start = timing::timestamp();
     let boot_info = create_boot_info(
         &boot_config,
         frame_allocator,
@@ -440,6 +803,9 @@ pub fn load_and_switch_to_kernel<I, D>(
         &mut mappings,
         system_info,
     );
+    boot_info
+        .timings
+        .push(BootPhase::CreateBootInfo, create_boot_info_start, timing::timestamp());
 
-    switch_to_kernel(page_tables, mappings, boot_info);
+    switch_to_kernel(page_tables, mappings, boot_info, system_info.smp);
 }
\ No newline at end of file
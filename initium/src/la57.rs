@@ -0,0 +1,162 @@
+use core::{arch::{asm, global_asm, x86_64::__cpuid_count}, ptr};
+
+use x86_64::{
+    registers::control::{Cr4, Cr4Flags},
+    structures::paging::{
+        FrameAllocator, Mapper, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+
+/// The switch runs with paging disabled, so its code and tables must be addressable in 32 bits.
+const MAX_ADDRESS: u64 = 0x1_0000_0000;
+
+extern "C" {
+    static la57_switch_start: u8;
+    static la57_switch_end: u8;
+    static la57_enter_kernel: u8;
+    static la57_kernel_arguments: u8;
+}
+
+// Enables 5-level paging, which is only possible with paging disabled. Called with the level 5
+// table in `esi`, the 64-bit continuation in `edx` and an argument in `edi`, which is passed on.
+// Uses the 32-bit code segment at 0x18 and the 64-bit code segment at 0x08 of `gdt.rs`.
+global_asm!(
+    ".global la57_switch_start",
+    ".global la57_switch_end",
+    ".global la57_enter_kernel",
+    ".global la57_kernel_arguments",
+    "la57_switch_start:",
+    "cli",
+    "lea ecx, [rip + 3f]",
+    "lea ebp, [rip + 2f]",
+    "push 0x18",
+    "lea rax, [rip + 1f]",
+    "push rax",
+    "retfq",
+    ".code32",
+    "1:",
+    "mov eax, cr0",
+    "and eax, 0x7fffffff",
+    "mov cr0, eax",
+    "mov eax, cr4",
+    "or eax, 0x1000",
+    "mov cr4, eax",
+    "mov cr3, esi",
+    "mov [ecx], ebp",
+    "mov eax, cr0",
+    "or eax, 0x80000000",
+    "mov cr0, eax",
+    "ljmp [ecx]",
+    ".code64",
+    "2:",
+    "mov edi, edi",
+    "mov edx, edx",
+    "jmp rdx",
+    "3:",
+    ".long 0",
+    ".word 0x08",
+    // continuation of the bootstrap processor, `rdi` points to `la57_kernel_arguments`
+    "la57_enter_kernel:",
+    "mov rsp, [rdi]",
+    "mov rax, [rdi + 8]",
+    "mov rdi, [rdi + 16]",
+    "push 0",
+    "jmp rax",
+    ".balign 8",
+    "la57_kernel_arguments:",
+    ".quad 0, 0, 0",
+    "la57_switch_end:",
+);
+
+pub fn is_supported() -> bool {
+    let features = unsafe { __cpuid_count(7, 0) };
+    features.ecx & (1 << 16) != 0
+}
+
+/// Panics if the firmware already runs with 5-level paging, which the bootloader
+/// page table does not handle.
+pub fn check_firmware_paging_mode() {
+    assert!(
+        !Cr4::read().contains(Cr4Flags::L5_PAGING),
+        "firmware runs with 5-level paging, which is not supported"
+    );
+}
+
+fn allocate_low_frame(frame_allocator: &mut impl FrameAllocator<Size4KiB>, kind: &str) -> PhysFrame {
+    let frame = frame_allocator
+        .allocate_frame()
+        .unwrap_or_else(|| panic!("failed to allocate frame for the {kind}"));
+    assert!(
+        frame.start_address().as_u64() < MAX_ADDRESS,
+        "frame for the {kind} must be below 4 GiB"
+    );
+    frame
+}
+
+/// Creates a level 5 table whose first and last entry point to `level_4_frame`.
+pub fn create_level_5_table(
+    level_4_frame: PhysFrame,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> PhysFrame {
+    let frame = allocate_low_frame(frame_allocator, "level 5 table");
+
+    let table = frame.start_address().as_u64() as *mut PageTable;
+    unsafe {
+        table.write(PageTable::new());
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        (*table)[0].set_frame(level_4_frame, flags);
+        (*table)[511].set_frame(level_4_frame, flags);
+    }
+
+    frame
+}
+
+/// Copies the switch into a frame below 4 GiB and identity maps it.
+pub fn map_switch(
+    kernel_page_table: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> PhysAddr {
+    let frame = allocate_low_frame(frame_allocator, "5-level paging switch");
+
+    unsafe {
+        let start = &la57_switch_start as *const u8;
+        let len = &la57_switch_end as *const u8 as usize - start as usize;
+        ptr::copy_nonoverlapping(start, frame.start_address().as_u64() as *mut u8, len);
+    }
+
+    match unsafe { kernel_page_table.identity_map(frame, PageTableFlags::PRESENT, frame_allocator) } {
+        Ok(tlb) => tlb.flush(),
+        Err(err) => panic!("failed to identity map frame {:?}: {:?}", frame, err),
+    }
+
+    frame.start_address()
+}
+
+fn symbol_in_copy(switch: PhysAddr, symbol: &u8) -> u64 {
+    let start = unsafe { &la57_switch_start as *const u8 as u64 };
+    switch.as_u64() + (symbol as *const u8 as u64 - start)
+}
+
+/// Enables 5-level paging with `level_5_frame` and jumps to the kernel.
+pub unsafe fn switch_to_kernel(
+    switch: PhysAddr,
+    level_5_frame: PhysFrame,
+    stack_top: VirtAddr,
+    entry_point: VirtAddr,
+    boot_info: u64,
+) -> ! {
+    let arguments = symbol_in_copy(switch, &la57_kernel_arguments) as *mut u64;
+    arguments.write(stack_top.as_u64());
+    arguments.add(1).write(entry_point.as_u64());
+    arguments.add(2).write(boot_info);
+
+    asm!(
+        "jmp {}",
+        in(reg) switch.as_u64(),
+        in("rsi") level_5_frame.start_address().as_u64(),
+        in("rdx") symbol_in_copy(switch, &la57_enter_kernel),
+        in("rdi") arguments as u64,
+        options(noreturn),
+    );
+}
@@ -2,11 +2,24 @@
 #![no_std]
 #![no_main]
 
-mod memory;
+use initium_core::{entries, kernel, memory};
+
 mod descriptor;
 mod gdt;
-mod entries;
-mod kernel;
+mod edid;
+mod decompress;
+mod verify;
+mod la57;
+mod smp;
+mod timing;
+mod menu;
+mod chainload;
+mod slot;
+mod memtest;
+mod multiboot;
+mod diagnostics;
+mod fs;
+mod splash;
 
 mod initium;
 
@@ -17,23 +30,27 @@ use crate::initium::{load_and_switch_to_kernel, PageTables, RawFramebufferInfo,
 use crate::kernel::Kernel;
 
 use synapse::framebuffer::FramebufferInfo;
-use synapse::boot::BootConfig;
+use synapse::firmware::{FirmwareInfo, FIRMWARE_VENDOR_MAX_LEN};
+use synapse::boot::{BootConfig, BootEntryKind, PagingMode, BOOT_CONFIG_FILE_NAME};
+use synapse::manifest::Manifest;
+use synapse::slot::BootSlot;
+use synapse::timing::{BootPhase, BootTimings};
 
 use core::{
     cell::UnsafeCell,
-    ops::{Deref, DerefMut},
-    ptr, slice,
+    fmt::Write,
+    ops::Deref,
+    slice,
 };
 use uefi::{
     prelude::{entry, Boot, Handle, Status, SystemTable},
     proto::{
-        console::gop::{GraphicsOutput, PixelFormat},
+        console::{
+            gop::{GraphicsOutput, PixelFormat},
+            text::Color,
+        },
         device_path::DevicePath,
         loaded_image::LoadedImage,
-        media::{
-            file::{File, FileAttribute, FileInfo, FileMode},
-            fs::SimpleFileSystem,
-        },
         network::{
             pxe::{BaseCode, DhcpV4Packet},
             IpAddress,
@@ -41,9 +58,9 @@ use uefi::{
         ProtocolPointer,
     },
     table::boot::{
-        AllocateType, MemoryType, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
+        AllocateType, MemoryMap, MemoryType, OpenProtocolAttributes, OpenProtocolParams, ScopedProtocol,
     },
-    CStr16, CStr8,
+    CStr8,
 };
 use uefi::proto::console::gop::Mode;
 use x86_64::{
@@ -149,65 +166,136 @@ fn locate_and_open_protocol<P: ProtocolPointer>(
     Some(opened_handle.unwrap())
 }
 
-fn load_file_from_disk(
-    name: &str,
-    image: Handle,
-    system_table: &SystemTable<Boot>,
-) -> Option<&'static mut [u8]> {
-    let mut file_system_raw = locate_and_open_protocol::<SimpleFileSystem>(image, system_table)?;
-    let file_system = file_system_raw.deref_mut();
+/// Calls `f` with the current UEFI memory map, read into temporarily allocated pages.
+fn with_memory_map<T>(system_table: &SystemTable<Boot>, f: impl FnOnce(&MemoryMap) -> T) -> T {
+    let boot_services = system_table.boot_services();
 
-    let mut root = file_system.open_volume().unwrap();
-    let mut buf = [0u16; 256];
+    // leave room for the descriptors of the buffer allocation itself
+    let size = boot_services.memory_map_size();
+    let len = size.map_size + 8 * size.entry_size;
+    let pages = ((len - 1) / 4096) + 1;
 
-    assert!(name.len() < 256);
+    let buffer_ptr = boot_services
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, pages)
+        .expect("failed to allocate pages for the memory map") as *mut u8;
+    let buffer = unsafe { slice::from_raw_parts_mut(buffer_ptr, pages * 4096) };
 
-    let filename = CStr16::from_str_with_buf(name.trim_end_matches('\0'), &mut buf)
-        .expect("Failed to convert string to utf16");
+    let memory_map = boot_services
+        .memory_map(buffer)
+        .expect("failed to read the memory map");
+    let result = f(&memory_map);
 
-    let file_handle_result = root.open(filename, FileMode::Read, FileAttribute::empty());
+    let _ = boot_services.free_pages(buffer_ptr as u64, pages);
+    result
+}
 
-    let file_handle = match file_handle_result {
-        Err(_) => return None,
-        Ok(handle) => handle,
-    };
+/// Loads a file of a kernel slot from the `kernel_dir` of the boot config, `None` if it does not exist.
+fn load_slot_file(
+    name: &str,
+    image: Handle,
+    system_table: &mut SystemTable<Boot>,
+    config: &BootConfig,
+) -> Option<&'static mut [u8]> {
+    let mut buf = [0; fs::MAX_PATH_LEN];
+    fs::join(config.kernel_dir(), name, &mut buf)
+        .and_then(|path| fs::load_file(path, image, system_table))
+        .unwrap_or_else(|err| verify::refuse_boot(system_table, format_args!("{name}: {err}")))
+}
 
-    let mut file = match file_handle.into_type().unwrap() {
-        uefi::proto::media::file::FileType::Regular(f) => f,
-        uefi::proto::media::file::FileType::Dir(_) => panic!(),
+fn load_manifest(image: Handle, system_table: &mut SystemTable<Boot>, config: &BootConfig, slot: BootSlot) -> Manifest {
+    let Some(data) = load_slot_file(slot.manifest_file_name(), image, system_table, config) else {
+        verify::refuse_boot(system_table, format_args!("the kernel slot has no manifest"));
     };
 
-    let mut buf = [0; 500];
-    let file_info: &mut FileInfo = file.get_info(&mut buf).unwrap();
-    let file_size = usize::try_from(file_info.file_size()).unwrap();
+    let Some(manifest) = Manifest::parse(data) else {
+        verify::refuse_boot(system_table, format_args!("the manifest is malformed"));
+    };
 
-    let file_ptr = system_table
-        .boot_services()
-        .allocate_pages(
-            AllocateType::AnyPages,
-            MemoryType::LOADER_DATA,
-            ((file_size - 1) / 4096) + 1,
-        )
-        .unwrap() as *mut u8;
-    unsafe { ptr::write_bytes(file_ptr, 0, file_size) };
-    let file_slice = unsafe { slice::from_raw_parts_mut(file_ptr, file_size) };
-    file.read(file_slice).unwrap();
+    if let Err(err) = verify::verify_manifest(&manifest) {
+        verify::refuse_boot(system_table, format_args!("{err}"));
+    }
 
-    Some(file_slice)
+    manifest
 }
 
 fn load_kernel(
     image: Handle,
     system_table: &mut SystemTable<Boot>,
-) -> Option<Kernel<'static>> {
-    Some(Kernel::parse(load_file_from_disk("kernel-x86_64\0", image, system_table)?))
+    config: &BootConfig,
+    manifest: &Manifest,
+    slot: BootSlot,
+) -> Kernel<'static> {
+    let kernel = load_slot_file(slot.kernel_file_name(), image, system_table, config)
+        .unwrap_or_else(|| verify::refuse_boot(system_table, format_args!("kernel: not found")));
+    if let Err(err) = verify::verify_hash(kernel, &manifest.kernel_hash) {
+        verify::refuse_boot(system_table, format_args!("kernel: {err}"));
+    }
+
+    let kernel = decompress::decompress_file(kernel, system_table)
+        .unwrap_or_else(|err| verify::refuse_boot(system_table, format_args!("kernel: {err}")));
+
+    Kernel::parse(kernel).unwrap_or_else(|err| verify::refuse_boot(system_table, format_args!("kernel: {err}")))
 }
 
 fn load_ramdisk(
     image: Handle,
     system_table: &mut SystemTable<Boot>,
+    config: &BootConfig,
+    manifest: &Manifest,
+    slot: BootSlot,
 ) -> Option<&'static mut [u8]> {
-    load_file_from_disk("ramdisk\0", image, system_table)
+    let ramdisk = load_slot_file(slot.ramdisk_file_name(), image, system_table, config);
+
+    let ramdisk = match (ramdisk, &manifest.ramdisk_hash) {
+        (Some(ramdisk), Some(hash)) => {
+            if let Err(err) = verify::verify_hash(ramdisk, hash) {
+                verify::refuse_boot(system_table, format_args!("ramdisk: {err}"));
+            }
+            ramdisk
+        }
+        (None, None) => return None,
+        (Some(_), None) => {
+            verify::refuse_boot(system_table, format_args!("ramdisk: not listed in the manifest"))
+        }
+        (None, Some(_)) => {
+            verify::refuse_boot(system_table, format_args!("ramdisk: listed in the manifest but missing"))
+        }
+    };
+
+    Some(
+        decompress::decompress_file(ramdisk, system_table)
+            .unwrap_or_else(|err| verify::refuse_boot(system_table, format_args!("ramdisk: {err}"))),
+    )
+}
+
+/// Draws the splash image of the boot config, a missing or unsupported image only prints a warning.
+fn load_splash(
+    image: Handle,
+    system_table: &mut SystemTable<Boot>,
+    config: &BootConfig,
+    framebuffer: &RawFramebufferInfo,
+) -> Option<splash::Splash> {
+    let path = config.splash()?;
+    let data = match fs::load_file(path, image, system_table) {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            let _ = writeln!(system_table.stdout(), "splash image `{path}` not found");
+            return None;
+        }
+        Err(err) => {
+            let _ = writeln!(system_table.stdout(), "failed to load the splash image: {err}");
+            return None;
+        }
+    };
+
+    let splash = splash::Splash::show(framebuffer, data);
+    if let Err(err) = &splash {
+        let _ = writeln!(system_table.stdout(), "failed to draw the splash image: {err}");
+    }
+
+    fs::free_file(data, system_table);
+
+    splash.ok()
 }
 
 fn load_framebuffer(
@@ -283,19 +371,72 @@ fn load_framebuffer(
         },
         bytes_per_pixel: 4,
         stride: mode_info.stride(),
+        caching: synapse::framebuffer::CachingMode::Default,
     };
 
     Some(RawFramebufferInfo {
         addr: PhysAddr::new(framebuffer.as_mut_ptr() as u64),
         info,
+        edid: edid::load_edid(image_handle, gop_handle, system_table),
     })
 }
 
+/// Reads the boot config, a missing file gives the defaults. So does a broken one, after a
+/// warning, so that a typo does not make the machine unbootable.
+fn load_boot_config(image: Handle, system_table: &mut SystemTable<Boot>) -> BootConfig {
+    let config = match fs::load_file(BOOT_CONFIG_FILE_NAME, image, system_table) {
+        Ok(Some(data)) => {
+            let config = core::str::from_utf8(data)
+                .map_err(|_| "the file is not valid UTF-8")
+                .and_then(BootConfig::parse);
+            fs::free_file(data, system_table);
+            config
+        }
+        Ok(None) => return BootConfig::default(),
+        Err(err) => Err(err),
+    };
+
+    config.unwrap_or_else(|err| {
+        let stdout = system_table.stdout();
+        let _ = stdout.set_color(Color::Yellow, Color::Black);
+        let _ = writeln!(stdout, "invalid boot config `{BOOT_CONFIG_FILE_NAME}`: {err}");
+        let _ = writeln!(stdout, "using the default configuration");
+        let _ = stdout.set_color(Color::LightGray, Color::Black);
+        system_table.boot_services().stall(3_000_000);
+
+        BootConfig::default()
+    })
+}
+
+fn load_firmware_info(system_table: &SystemTable<Boot>) -> FirmwareInfo {
+    let mut firmware = FirmwareInfo::empty();
+
+    let vendor = system_table.firmware_vendor().to_u16_slice();
+    for c in char::decode_utf16(vendor.iter().copied()) {
+        let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+        if firmware.vendor_len + c.len_utf8() > FIRMWARE_VENDOR_MAX_LEN {
+            break;
+        }
+
+        c.encode_utf8(&mut firmware.vendor[firmware.vendor_len..]);
+        firmware.vendor_len += c.len_utf8();
+    }
+
+    firmware.revision = system_table.firmware_revision();
+    firmware.uefi_major = system_table.uefi_revision().major();
+    firmware.uefi_minor = system_table.uefi_revision().minor();
+
+    firmware
+}
+
 fn create_page_tables(
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    paging_mode: PagingMode,
 ) -> PageTables {
     let phys_offset = VirtAddr::new(0);
 
+    la57::check_firmware_paging_mode();
+
     let bootloader_page_table = {
         let old_table = {
             let frame = x86_64::registers::control::Cr3::read().0;
@@ -342,10 +483,16 @@ fn create_page_tables(
         )
     };
 
+    let kernel_level_5_frame = match paging_mode {
+        PagingMode::Level4 => None,
+        PagingMode::Level5 => Some(la57::create_level_5_table(kernel_level_4_frame, frame_allocator)),
+    };
+
     initium::PageTables {
         bootloader: bootloader_page_table,
         kernel: kernel_page_table,
         kernel_level_4_frame,
+        kernel_level_5_frame,
     }
 }
 
@@ -355,34 +502,100 @@ fn efi_main(image: Handle, system_table: SystemTable<Boot>) -> Status {
 }
 
 fn main_inner(image: Handle, mut system_table: SystemTable<Boot>) -> Status {
+    let mut timings = BootTimings::new(timing::timestamp());
+
     unsafe {
         *SYSTEM_TABLE.get() = Some(system_table.unsafe_clone());
     }
 
-    let mut kernel = load_kernel(image, &mut system_table);
-    let kernel = kernel.expect("Failed to load kernel");
+    timing::calibrate_tsc(&mut timings, &system_table);
+
+    let config = timing::measure(&mut timings, BootPhase::BootMenu, || {
+        let config = load_boot_config(image, &mut system_table);
+        while let Some(entry) = menu::select_entry(&mut system_table, &config) {
+            match entry.kind {
+                BootEntryKind::EfiApplication => {
+                    if let Err(err) = chainload::start_efi_application(image, &system_table, entry) {
+                        let stdout = system_table.stdout();
+                        let _ = writeln!(stdout, "failed to start `{}`: {err}", entry.name());
+                        system_table.boot_services().stall(3_000_000);
+                    }
+                }
+                BootEntryKind::MemoryTest => memtest::run(&mut system_table, config.memtest_mark_bad),
+                BootEntryKind::Diagnostics => diagnostics::run(image, &mut system_table),
+                BootEntryKind::Multiboot2 => {
+                    if let Err(err) = multiboot::boot(image, &system_table, &config, entry) {
+                        let stdout = system_table.stdout();
+                        let _ = writeln!(stdout, "failed to boot `{}`: {err}", entry.name());
+                        system_table.boot_services().stall(3_000_000);
+                    }
+                }
+            }
+        }
+
+        config
+    });
+
+    let boot_slot = (config.boot_attempts > 0)
+        .then(|| slot::select_slot(image, &system_table, &config));
+    let slot = boot_slot.map_or(BootSlot::A, |info| info.slot);
+
+    // the splash is drawn before anything else is loaded
+    let framebuffer = timing::measure(&mut timings, BootPhase::LoadFramebuffer, || {
+        load_framebuffer(image, &system_table, &config)
+    });
+    let mut splash = framebuffer
+        .as_ref()
+        .and_then(|framebuffer| load_splash(image, &mut system_table, &config, framebuffer));
+
+    let manifest = load_manifest(image, &mut system_table, &config, slot);
+    if let Some(splash) = splash.as_mut() {
+        splash.set_progress(10);
+    }
+
+    let kernel = timing::measure(&mut timings, BootPhase::LoadKernel, || {
+        load_kernel(image, &mut system_table, &config, &manifest, slot)
+    });
+    if let Some(splash) = splash.as_mut() {
+        splash.set_progress(50);
+    }
 
-    let config = BootConfig {
-        framebuffer_width: 1280,
-        framebuffer_height: 720,
+    let paging_mode = match config.paging_mode {
+        PagingMode::Level5 if la57::is_supported() => PagingMode::Level5,
+        _ => PagingMode::Level4,
     };
 
-    let framebuffer = load_framebuffer(image, &system_table, &config);
+    let firmware = load_firmware_info(&system_table);
 
     unsafe {
         *SYSTEM_TABLE.get() = None;
     }
 
-    let ramdisk = load_ramdisk(image, &mut system_table);
+    let ramdisk = timing::measure(&mut timings, BootPhase::LoadRamdisk, || {
+        load_ramdisk(image, &mut system_table, &config, &manifest, slot)
+    });
+    if let Some(splash) = splash.as_mut() {
+        splash.set_progress(85);
+    }
+    let smp = timing::measure(&mut timings, BootPhase::FindProcessors, || {
+        smp::find_application_processors(image, &system_table)
+    });
+    if let Some(splash) = splash.as_mut() {
+        splash.set_progress(100);
+    }
 
-    let (system_table, mut memory_map) = system_table.exit_boot_services();
+    let (system_table, mut memory_map) = timing::measure(&mut timings, BootPhase::ExitBootServices, || {
+        system_table.exit_boot_services()
+    });
 
     memory_map.sort();
 
     let mut frame_allocator =
         LegacyFrameAllocator::new(memory_map.entries().copied().map(UefiMemoryDescriptor));
 
-    let page_tables = create_page_tables(&mut frame_allocator);
+    let page_tables = timing::measure(&mut timings, BootPhase::CreatePageTables, || {
+        create_page_tables(&mut frame_allocator, paging_mode)
+    });
     let mut ramdisk_len = 0u64;
     let ramdisk_addr = if let Some(rd) = ramdisk {
         ramdisk_len = rd.len() as u64;
@@ -402,8 +615,32 @@ fn main_inner(image: Handle, mut system_table: SystemTable<Boot>) -> Status {
 
             rsdp.map(|entry| PhysAddr::new(entry.address as u64))
         },
+        smbios_addr: {
+            use uefi::table::cfg;
+
+            let mut config_entries = system_table.config_table().iter();
+            let smbios = config_entries.find(|entry| matches!(entry.guid, cfg::SMBIOS_GUID));
+
+            smbios.map(|entry| PhysAddr::new(entry.address as u64))
+        },
+        smbios3_addr: {
+            use uefi::table::cfg;
+
+            let mut config_entries = system_table.config_table().iter();
+            let smbios3 = config_entries.find(|entry| matches!(entry.guid, cfg::SMBIOS3_GUID));
+
+            smbios3.map(|entry| PhysAddr::new(entry.address as u64))
+        },
+        firmware,
         ramdisk_addr,
         ramdisk_len,
+        smp,
+        timings,
+        boot_slot: boot_slot.map(|mut info| {
+            info.runtime_services = unsafe { system_table.runtime_services() } as *const _ as u64;
+            info
+        }),
+        splash_shown: splash.is_some(),
     };
 
     load_and_switch_to_kernel(kernel, config, frame_allocator, page_tables, system_info)
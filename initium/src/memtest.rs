@@ -0,0 +1,209 @@
+use core::{
+    fmt::Write,
+    ptr::{read_volatile, write_volatile},
+    slice,
+};
+
+use uefi::{
+    prelude::{Boot, SystemTable},
+    table::boot::{AllocateType, MemoryType},
+};
+
+use crate::{menu, timing, with_memory_map};
+
+/// Memory type of pages that failed the memory test, they stay allocated so neither
+/// initium nor the kernel use them.
+pub const BAD_MEMORY_TYPE: MemoryType = MemoryType::custom(0x8000_0002);
+
+const MAX_REGIONS: usize = 512;
+const MAX_BAD_PAGES: usize = 256;
+/// Failing addresses that are printed, further errors are only counted.
+const MAX_REPORTED_ERRORS: u64 = 8;
+
+struct Errors {
+    count: u64,
+    bad_pages: [u64; MAX_BAD_PAGES],
+    bad_pages_len: usize,
+}
+
+impl Errors {
+    fn record(&mut self, system_table: &mut SystemTable<Boot>, address: u64, expected: u64, actual: u64) {
+        if self.count < MAX_REPORTED_ERRORS {
+            let _ = writeln!(
+                system_table.stdout(),
+                "  bad address {address:#x}: expected {expected:#018x}, read {actual:#018x}"
+            );
+        }
+        self.count += 1;
+
+        let page = address & !0xfff;
+        if self.bad_pages[..self.bad_pages_len].contains(&page) {
+            return;
+        }
+        if let Some(slot) = self.bad_pages.get_mut(self.bad_pages_len) {
+            *slot = page;
+            self.bad_pages_len += 1;
+        }
+    }
+}
+
+struct Tester<'a> {
+    system_table: &'a mut SystemTable<Boot>,
+    words: &'a mut [u64],
+    errors: &'a mut Errors,
+}
+
+impl Tester<'_> {
+    fn write(&mut self, index: usize, value: u64) {
+        unsafe { write_volatile(&mut self.words[index], value) };
+    }
+
+    fn check(&mut self, index: usize, expected: u64) {
+        let actual = unsafe { read_volatile(&self.words[index]) };
+        if actual != expected {
+            let address = &self.words[index] as *const u64 as u64;
+            self.errors.record(self.system_table, address, expected, actual);
+        }
+    }
+
+    /// Every word holds a single set bit, which moves by one position from word to word.
+    fn walking_ones(&mut self) {
+        for i in 0..self.words.len() {
+            self.write(i, 1 << (i % 64));
+        }
+        for i in 0..self.words.len() {
+            self.check(i, 1 << (i % 64));
+        }
+    }
+
+    /// Checks and inverts the pattern word by word, first ascending and then descending.
+    fn moving_inversions(&mut self, pattern: u64) {
+        for i in 0..self.words.len() {
+            self.write(i, pattern);
+        }
+        for i in 0..self.words.len() {
+            self.check(i, pattern);
+            self.write(i, !pattern);
+        }
+        for i in (0..self.words.len()).rev() {
+            self.check(i, !pattern);
+            self.write(i, pattern);
+        }
+    }
+
+    fn random(&mut self, seed: u64) {
+        let mut state = seed;
+        for i in 0..self.words.len() {
+            state = xorshift(state);
+            self.write(i, state);
+        }
+
+        let mut state = seed;
+        for i in 0..self.words.len() {
+            state = xorshift(state);
+            self.check(i, state);
+        }
+    }
+}
+
+fn xorshift(mut state: u64) -> u64 {
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    state
+}
+
+/// Tests all `CONVENTIONAL` memory with walking ones, moving inversions and random patterns
+/// and reports failing addresses.
+///
+/// With `mark_bad`, failing pages are allocated as `BAD_MEMORY_TYPE` and show up as
+/// `MemoryRegionKind::Bad` in the memory regions of the kernel.
+pub fn run(system_table: &mut SystemTable<Boot>, mark_bad: bool) {
+    let _ = system_table.stdout().clear();
+    let _ = writeln!(system_table.stdout(), "memory test\n");
+
+    // the memory map changes with every allocation, so copy the regions first
+    let mut regions = [(0u64, 0usize); MAX_REGIONS];
+    let mut regions_len = 0;
+    let mut skipped = 0;
+    with_memory_map(system_table, |memory_map| {
+        for descriptor in memory_map.entries().filter(|d| d.ty == MemoryType::CONVENTIONAL) {
+            match regions.get_mut(regions_len) {
+                Some(slot) => {
+                    *slot = (descriptor.phys_start, descriptor.page_count as usize);
+                    regions_len += 1;
+                }
+                None => skipped += 1,
+            }
+        }
+    });
+
+    let mut errors = Errors {
+        count: 0,
+        bad_pages: [0; MAX_BAD_PAGES],
+        bad_pages_len: 0,
+    };
+
+    for &(mut start, mut pages) in &regions[..regions_len] {
+        // page 0 is not addressable from rust
+        if start == 0 {
+            start += 4096;
+            pages -= 1;
+        }
+        if pages == 0 {
+            continue;
+        }
+
+        let _ = writeln!(
+            system_table.stdout(),
+            "{:#014x}-{:#014x} ({} KiB)",
+            start,
+            start + pages as u64 * 4096,
+            pages * 4
+        );
+
+        // the region has to be ours while it is overwritten
+        let allocated = system_table
+            .boot_services()
+            .allocate_pages(AllocateType::Address(start), MemoryType::LOADER_DATA, pages);
+        if allocated.is_err() {
+            let _ = writeln!(system_table.stdout(), "  skipped, the region is no longer free");
+            continue;
+        }
+
+        let words = unsafe { slice::from_raw_parts_mut(start as *mut u64, pages * 512) };
+        let mut tester = Tester {
+            system_table: &mut *system_table,
+            words,
+            errors: &mut errors,
+        };
+        tester.walking_ones();
+        tester.moving_inversions(0);
+        tester.moving_inversions(0x5555_5555_5555_5555);
+        tester.random(timing::timestamp() | 1);
+
+        let _ = system_table.boot_services().free_pages(start, pages);
+    }
+
+    if skipped > 0 {
+        let _ = writeln!(system_table.stdout(), "{skipped} regions were not tested");
+    }
+
+    if mark_bad {
+        for &page in &errors.bad_pages[..errors.bad_pages_len] {
+            let _ = system_table
+                .boot_services()
+                .allocate_pages(AllocateType::Address(page), BAD_MEMORY_TYPE, 1);
+        }
+    }
+
+    let _ = writeln!(
+        system_table.stdout(),
+        "\n{} errors in {} pages{}",
+        errors.count,
+        errors.bad_pages_len,
+        if mark_bad && errors.bad_pages_len > 0 { ", marked as unusable" } else { "" }
+    );
+    let _ = writeln!(system_table.stdout(), "press any key to return to the menu");
+    menu::wait_for_key(system_table, false);
+}
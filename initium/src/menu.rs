@@ -0,0 +1,116 @@
+use core::fmt::Write;
+
+use synapse::boot::{BootConfig, BootEntry};
+use uefi::{
+    prelude::{Boot, SystemTable},
+    proto::console::text::{Color, Key, ScanCode},
+    table::boot::{EventType, TimerTrigger, Tpl},
+};
+
+/// Timer period of the countdown in units of 100ns.
+const ONE_SECOND: u64 = 10_000_000;
+
+/// Shows the boot menu and returns the selected entry, `None` selects the kernel.
+///
+/// The menu is skipped if the configuration has no entries besides the kernel.
+pub fn select_entry<'a>(
+    system_table: &mut SystemTable<Boot>,
+    config: &'a BootConfig,
+) -> Option<&'a BootEntry> {
+    let entries = config.entries();
+    if entries.is_empty() {
+        return None;
+    }
+
+    let mut selected = 0;
+    let mut countdown = (config.menu_timeout > 0).then_some(config.menu_timeout);
+
+    loop {
+        draw(system_table, entries, selected, countdown);
+
+        match wait_for_key(system_table, countdown.is_some()) {
+            None => match countdown {
+                Some(1) => return None,
+                Some(seconds) => countdown = Some(seconds - 1),
+                None => {}
+            },
+            Some(key) => {
+                countdown = None;
+                match key {
+                    Key::Special(ScanCode::UP) => selected = selected.saturating_sub(1),
+                    Key::Special(ScanCode::DOWN) => selected = (selected + 1).min(entries.len()),
+                    Key::Printable(c) if char::from(c) == '\r' => break,
+                    Key::Printable(c) => match char::from(c).to_digit(10) {
+                        Some(digit @ 1..) if digit as usize <= entries.len() + 1 => {
+                            selected = digit as usize - 1;
+                            break;
+                        }
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let _ = system_table.stdout().clear();
+    selected.checked_sub(1).map(|index| &entries[index])
+}
+
+fn draw(
+    system_table: &mut SystemTable<Boot>,
+    entries: &[BootEntry],
+    selected: usize,
+    countdown: Option<u64>,
+) {
+    let stdout = system_table.stdout();
+    let _ = stdout.clear();
+    let _ = writeln!(stdout, "boot menu\n");
+
+    let names = core::iter::once("kernel").chain(entries.iter().map(BootEntry::name));
+    for (index, name) in names.enumerate() {
+        if index == selected {
+            let _ = stdout.set_color(Color::Black, Color::LightGray);
+        }
+        let _ = writeln!(stdout, " {}. {} ", index + 1, name);
+        let _ = stdout.set_color(Color::LightGray, Color::Black);
+    }
+
+    let _ = writeln!(stdout);
+    match countdown {
+        Some(seconds) => {
+            let _ = writeln!(stdout, "starting the kernel in {seconds}s, press any key to stop");
+        }
+        None => {
+            let _ = writeln!(stdout, "select an entry with the arrow keys and enter");
+        }
+    }
+}
+
+/// Waits for a key press, or for one second if `tick` is set.
+///
+/// Returns `None` if the second passed without a key press.
+pub fn wait_for_key(system_table: &mut SystemTable<Boot>, tick: bool) -> Option<Key> {
+    let key_event = unsafe { system_table.stdin().wait_for_key_event().unsafe_clone() };
+    let boot_services = system_table.boot_services();
+
+    if tick {
+        let timer = unsafe { boot_services.create_event(EventType::TIMER, Tpl::APPLICATION, None, None) }
+            .expect("failed to create the boot menu timer");
+        boot_services
+            .set_timer(&timer, TimerTrigger::Relative(ONE_SECOND))
+            .expect("failed to start the boot menu timer");
+
+        let mut events = [key_event, unsafe { timer.unsafe_clone() }];
+        let index = boot_services.wait_for_event(&mut events).unwrap_or(1);
+        let _ = boot_services.close_event(timer);
+
+        if index != 0 {
+            return None;
+        }
+    } else {
+        let _ = boot_services.wait_for_event(&mut [key_event]);
+    }
+
+    system_table.stdin().read_key().ok().flatten()
+}
@@ -0,0 +1,578 @@
+use core::{arch::{asm, global_asm}, convert::Infallible, ptr, slice};
+
+use synapse::boot::{BootConfig, BootEntry, MAX_BOOT_MODULES};
+use synapse::framebuffer::PixelFormat;
+use uefi::{
+    prelude::{Boot, Handle, SystemTable},
+    table::boot::{AllocateType, MemoryMap, MemoryType},
+};
+use xmas_elf::{program::Type, ElfFile};
+
+use initium_core::kernel;
+
+use crate::memtest::BAD_MEMORY_TYPE;
+use crate::{fs, initium::RawFramebufferInfo, load_framebuffer, verify};
+
+const HEADER_MAGIC: u32 = 0xe852_50d6;
+/// The header must be within this many bytes from the start of the kernel file.
+const HEADER_SEARCH_LEN: usize = 32768;
+/// Architecture field of the header for 32-bit protected mode.
+const ARCHITECTURE_I386: u32 = 0;
+
+/// The kernel is entered with paging disabled, so everything it receives must be below 4 GiB.
+const MAX_ADDRESS: u64 = 0x1_0000_0000;
+const INFO_PAGES: usize = 8;
+/// The kernel, the modules, the information structure and the trampoline.
+const MAX_ALLOCATIONS: usize = MAX_BOOT_MODULES + 3;
+
+const TAG_END: u32 = 0;
+const TAG_CMDLINE: u32 = 1;
+const TAG_BOOTLOADER_NAME: u32 = 2;
+const TAG_MODULE: u32 = 3;
+const TAG_BASIC_MEMINFO: u32 = 4;
+const TAG_MMAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+const TAG_ACPI_OLD: u32 = 14;
+const TAG_ACPI_NEW: u32 = 15;
+const TAG_LOAD_BASE_ADDR: u32 = 21;
+
+/// Information tags that initium provides, the kernel may require any of them.
+const PROVIDED_TAGS: [u32; 9] = [
+    TAG_CMDLINE,
+    TAG_BOOTLOADER_NAME,
+    TAG_MODULE,
+    TAG_BASIC_MEMINFO,
+    TAG_MMAP,
+    TAG_FRAMEBUFFER,
+    TAG_ACPI_OLD,
+    TAG_ACPI_NEW,
+    TAG_LOAD_BASE_ADDR,
+];
+
+extern "C" {
+    static multiboot_trampoline_start: u8;
+    static multiboot_trampoline_end: u8;
+    static multiboot_trampoline_gdtr: u8;
+    static multiboot_trampoline_gdt: u8;
+}
+
+// Leaves long mode and enters the kernel in the state the Multiboot2 specification requires.
+// Called with the entry point in `edi` and the information structure in `esi`, from a copy below
+// 4 GiB whose `multiboot_trampoline_gdtr` points to its own GDT.
+global_asm!(
+    ".global multiboot_trampoline_start",
+    ".global multiboot_trampoline_end",
+    ".global multiboot_trampoline_gdtr",
+    ".global multiboot_trampoline_gdt",
+    "multiboot_trampoline_start:",
+    "cli",
+    "lgdt [rip + multiboot_trampoline_gdtr]",
+    "push 0x08",
+    "lea rax, [rip + 1f]",
+    "push rax",
+    "retfq",
+    ".code32",
+    "1:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov fs, ax",
+    "mov gs, ax",
+    "mov ss, ax",
+    // disabling paging in compatibility mode leaves long mode, then clear EFER.LME and CR4.PAE
+    "mov eax, cr0",
+    "and eax, 0x7fffffff",
+    "mov cr0, eax",
+    "mov ecx, 0xc0000080",
+    "rdmsr",
+    "and eax, 0xfffffeff",
+    "wrmsr",
+    "mov eax, cr4",
+    "and eax, 0xffffffdf",
+    "mov cr4, eax",
+    // the bootloader magic
+    "mov eax, 0x36d76289",
+    "mov ebx, esi",
+    "jmp edi",
+    ".code64",
+    ".balign 8",
+    "multiboot_trampoline_gdtr:",
+    ".word 23",
+    ".quad 0",
+    ".balign 8",
+    "multiboot_trampoline_gdt:",
+    ".quad 0",
+    ".quad 0x00cf9a000000ffff",
+    ".quad 0x00cf92000000ffff",
+    "multiboot_trampoline_end:",
+);
+
+/// Load addresses of an a.out kludge kernel, from the address tag of the header.
+struct AddressTag {
+    header_addr: u32,
+    load_addr: u32,
+    load_end_addr: u32,
+    bss_end_addr: u32,
+}
+
+struct Header {
+    /// Offset of the header in the kernel file.
+    offset: usize,
+    address: Option<AddressTag>,
+    entry: Option<u32>,
+    /// Preferred framebuffer resolution, `0` for no preference.
+    framebuffer: Option<(u32, u32)>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().unwrap()))
+}
+
+fn parse_header(kernel: &[u8]) -> Result<Header, &'static str> {
+    let offset = (0..kernel.len().min(HEADER_SEARCH_LEN))
+        .step_by(8)
+        .find(|&offset| read_u32(kernel, offset) == Some(HEADER_MAGIC))
+        .ok_or("the kernel has no Multiboot2 header")?;
+
+    let truncated = "the Multiboot2 header is truncated";
+    let architecture = read_u32(kernel, offset + 4).ok_or(truncated)?;
+    let header_len = read_u32(kernel, offset + 8).ok_or(truncated)? as usize;
+    let checksum = read_u32(kernel, offset + 12).ok_or(truncated)?;
+    if HEADER_MAGIC
+        .wrapping_add(architecture)
+        .wrapping_add(header_len as u32)
+        .wrapping_add(checksum)
+        != 0
+    {
+        return Err("the Multiboot2 header checksum is wrong");
+    }
+    if architecture != ARCHITECTURE_I386 {
+        return Err("the kernel is not built for i386");
+    }
+    let header_end = offset.checked_add(header_len).filter(|&end| end <= kernel.len()).ok_or(truncated)?;
+
+    let mut header = Header {
+        offset,
+        address: None,
+        entry: None,
+        framebuffer: None,
+    };
+
+    let mut tag = offset + 16;
+    loop {
+        let ty = read_u16(kernel, tag).filter(|_| tag + 8 <= header_end).ok_or(truncated)?;
+        let optional = read_u16(kernel, tag + 2).ok_or(truncated)? & 1 != 0;
+        let size = read_u32(kernel, tag + 4).ok_or(truncated)? as usize;
+        if size < 8 || tag + size > header_end {
+            return Err("the Multiboot2 header has a malformed tag");
+        }
+        let field = |index: usize| read_u32(kernel, tag + 8 + index * 4).filter(|_| 12 + index * 4 <= size);
+
+        match ty {
+            0 => break,
+            // information request
+            1 => {
+                for index in 0..(size - 8) / 4 {
+                    let requested = field(index).ok_or(truncated)?;
+                    if !optional && !PROVIDED_TAGS.contains(&requested) {
+                        return Err("the kernel requires information that initium does not provide");
+                    }
+                }
+            }
+            2 => {
+                header.address = Some(AddressTag {
+                    header_addr: field(0).ok_or(truncated)?,
+                    load_addr: field(1).ok_or(truncated)?,
+                    load_end_addr: field(2).ok_or(truncated)?,
+                    bss_end_addr: field(3).ok_or(truncated)?,
+                })
+            }
+            3 => header.entry = Some(field(0).ok_or(truncated)?),
+            5 => header.framebuffer = Some((field(0).ok_or(truncated)?, field(1).ok_or(truncated)?)),
+            // console flags: there is always a framebuffer and no text console
+            // module alignment: modules are always page aligned
+            // relocatable: the kernel is loaded at its preferred address
+            4 | 6 | 10 => {}
+            _ if optional => {}
+            _ => return Err("the kernel requires an unsupported Multiboot2 header tag"),
+        }
+
+        tag += (size + 7) & !7;
+    }
+
+    Ok(header)
+}
+
+/// Pages allocated for the kernel, freed when dropped so that a failed boot can be retried from
+/// the menu.
+struct Allocations<'a> {
+    system_table: &'a SystemTable<Boot>,
+    pages: [(u64, usize); MAX_ALLOCATIONS],
+    len: usize,
+}
+
+impl<'a> Allocations<'a> {
+    fn new(system_table: &'a SystemTable<Boot>) -> Self {
+        Self {
+            system_table,
+            pages: [(0, 0); MAX_ALLOCATIONS],
+            len: 0,
+        }
+    }
+
+    fn allocate(&mut self, ty: AllocateType, memory_type: MemoryType, pages: usize) -> Result<u64, &'static str> {
+        let slot = self.pages.get_mut(self.len).ok_or("too many allocations for the kernel")?;
+        let start = self
+            .system_table
+            .boot_services()
+            .allocate_pages(ty, memory_type, pages)
+            .map_err(|_| "failed to allocate pages for the kernel")?;
+        *slot = (start, pages);
+        self.len += 1;
+        Ok(start)
+    }
+
+    fn load_module(&mut self, path: &str, image: Handle) -> Result<&'static mut [u8], &'static str> {
+        let slot = self.pages.get_mut(self.len).ok_or("too many allocations for the kernel")?;
+        let data = fs::load_file_below(path, MAX_ADDRESS, image, self.system_table)?.ok_or("module not found")?;
+        *slot = (data.as_ptr() as u64, fs::pages(data.len()));
+        self.len += 1;
+        Ok(data)
+    }
+
+    /// Keeps the pages, for when the boot services are exited.
+    fn keep(mut self) {
+        self.len = 0;
+    }
+}
+
+impl Drop for Allocations<'_> {
+    fn drop(&mut self) {
+        for &(start, pages) in &self.pages[..self.len] {
+            let _ = self.system_table.boot_services().free_pages(start, pages);
+        }
+    }
+}
+
+/// Allocates `start..end` at its physical address and zeroes it.
+fn allocate_at(allocations: &mut Allocations, start: u64, end: u64) -> Result<&'static mut [u8], &'static str> {
+    if start >= end || end > MAX_ADDRESS {
+        return Err("the kernel must be loaded below 4 GiB");
+    }
+
+    let first_page = start & !0xfff;
+    let pages = ((end - first_page - 1) / 4096) + 1;
+    allocations
+        .allocate(AllocateType::Address(first_page), MemoryType::LOADER_DATA, pages as usize)
+        .map_err(|_| "the kernel overlaps memory that is in use")?;
+
+    let memory = unsafe { slice::from_raw_parts_mut(start as *mut u8, (end - start) as usize) };
+    memory.fill(0);
+    Ok(memory)
+}
+
+/// Copies the kernel to its load address and returns its entry point and load base.
+fn load_image(kernel: &[u8], header: &Header, allocations: &mut Allocations) -> Result<(u32, u32), &'static str> {
+    if let Some(address) = &header.address {
+        let entry = header.entry.ok_or("the address tag requires an entry address tag")?;
+
+        let file_start = (header.offset as u64 + u64::from(address.load_addr))
+            .checked_sub(u64::from(address.header_addr))
+            .ok_or("the address tag is malformed")? as usize;
+        let load_end = match address.load_end_addr {
+            0 => u64::from(address.load_addr) + (kernel.len() - file_start.min(kernel.len())) as u64,
+            end => u64::from(end),
+        };
+        let file_len = load_end
+            .checked_sub(u64::from(address.load_addr))
+            .filter(|&len| file_start + len as usize <= kernel.len())
+            .ok_or("the address tag is malformed")? as usize;
+        let bss_end = load_end.max(u64::from(address.bss_end_addr));
+
+        let memory = allocate_at(allocations, u64::from(address.load_addr), bss_end)?;
+        memory[..file_len].copy_from_slice(&kernel[file_start..file_start + file_len]);
+
+        return Ok((entry, address.load_addr));
+    }
+
+    let elf = ElfFile::new(kernel)?;
+    kernel::check_bounds(&elf)?;
+    let segments = || {
+        elf.program_iter()
+            .filter(|segment| matches!(segment.get_type(), Ok(Type::Load)) && segment.mem_size() > 0)
+    };
+
+    let start = segments().map(|segment| segment.physical_addr()).min().ok_or("the kernel has no segments")?;
+    let end = segments()
+        .map(|segment| segment.physical_addr().saturating_add(segment.mem_size()))
+        .max()
+        .unwrap();
+    let memory = allocate_at(allocations, start, end)?;
+
+    for segment in segments() {
+        let offset = (segment.physical_addr() - start) as usize;
+        let data = &kernel[segment.offset() as usize..][..segment.file_size() as usize];
+        memory[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    // the ELF entry point may be virtual, the kernel is entered at its physical address
+    let entry = match header.entry {
+        Some(entry) => u64::from(entry),
+        None => {
+            let entry = elf.header.pt2.entry_point();
+            segments()
+                .find(|segment| (segment.virtual_addr()..segment.virtual_addr().saturating_add(segment.mem_size())).contains(&entry))
+                .map(|segment| segment.physical_addr() + (entry - segment.virtual_addr()))
+                .ok_or("the entry point is outside of the kernel")?
+        }
+    };
+
+    Ok((
+        u32::try_from(entry).map_err(|_| "the entry point must be below 4 GiB")?,
+        start as u32,
+    ))
+}
+
+/// Multiboot2 information structure in a fixed buffer.
+struct Info {
+    buf: &'static mut [u8],
+    len: usize,
+}
+
+impl Info {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        self.buf
+            .get_mut(self.len..self.len + bytes.len())
+            .ok_or("the Multiboot2 information does not fit")?
+            .copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    fn u32(&mut self, value: u32) -> Result<(), &'static str> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn u64(&mut self, value: u64) -> Result<(), &'static str> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// Null-terminated string.
+    fn string(&mut self, s: &str) -> Result<(), &'static str> {
+        self.bytes(s.as_bytes())?;
+        self.bytes(&[0])
+    }
+
+    /// Writes a tag whose content is written by `content`, its size is filled in afterwards.
+    fn tag(&mut self, ty: u32, content: impl FnOnce(&mut Self) -> Result<(), &'static str>) -> Result<(), &'static str> {
+        let start = self.len;
+        self.u32(ty)?;
+        self.u32(0)?;
+        content(self)?;
+
+        let size = (self.len - start) as u32;
+        self.buf[start + 4..start + 8].copy_from_slice(&size.to_le_bytes());
+        while self.len % 8 != 0 {
+            self.bytes(&[0])?;
+        }
+        Ok(())
+    }
+}
+
+fn memory_type(ty: MemoryType) -> u32 {
+    match ty {
+        MemoryType::CONVENTIONAL
+        | MemoryType::LOADER_CODE
+        | MemoryType::LOADER_DATA
+        | MemoryType::BOOT_SERVICES_CODE
+        | MemoryType::BOOT_SERVICES_DATA => 1,
+        MemoryType::ACPI_RECLAIM => 3,
+        MemoryType::ACPI_NON_VOLATILE => 4,
+        MemoryType::UNUSABLE | BAD_MEMORY_TYPE => 5,
+        _ => 2,
+    }
+}
+
+/// Size of the memory map, basic memory information and end tags for a memory map of `regions`
+/// regions.
+fn memory_map_len(regions: usize) -> usize {
+    16 + regions * 24 + 16 + 8
+}
+
+/// Writes the memory map, merging adjacent regions of the same type, and the basic memory
+/// information derived from it.
+fn write_memory_map(info: &mut Info, memory_map: &MemoryMap) -> Result<(), &'static str> {
+    let merged = || {
+        let mut descriptors = memory_map.entries().peekable();
+        core::iter::from_fn(move || {
+            let first = descriptors.next()?;
+            let ty = memory_type(first.ty);
+            let start = first.phys_start;
+            let mut end = start + first.page_count * 4096;
+            while let Some(next) = descriptors.next_if(|next| next.phys_start == end && memory_type(next.ty) == ty) {
+                end += next.page_count * 4096;
+            }
+            Some((start, end, ty))
+        })
+    };
+
+    info.tag(TAG_MMAP, |info| {
+        info.u32(24)?;
+        info.u32(0)?;
+        for (start, end, ty) in merged() {
+            info.u64(start)?;
+            info.u64(end - start)?;
+            info.u32(ty)?;
+            info.u32(0)?;
+        }
+        Ok(())
+    })?;
+
+    let available_from = |address: u64| {
+        merged()
+            .find(|&(start, end, ty)| ty == 1 && (start..end).contains(&address))
+            .map_or(0, |(_, end, _)| end - address)
+    };
+    let lower = available_from(0).min(640 * 1024);
+    let upper = available_from(0x10_0000);
+    info.tag(TAG_BASIC_MEMINFO, |info| {
+        info.u32((lower / 1024) as u32)?;
+        info.u32((upper / 1024).min(u64::from(u32::MAX)) as u32)
+    })
+}
+
+fn write_framebuffer(info: &mut Info, framebuffer: &RawFramebufferInfo) -> Result<(), &'static str> {
+    let fb = framebuffer.info;
+    let (red, blue) = match fb.pixel_format {
+        PixelFormat::Rgb => (0, 16),
+        PixelFormat::Bgr => (16, 0),
+    };
+
+    info.tag(TAG_FRAMEBUFFER, |info| {
+        info.u64(framebuffer.addr.as_u64())?;
+        info.u32((fb.stride * fb.bytes_per_pixel) as u32)?;
+        info.u32(fb.width as u32)?;
+        info.u32(fb.height as u32)?;
+        // bits per pixel, direct RGB color and a reserved field
+        info.bytes(&[(fb.bytes_per_pixel * 8) as u8, 1, 0, 0])?;
+        info.bytes(&[red, 8, 8, 8, blue, 8])
+    })
+}
+
+fn write_acpi(info: &mut Info, system_table: &SystemTable<Boot>) -> Result<(), &'static str> {
+    use uefi::table::cfg;
+
+    for entry in system_table.config_table() {
+        let rsdp = entry.address as *const u8;
+        match entry.guid {
+            cfg::ACPI_GUID => {
+                let rsdp = unsafe { slice::from_raw_parts(rsdp, 20) };
+                info.tag(TAG_ACPI_OLD, |info| info.bytes(rsdp))?;
+            }
+            cfg::ACPI2_GUID => {
+                let len = unsafe { ptr::read_unaligned(rsdp.add(20) as *const u32) };
+                let rsdp = unsafe { slice::from_raw_parts(rsdp, len as usize) };
+                info.tag(TAG_ACPI_NEW, |info| info.bytes(rsdp))?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Loads the Multiboot2 kernel of `entry` with its modules and enters it, only returns on errors
+/// before the boot services were exited.
+pub fn boot(
+    image: Handle,
+    system_table: &SystemTable<Boot>,
+    config: &BootConfig,
+    entry: &BootEntry,
+) -> Result<Infallible, &'static str> {
+    let mut allocations = Allocations::new(system_table);
+
+    let kernel = fs::load_file(entry.path(), image, system_table)?.ok_or("kernel not found")?;
+    let loaded = verify::verify_file(entry.path(), kernel, image, system_table)
+        .and_then(|()| parse_header(kernel))
+        .and_then(|header| Ok((load_image(kernel, &header, &mut allocations)?, header)));
+    fs::free_file(kernel, system_table);
+    let ((entry_point, load_base), header) = loaded?;
+
+    let mut framebuffer_config = *config;
+    if let Some((width, height)) = header.framebuffer.filter(|&(width, height)| width > 0 && height > 0) {
+        framebuffer_config.framebuffer_width = width as usize;
+        framebuffer_config.framebuffer_height = height as usize;
+    }
+    let framebuffer = load_framebuffer(image, system_table, &framebuffer_config);
+
+    let info_ptr = allocations
+        .allocate(AllocateType::MaxAddress(MAX_ADDRESS - 1), MemoryType::LOADER_DATA, INFO_PAGES)
+        .map_err(|_| "failed to allocate pages for the Multiboot2 information")?;
+    let mut info = Info {
+        buf: unsafe { slice::from_raw_parts_mut(info_ptr as *mut u8, INFO_PAGES * 4096) },
+        len: 0,
+    };
+
+    // total size and a reserved field, filled in last
+    info.u64(0)?;
+    info.tag(TAG_CMDLINE, |info| info.string(entry.cmdline()))?;
+    info.tag(TAG_BOOTLOADER_NAME, |info| info.string("initium"))?;
+    info.tag(TAG_LOAD_BASE_ADDR, |info| info.u32(load_base))?;
+    for module in config.modules(entry) {
+        let data = allocations.load_module(module.path(), image)?;
+        verify::verify_file(module.path(), data, image, system_table)?;
+        let start = data.as_ptr() as u64;
+        info.tag(TAG_MODULE, |info| {
+            info.u32(start as u32)?;
+            info.u32((start + data.len() as u64) as u32)?;
+            info.string(module.string())
+        })?;
+    }
+    if let Some(framebuffer) = &framebuffer {
+        write_framebuffer(&mut info, framebuffer)?;
+    }
+    write_acpi(&mut info, system_table)?;
+
+    let trampoline = allocations
+        .allocate(AllocateType::MaxAddress(MAX_ADDRESS - 1), MemoryType::LOADER_CODE, 1)
+        .map_err(|_| "failed to allocate a page for the Multiboot2 trampoline")?;
+    unsafe {
+        let start = &multiboot_trampoline_start as *const u8;
+        let len = &multiboot_trampoline_end as *const u8 as usize - start as usize;
+        ptr::copy_nonoverlapping(start, trampoline as *mut u8, len);
+
+        let in_copy = |symbol: &u8| trampoline + (symbol as *const u8 as u64 - start as u64);
+        let gdt_base = (in_copy(&multiboot_trampoline_gdtr) + 2) as *mut u64;
+        gdt_base.write_unaligned(in_copy(&multiboot_trampoline_gdt));
+    }
+
+    // `exit_boot_services` reads the memory map into a buffer with room for 8 more descriptors
+    // than it has now, so the memory map passed to the kernel has at most that many regions
+    let size = system_table.boot_services().memory_map_size();
+    let regions = size.map_size / size.entry_size + 8;
+    if info.len + memory_map_len(regions) > info.buf.len() {
+        return Err("the Multiboot2 information does not fit");
+    }
+
+    allocations.keep();
+    let (_, mut memory_map) = unsafe { system_table.unsafe_clone() }.exit_boot_services();
+    memory_map.sort();
+
+    // cannot fail, there is room for the whole memory map
+    let _ = write_memory_map(&mut info, &memory_map).and_then(|()| info.tag(TAG_END, |_| Ok(())));
+    let total_size = info.len as u32;
+    info.buf[..4].copy_from_slice(&total_size.to_le_bytes());
+
+    unsafe {
+        asm!(
+            "jmp {}",
+            in(reg) trampoline,
+            in("rdi") entry_point,
+            in("rsi") info_ptr,
+            options(noreturn),
+        );
+    }
+}
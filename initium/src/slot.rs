@@ -0,0 +1,63 @@
+use synapse::boot::BootConfig;
+use synapse::slot::{
+    BootSlot, BootSlotInfo, BootState, BOOT_STATE_LEN, BOOT_STATE_VARIABLE_NAME, BOOT_STATE_VENDOR_GUID,
+};
+use uefi::{
+    prelude::{Boot, Handle, SystemTable},
+    table::runtime::{VariableAttributes, VariableVendor},
+    CStr16, Guid,
+};
+
+use crate::fs;
+
+/// Picks the kernel slot to boot and counts the attempt in the boot state variable.
+///
+/// A slot is abandoned once it used up `boot_attempts` without being marked as successful
+/// by the kernel, or if it has no manifest in `kernel_dir`.
+pub fn select_slot(image: Handle, system_table: &SystemTable<Boot>, config: &BootConfig) -> BootSlotInfo {
+    let max_attempts = config.boot_attempts;
+    let name = CStr16::from_u16_with_nul(&BOOT_STATE_VARIABLE_NAME).unwrap();
+    let vendor = VariableVendor(Guid::from_bytes(BOOT_STATE_VENDOR_GUID));
+    let runtime_services = system_table.runtime_services();
+
+    let mut buf = [0u8; BOOT_STATE_LEN];
+    let stored = runtime_services
+        .get_variable(name, &vendor, &mut buf)
+        .ok()
+        .and_then(|(data, _)| BootState::parse(data));
+    let mut state = stored.unwrap_or(BootState {
+        slot: BootSlot::A,
+        attempts_left: max_attempts,
+    });
+
+    let exists = |slot: BootSlot| {
+        let mut buf = [0; fs::MAX_PATH_LEN];
+        fs::join(config.kernel_dir(), slot.manifest_file_name(), &mut buf)
+            .map_or(false, |path| fs::file_exists(path, image, system_table))
+    };
+    if state.attempts_left == 0 || !exists(state.slot) {
+        if exists(state.slot.other()) {
+            state.slot = state.slot.other();
+        }
+        state.attempts_left = max_attempts;
+    }
+
+    state.attempts_left -= 1;
+
+    // without the variable every boot is the first attempt, which only disables the fallback
+    let _ = runtime_services.set_variable(
+        name,
+        &vendor,
+        VariableAttributes::NON_VOLATILE
+            | VariableAttributes::BOOTSERVICE_ACCESS
+            | VariableAttributes::RUNTIME_ACCESS,
+        &state.to_bytes(),
+    );
+
+    BootSlotInfo {
+        slot: state.slot,
+        attempts_left: state.attempts_left,
+        max_attempts,
+        runtime_services: 0,
+    }
+}
@@ -0,0 +1,473 @@
+use core::{
+    arch::global_asm,
+    mem::size_of,
+    ptr, slice,
+    sync::atomic::Ordering,
+};
+
+use synapse::smp::{ApMailbox, ApState};
+
+use uefi::{
+    prelude::{Boot, Handle, SystemTable},
+    proto::pi::mp::MpServices,
+    table::boot::{AllocateType, MemoryType, OpenProtocolAttributes, OpenProtocolParams},
+};
+use x86_64::{
+    instructions::{
+        segmentation::{Segment, CS, DS},
+        tables::sgdt,
+    },
+    registers::model_specific::Msr,
+    structures::paging::{
+        FrameAllocator, Mapper, PageTable, PageTableFlags, PhysFrame, Size4KiB,
+    },
+    PhysAddr,
+};
+
+use crate::timing;
+
+/// Memory holding the mailboxes and the trampoline must survive the hand-off to the
+/// kernel, so it is allocated with a type the kernel does not treat as usable.
+pub const AP_MAILBOX_MEMORY_TYPE: MemoryType = MemoryType::custom(0x8000_0001);
+
+/// The startup page and the tables identity mapping the first 4 GiB, which are only
+/// used until a processor reaches the trampoline.
+const STARTUP_PAGES: usize = 7;
+
+/// Startup IPIs carry the page number of the startup code in 8 bits.
+const STARTUP_MAX_ADDRESS: u64 = 0xf_ffff;
+
+/// Wait after the INIT IPI before the first startup IPI, as the MP specification asks for.
+const INIT_DELAY_US: u64 = 10_000;
+
+/// Wait after the first startup IPI before sending the second one.
+const STARTUP_DELAY_US: u64 = 200;
+
+/// Time an application processor gets to reach the kernel page table.
+const START_TIMEOUT_US: u64 = 10_000;
+
+/// Offsets of the data patched into the startup code.
+const STARTUP_GDT_BASE: u64 = 42;
+const STARTUP_ENTRY_32: u64 = 48;
+const STARTUP_ENTRY_64: u64 = 56;
+const STARTUP_ARGUMENTS: u64 = 64;
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const IA32_PAT: u32 = 0x277;
+const X2APIC_ICR: u32 = 0x830;
+const APIC_BASE_X2APIC_ENABLE: u64 = 1 << 10;
+const XAPIC_ICR_LOW: u64 = 0x300;
+const XAPIC_ICR_HIGH: u64 = 0x310;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+const ICR_INIT: u32 = 0x4500;
+const ICR_STARTUP: u32 = 0x4600;
+
+#[derive(Debug, Copy, Clone)]
+pub struct ApplicationProcessors {
+    pub bsp_apic_id: u32,
+    pub mailboxes: *mut ApMailbox,
+    pub count: usize,
+    /// Page below 4 GiB the trampoline is copied to.
+    trampoline: PhysFrame,
+    /// Page below 1 MiB the startup code is copied to, followed by its page tables.
+    startup: PhysFrame,
+}
+
+impl ApplicationProcessors {
+    pub fn mailboxes(&self) -> &'static mut [ApMailbox] {
+        unsafe { slice::from_raw_parts_mut(self.mailboxes, self.count) }
+    }
+
+    fn mailbox_frames(&self) -> impl Iterator<Item = PhysFrame> {
+        let start = PhysAddr::new(self.mailboxes as u64);
+        let end = start + (self.count * size_of::<ApMailbox>()) as u64 - 1u64;
+
+        PhysFrame::range_inclusive(
+            PhysFrame::containing_address(start),
+            PhysFrame::containing_address(end),
+        )
+    }
+}
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_end: u8;
+    static ap_startup_start: u8;
+    static ap_startup_32: u8;
+    static ap_startup_64: u8;
+    static ap_startup_end: u8;
+}
+
+// Runs with the mailbox in `rdi`, first on the identity map of the startup code and
+// then on the kernel page table, so it is copied into a frame below 4 GiB that is
+// identity mapped in both. The offsets are those of `synapse::smp::ApMailbox`. With
+// 5-level paging the page table is loaded through the switch of `la57.rs`, which
+// returns to label 4.
+global_asm!(
+    ".global ap_trampoline_start",
+    ".global ap_trampoline_end",
+    "ap_trampoline_start:",
+    "lgdt [rdi + 22]",
+    "mov rax, [rdi + 56]",
+    "test rax, rax",
+    "jnz 5f",
+    "mov rax, [rdi + 8]",
+    "mov cr3, rax",
+    "4:",
+    "mov rsp, [rdi + 32]",
+    "movzx eax, word ptr [rdi + 16]",
+    "push rax",
+    "lea rax, [rip + 2f]",
+    "push rax",
+    "retfq",
+    "2:",
+    "movzx eax, word ptr [rdi + 18]",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    "mov dword ptr [rdi + 4], 2",
+    "3:",
+    "pause",
+    "mov rax, [rdi + 40]",
+    "test rax, rax",
+    "jz 3b",
+    "mov dword ptr [rdi + 4], 3",
+    "push 0",
+    "jmp rax",
+    "5:",
+    "mov esi, [rdi + 8]",
+    "lea rdx, [rip + 4b]",
+    "jmp rax",
+    "ap_trampoline_end:",
+);
+
+// Entered in real mode through a startup IPI, with `cs` pointing to the start of the
+// page. Switches through protected mode to long mode on the identity map following the
+// page and continues in the trampoline. The data at the start of the page is patched
+// with absolute addresses, see the `STARTUP_*` offsets. Processors are started one at
+// a time, so they share the arguments and the stack at the end of the page.
+global_asm!(
+    ".global ap_startup_start",
+    ".global ap_startup_32",
+    ".global ap_startup_64",
+    ".global ap_startup_end",
+    ".code16",
+    "ap_startup_start:",
+    "jmp 1f",
+    // null, 32-bit code at 0x08, data at 0x10, 64-bit code at 0x18
+    ".balign 8",
+    ".quad 0",
+    ".quad 0x00cf9a000000ffff",
+    ".quad 0x00cf92000000ffff",
+    ".quad 0x00af9a000000ffff",
+    // GDT pointer at 40, far pointers to the 32-bit and 64-bit code at 48 and 56
+    ".word 31",
+    ".long 0",
+    ".balign 8",
+    ".long 0",
+    ".word 0x08",
+    ".balign 8",
+    ".long 0",
+    ".word 0x18",
+    // page attribute table, identity map, mailbox and trampoline at 64
+    ".balign 8",
+    ".quad 0, 0, 0, 0",
+    "1:",
+    "cli",
+    "cld",
+    "mov ax, cs",
+    "mov ds, ax",
+    "movzx ebx, ax",
+    "shl ebx, 4",
+    "lgdt [40]",
+    "mov eax, cr0",
+    "or eax, 1",
+    "mov cr0, eax",
+    // far jump through the 32-bit offset at 48, which the assembler does not encode
+    ".byte 0x66, 0xff, 0x2e",
+    ".word 48",
+    ".code32",
+    "ap_startup_32:",
+    "mov ax, 0x10",
+    "mov ds, ax",
+    "mov es, ax",
+    "mov ss, ax",
+    // caches are still disabled after INIT, which is when the attribute table should change
+    "mov ecx, 0x277",
+    "mov eax, [ebx + 64]",
+    "mov edx, [ebx + 68]",
+    "wrmsr",
+    // physical address extension, SSE
+    "mov eax, 0x620",
+    "mov cr4, eax",
+    "mov eax, [ebx + 72]",
+    "mov cr3, eax",
+    // long mode, no-execute
+    "mov ecx, 0xc0000080",
+    "rdmsr",
+    "or eax, 0x900",
+    "wrmsr",
+    // paging, write protection, caches enabled
+    "mov eax, 0x80010033",
+    "mov cr0, eax",
+    "ljmp [ebx + 56]",
+    ".code64",
+    "ap_startup_64:",
+    "mov ebx, ebx",
+    "lea rsp, [rbx + 4096]",
+    "mov rdi, [rbx + 80]",
+    "mov dword ptr [rdi + 4], 1",
+    "mov rax, [rbx + 88]",
+    "jmp rax",
+    "ap_startup_end:",
+);
+
+/// Finds the enabled application processors and reserves the memory to start them
+/// with once the boot services have been exited.
+///
+/// The processors are not started through the MP services, the firmware puts them
+/// back into INIT when the boot services are exited.
+pub fn find_application_processors(
+    image: Handle,
+    system_table: &SystemTable<Boot>,
+) -> Option<ApplicationProcessors> {
+    let boot_services = system_table.boot_services();
+    let mp_handle = boot_services.get_handle_for_protocol::<MpServices>().ok()?;
+    let mp = unsafe {
+        boot_services
+            .open_protocol::<MpServices>(
+                OpenProtocolParams {
+                    handle: mp_handle,
+                    agent: image,
+                    controller: None,
+                },
+                OpenProtocolAttributes::GetProtocol,
+            )
+            .ok()?
+    };
+
+    let total = mp.get_number_of_processors().ok()?.total;
+
+    let mut apic_ids = [0u32; 256];
+    let mut count = 0;
+    let mut bsp_apic_id = 0;
+
+    for processor_number in 0..total {
+        let Ok(info) = mp.get_processor_info(processor_number) else {
+            continue;
+        };
+
+        if info.is_bsp() {
+            bsp_apic_id = info.processor_id as u32;
+        } else if info.is_enabled() && count < apic_ids.len() {
+            apic_ids[count] = info.processor_id as u32;
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    // the trampoline and the mailboxes, below 4 GiB since the startup code only maps
+    // that much and the switch to 5-level paging passes the mailbox in a 32-bit register
+    let pages = 1 + (count * size_of::<ApMailbox>() - 1) / 4096 + 1;
+    let trampoline = boot_services
+        .allocate_pages(AllocateType::MaxAddress(0xffff_ffff), AP_MAILBOX_MEMORY_TYPE, pages)
+        .ok()?;
+    let Ok(startup) = boot_services.allocate_pages(
+        AllocateType::MaxAddress(STARTUP_MAX_ADDRESS),
+        MemoryType::LOADER_DATA,
+        STARTUP_PAGES,
+    ) else {
+        let _ = boot_services.free_pages(trampoline, pages);
+        return None;
+    };
+
+    let mailboxes = (trampoline + 4096) as *mut ApMailbox;
+    for (index, &apic_id) in apic_ids[..count].iter().enumerate() {
+        unsafe { ptr::write(mailboxes.add(index), ApMailbox::new(apic_id)) };
+    }
+
+    let startup = PhysFrame::containing_address(PhysAddr::new(startup));
+    create_identity_map(startup + 1);
+
+    Some(ApplicationProcessors {
+        bsp_apic_id,
+        mailboxes,
+        count,
+        trampoline: PhysFrame::containing_address(PhysAddr::new(trampoline)),
+        startup,
+    })
+}
+
+/// Maps the first 4 GiB with 2 MiB pages into the level 4 table at `frame`, followed
+/// by the level 3 table and four level 2 tables.
+fn create_identity_map(frame: PhysFrame) {
+    let table = |index: u64| (frame + index).start_address().as_u64() as *mut PageTable;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    unsafe {
+        for index in 0..6 {
+            table(index).write(PageTable::new());
+        }
+
+        (*table(0))[0].set_frame(frame + 1, flags);
+        for gigabyte in 0..4 {
+            (*table(1))[gigabyte as usize].set_frame(frame + 2 + gigabyte, flags);
+
+            for (index, entry) in (*table(2 + gigabyte)).iter_mut().enumerate() {
+                let addr = PhysAddr::new(((gigabyte << 9) + index as u64) << 21);
+                entry.set_addr(addr, flags | PageTableFlags::HUGE_PAGE);
+            }
+        }
+    }
+}
+
+/// Copies the trampoline and identity maps it together with the mailboxes.
+///
+/// Returns the physical address of the trampoline.
+pub fn map_trampoline(
+    processors: &ApplicationProcessors,
+    la57_switch: Option<PhysAddr>,
+    kernel_page_table: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> PhysAddr {
+    let trampoline_frame = processors.trampoline;
+
+    if let Some(la57_switch) = la57_switch {
+        for mailbox in processors.mailboxes().iter_mut() {
+            mailbox.la57_switch = la57_switch.as_u64();
+        }
+    }
+
+    unsafe {
+        let start = &ap_trampoline_start as *const u8;
+        let len = &ap_trampoline_end as *const u8 as usize - start as usize;
+        ptr::copy_nonoverlapping(start, trampoline_frame.start_address().as_u64() as *mut u8, len);
+    }
+
+    match unsafe {
+        kernel_page_table.identity_map(trampoline_frame, PageTableFlags::PRESENT, frame_allocator)
+    } {
+        Ok(tlb) => tlb.flush(),
+        Err(err) => panic!("failed to identity map frame {:?}: {:?}", trampoline_frame, err),
+    }
+
+    for frame in processors.mailbox_frames() {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        match unsafe { kernel_page_table.identity_map(frame, flags, frame_allocator) } {
+            Ok(tlb) => tlb.flush(),
+            Err(err) => panic!("failed to identity map frame {:?}: {:?}", frame, err),
+        }
+    }
+
+    trampoline_frame.start_address()
+}
+
+/// Starts the application processors with INIT and startup IPIs and waits for each to
+/// reach the kernel page table and the bootloader GDT.
+///
+/// Must be called after the GDT has been loaded and right before the switch to the
+/// kernel. Processors that do not come up in time are put back into INIT and stay
+/// offline. Returns the number of started processors.
+pub fn start_application_processors(
+    processors: &ApplicationProcessors,
+    trampoline: PhysAddr,
+    page_table: PhysFrame,
+    tsc_frequency: u64,
+) -> usize {
+    let gdt = sgdt();
+
+    for mailbox in processors.mailboxes().iter_mut() {
+        mailbox.code_selector = CS::get_reg().0;
+        mailbox.data_selector = DS::get_reg().0;
+        mailbox.gdt_limit = gdt.limit;
+        mailbox.gdt_base = gdt.base.as_u64();
+        mailbox
+            .page_table
+            .store(page_table.start_address().as_u64(), Ordering::Release);
+    }
+
+    let startup = processors.startup.start_address().as_u64();
+    let arguments = unsafe {
+        let start = &ap_startup_start as *const u8;
+        let len = &ap_startup_end as *const u8 as usize - start as usize;
+        ptr::copy_nonoverlapping(start, startup as *mut u8, len);
+
+        let offset = |symbol: &u8| startup + (symbol as *const u8 as u64 - start as u64);
+        let patch = |at: u64, value: u64| ((startup + at) as *mut u32).write_unaligned(value as u32);
+        patch(STARTUP_GDT_BASE, startup + 8);
+        patch(STARTUP_ENTRY_32, offset(&ap_startup_32));
+        patch(STARTUP_ENTRY_64, offset(&ap_startup_64));
+
+        let arguments = (startup + STARTUP_ARGUMENTS) as *mut u64;
+        arguments.write(Msr::new(IA32_PAT).read());
+        arguments.add(1).write(startup + 4096);
+        arguments.add(3).write(trampoline.as_u64());
+        arguments
+    };
+
+    let apic = LocalApic::new();
+    for mailbox in processors.mailboxes().iter() {
+        apic.send(mailbox.apic_id, ICR_INIT);
+    }
+    timing::spin_us(tsc_frequency, INIT_DELAY_US);
+
+    let vector = (startup >> 12) as u32;
+    let mut started = 0;
+    for mailbox in processors.mailboxes().iter() {
+        unsafe { arguments.add(2).write_volatile(mailbox as *const ApMailbox as u64) };
+
+        apic.send(mailbox.apic_id, ICR_STARTUP | vector);
+        timing::spin_us(tsc_frequency, STARTUP_DELAY_US);
+        if mailbox.state() == ApState::Offline {
+            apic.send(mailbox.apic_id, ICR_STARTUP | vector);
+        }
+
+        if timing::spin_until(tsc_frequency, START_TIMEOUT_US, || mailbox.state() == ApState::Waiting) {
+            started += 1;
+        } else {
+            // the startup code and its stack are reused by the next processor
+            apic.send(mailbox.apic_id, ICR_INIT);
+            mailbox.state.store(ApState::Offline as u32, Ordering::Release);
+        }
+    }
+
+    started
+}
+
+/// The local APIC of the bootstrap processor, only used to send IPIs.
+enum LocalApic {
+    XApic(u64),
+    X2Apic,
+}
+
+impl LocalApic {
+    fn new() -> Self {
+        let base = unsafe { Msr::new(IA32_APIC_BASE).read() };
+        if base & APIC_BASE_X2APIC_ENABLE != 0 {
+            LocalApic::X2Apic
+        } else {
+            LocalApic::XApic(base & 0x000f_ffff_ffff_f000)
+        }
+    }
+
+    fn send(&self, apic_id: u32, command: u32) {
+        match *self {
+            LocalApic::X2Apic => unsafe {
+                Msr::new(X2APIC_ICR).write(u64::from(apic_id) << 32 | u64::from(command));
+            },
+            // runs on the firmware page table, which identity maps the registers
+            LocalApic::XApic(base) => unsafe {
+                let low = (base + XAPIC_ICR_LOW) as *mut u32;
+                let high = (base + XAPIC_ICR_HIGH) as *mut u32;
+                high.write_volatile(apic_id << 24);
+                low.write_volatile(command);
+                while low.read_volatile() & ICR_DELIVERY_PENDING != 0 {
+                    core::hint::spin_loop();
+                }
+            },
+        }
+    }
+}
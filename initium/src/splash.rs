@@ -0,0 +1,84 @@
+use core::slice;
+
+use initium_core::image::Image;
+use synapse::framebuffer::{FramebufferInfo, PixelFormat};
+
+use crate::initium::RawFramebufferInfo;
+
+const BAR_HEIGHT: usize = 6;
+/// Space between the image and the progress bar.
+const BAR_MARGIN: usize = 24;
+const BAR_BORDER: [u8; 3] = [96, 96, 96];
+const BAR_FILL: [u8; 3] = [255, 255, 255];
+
+/// Splash image centred on the framebuffer, with a progress bar below it.
+pub struct Splash {
+    buffer: &'static mut [u8],
+    info: FramebufferInfo,
+    bar_x: usize,
+    bar_y: usize,
+    bar_width: usize,
+}
+
+impl Splash {
+    /// Clears the framebuffer and draws the image, `image` may be freed afterwards.
+    pub fn show(framebuffer: &RawFramebufferInfo, image: &[u8]) -> Result<Self, &'static str> {
+        let image = Image::parse(image)?;
+
+        let info = framebuffer.info;
+        let buffer = unsafe { slice::from_raw_parts_mut(framebuffer.addr.as_u64() as *mut u8, info.byte_len) };
+        let mut splash = Splash {
+            buffer,
+            info,
+            bar_x: 0,
+            bar_y: 0,
+            bar_width: 0,
+        };
+
+        splash.buffer.fill(0);
+
+        let (width, height) = image.size();
+        let left = info.width.saturating_sub(width) / 2;
+        let top = info.height.saturating_sub(height + BAR_MARGIN + BAR_HEIGHT) / 2;
+        image.draw(|x, y, color| splash.put_pixel(left + x, top + y, color));
+
+        splash.bar_width = (width / 2).clamp(64.min(info.width), info.width);
+        splash.bar_x = (info.width - splash.bar_width) / 2;
+        splash.bar_y = (top + height + BAR_MARGIN).min(info.height.saturating_sub(BAR_HEIGHT));
+        splash.set_progress(0);
+
+        Ok(splash)
+    }
+
+    fn put_pixel(&mut self, x: usize, y: usize, [red, green, blue]: [u8; 3]) {
+        if x >= self.info.width || y >= self.info.height {
+            return;
+        }
+
+        let offset = (y * self.info.stride + x) * self.info.bytes_per_pixel;
+        let color = match self.info.pixel_format {
+            PixelFormat::Rgb => [red, green, blue],
+            PixelFormat::Bgr => [blue, green, red],
+        };
+        self.buffer[offset..offset + 3].copy_from_slice(&color);
+    }
+
+    /// Fills the progress bar to `percent`.
+    pub fn set_progress(&mut self, percent: usize) {
+        let filled = self.bar_width * percent.min(100) / 100;
+
+        for y in 0..BAR_HEIGHT {
+            for x in 0..self.bar_width {
+                let border = y == 0 || y == BAR_HEIGHT - 1 || x == 0 || x == self.bar_width - 1;
+                let color = if x < filled {
+                    BAR_FILL
+                } else if border {
+                    BAR_BORDER
+                } else {
+                    [0; 3]
+                };
+                self.put_pixel(self.bar_x + x, self.bar_y + y, color);
+            }
+        }
+    }
+}
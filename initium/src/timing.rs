@@ -0,0 +1,48 @@
+use core::arch::x86_64::_rdtsc;
+
+use synapse::timing::{BootPhase, BootTimings};
+use uefi::prelude::{Boot, SystemTable};
+
+/// Length of the `Stall` the time stamp counter is calibrated against.
+const CALIBRATION_STALL_US: u64 = 2_000;
+
+pub fn timestamp() -> u64 {
+    unsafe { _rdtsc() }
+}
+
+pub fn calibrate_tsc(timings: &mut BootTimings, system_table: &SystemTable<Boot>) {
+    let start = timestamp();
+    system_table.boot_services().stall(CALIBRATION_STALL_US as usize);
+    let end = timestamp();
+
+    timings.tsc_frequency = (end - start) * (1_000_000 / CALIBRATION_STALL_US);
+    timings.push(BootPhase::CalibrateTsc, start, end);
+}
+
+pub fn measure<T>(timings: &mut BootTimings, phase: BootPhase, f: impl FnOnce() -> T) -> T {
+    let start = timestamp();
+    let result = f();
+    timings.push(phase, start, timestamp());
+
+    result
+}
+
+/// Busy-waits for `us` microseconds, for use once the boot services have been exited.
+pub fn spin_us(tsc_frequency: u64, us: u64) {
+    spin_until(tsc_frequency, us, || false);
+}
+
+/// Busy-waits until `condition` holds or `us` microseconds have passed.
+///
+/// Returns whether `condition` held.
+pub fn spin_until(tsc_frequency: u64, us: u64, condition: impl Fn() -> bool) -> bool {
+    let end = timestamp() + tsc_frequency / 1_000_000 * us;
+    while timestamp() < end {
+        if condition() {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+
+    condition()
+}
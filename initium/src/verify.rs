@@ -0,0 +1,69 @@
+use core::fmt::{self, Write};
+
+use ed25519_compact::{sha512, PublicKey, Signature};
+use synapse::manifest::{Manifest, HASH_LEN, SIGNATURE_SUFFIX};
+use uefi::{
+    prelude::{Boot, Handle, SystemTable},
+    proto::console::text::Color,
+};
+
+use crate::fs;
+
+/// Ed25519 public key of the host tool, set through `LIFE_PUBLIC_KEY` at build time.
+static PUBLIC_KEY: [u8; 32] = *include_bytes!(concat!(env!("OUT_DIR"), "/public_key"));
+
+/// Checks the signature of the manifest against the embedded public key.
+pub fn verify_manifest(manifest: &Manifest) -> Result<(), &'static str> {
+    let public_key =
+        PublicKey::from_slice(&PUBLIC_KEY).map_err(|_| "the embedded public key is invalid")?;
+    let signature =
+        Signature::from_slice(&manifest.signature).map_err(|_| "the manifest signature is malformed")?;
+
+    public_key
+        .verify(manifest.signed_bytes(), &signature)
+        .map_err(|_| "the manifest signature does not match")
+}
+
+pub fn verify_hash(data: &[u8], expected: &[u8; HASH_LEN]) -> Result<(), &'static str> {
+    if sha512::Hash::hash(data) != *expected {
+        return Err("the hash does not match the manifest");
+    }
+
+    Ok(())
+}
+
+/// Checks a file outside of the kernel slots against its detached manifest at `path` + `.sig`.
+pub fn verify_file(
+    path: &str,
+    data: &[u8],
+    image: Handle,
+    system_table: &SystemTable<Boot>,
+) -> Result<(), &'static str> {
+    let mut buf = [0; fs::MAX_PATH_LEN];
+    let manifest_path = buf
+        .get_mut(..path.len() + SIGNATURE_SUFFIX.len())
+        .ok_or("path is too long")?;
+    manifest_path[..path.len()].copy_from_slice(path.as_bytes());
+    manifest_path[path.len()..].copy_from_slice(SIGNATURE_SUFFIX.as_bytes());
+    let manifest_path = core::str::from_utf8(manifest_path).unwrap();
+
+    let manifest = fs::load_file(manifest_path, image, system_table)?.ok_or("the file is not signed")?;
+    let parsed = Manifest::parse(manifest).filter(|manifest| manifest.ramdisk_hash.is_none());
+    fs::free_file(manifest, system_table);
+    let manifest = parsed.ok_or("the signature of the file is malformed")?;
+
+    verify_manifest(&manifest)?;
+    verify_hash(data, &manifest.kernel_hash)
+}
+
+/// Prints the error to the firmware console and halts, the kernel is never started.
+pub fn refuse_boot(system_table: &mut SystemTable<Boot>, args: fmt::Arguments) -> ! {
+    let stdout = system_table.stdout();
+    let _ = stdout.set_color(Color::LightRed, Color::Black);
+    let _ = writeln!(stdout, "cannot boot the kernel: {args}");
+    let _ = writeln!(stdout, "refusing to boot");
+
+    loop {
+        system_table.boot_services().stall(1_000_000);
+    }
+}
@@ -0,0 +1,132 @@
+use x86_64::structures::paging::{PageTable, PageTableFlags};
+use x86_64::VirtAddr;
+
+use synapse::boot::BootInfo;
+use synapse::dump::{PageRange, Record, DUMP_BEGIN, DUMP_END};
+
+use crate::memory::active_level_4_table;
+use crate::println;
+
+/// Prints the boot info and the layout of the active page tables in the format of
+/// `synapse::dump`, decoded on the host with `life dump`.
+///
+/// The serial port is locked for each record, not for the whole dump.
+pub fn write(boot_info: &BootInfo) {
+    println!("{DUMP_BEGIN}");
+    println!("{}", Record::BootInfo { version: boot_info.header.version, size: boot_info.header.size });
+    if let Some(name) = boot_info.extensions.bootloader_name() {
+        println!("{}", Record::Bootloader(name));
+    }
+    let firmware = &boot_info.firmware;
+    println!("{}", Record::Firmware {
+        revision: firmware.revision,
+        uefi_major: firmware.uefi_major,
+        uefi_minor: firmware.uefi_minor,
+        vendor: firmware.vendor(),
+    });
+    if let Some(&address) = boot_info.smbios_address.as_ref() {
+        println!("{}", Record::Smbios(address));
+    }
+    if let Some(&address) = boot_info.smbios3_address.as_ref() {
+        println!("{}", Record::Smbios3(address));
+    }
+    println!("{}", Record::Paging(boot_info.paging_mode));
+    if let Some(&offset) = boot_info.physical_memory_offset.as_ref() {
+        println!("{}", Record::PhysicalMemoryOffset(offset));
+    }
+    if let Some(framebuffer) = boot_info.framebuffer.as_ref() {
+        println!("{}", Record::Framebuffer {
+            address: framebuffer.start_address,
+            len: framebuffer.info.byte_len as u64,
+            width: framebuffer.info.width as u64,
+            height: framebuffer.info.height as u64,
+        });
+    }
+    if let Some(&address) = boot_info.rsdp_address.as_ref() {
+        println!("{}", Record::Rsdp(address));
+    }
+    if let Some(&address) = boot_info.ramdisk_address.as_ref() {
+        println!("{}", Record::Ramdisk { address, len: boot_info.ramdisk_len });
+    }
+    for region in boot_info.memory_regions.iter() {
+        println!("{}", Record::Memory(*region));
+    }
+    for region in boot_info.virtual_regions.iter() {
+        println!("{}", Record::Virtual(*region));
+    }
+
+    // the page tables are only reachable through the physical memory mapping
+    if let Some(&offset) = boot_info.physical_memory_offset.as_ref() {
+        let offset = VirtAddr::new(offset);
+        let mut pages = Pages { current: None };
+        unsafe {
+            let level_4_table = active_level_4_table(offset, boot_info.paging_mode);
+            walk(&mut pages, level_4_table, 4, 0, offset);
+        }
+        pages.finish();
+    }
+
+    println!("{DUMP_END}");
+}
+
+/// Merges consecutive pages into `PageRange` records.
+struct Pages {
+    current: Option<PageRange>,
+}
+
+impl Pages {
+    fn push(&mut self, start: u64, size: u64, physical_start: u64, flags: u64) {
+        if let Some(current) = &mut self.current {
+            let len = current.end.wrapping_sub(current.start);
+            if current.end == start && current.physical_start + len == physical_start && current.flags == flags {
+                current.end = start.wrapping_add(size);
+                return;
+            }
+            println!("{}", Record::Page(*current));
+        }
+
+        self.current = Some(PageRange {
+            start,
+            // wraps to 0 for the last page of the address space
+            end: start.wrapping_add(size),
+            physical_start,
+            flags,
+        });
+    }
+
+    fn finish(mut self) {
+        if let Some(current) = self.current.take() {
+            println!("{}", Record::Page(current));
+        }
+    }
+}
+
+unsafe fn walk(pages: &mut Pages, table: &PageTable, level: u8, base: u64, physical_memory_offset: VirtAddr) {
+    let size = 4096u64 << (9 * (level - 1));
+
+    for (index, entry) in table.iter().enumerate() {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+
+        let mut start = base + index as u64 * size;
+        if level == 4 && index >= 256 {
+            // sign extension of canonical higher half addresses
+            start |= 0xffff_0000_0000_0000;
+        }
+
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            // accessed and dirty bits would split the ranges without telling anything about the mapping
+            let mut flags = flags - PageTableFlags::ACCESSED - PageTableFlags::DIRTY;
+            if level > 1 {
+                flags -= PageTableFlags::HUGE_PAGE;
+            }
+            let physical_start = entry.addr().as_u64() & !(size - 1);
+            pages.push(start, size, physical_start, flags.bits());
+        } else {
+            let next: &PageTable = &*(physical_memory_offset + entry.addr().as_u64()).as_ptr();
+            walk(pages, next, level - 1, start, physical_memory_offset);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use core::fmt::{self, Write};
+
+use spin::Lazy;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{
+    Entry, HandlerFunc, HandlerFuncWithErrCode, InterruptDescriptorTable, InterruptStackFrame,
+    PageFaultErrorCode,
+};
+
+use crate::gdt::DOUBLE_FAULT_IST_INDEX;
+use crate::panic_screen;
+use crate::serial::SERIAL;
+
+static IDT: Lazy<InterruptDescriptorTable> = Lazy::new(|| {
+    let mut idt = InterruptDescriptorTable::new();
+    idt.divide_error.set_handler_fn(divide_error);
+    idt.debug.set_handler_fn(debug);
+    idt.non_maskable_interrupt.set_handler_fn(non_maskable_interrupt);
+    idt.breakpoint.set_handler_fn(breakpoint);
+    idt.overflow.set_handler_fn(overflow);
+    idt.bound_range_exceeded.set_handler_fn(bound_range_exceeded);
+    idt.invalid_opcode.set_handler_fn(invalid_opcode);
+    idt.device_not_available.set_handler_fn(device_not_available);
+    unsafe {
+        idt.double_fault
+            .set_handler_fn(double_fault)
+            .set_stack_index(DOUBLE_FAULT_IST_INDEX);
+    }
+    idt.invalid_tss.set_handler_fn(invalid_tss);
+    idt.segment_not_present.set_handler_fn(segment_not_present);
+    idt.stack_segment_fault.set_handler_fn(stack_segment_fault);
+    idt.general_protection_fault.set_handler_fn(general_protection_fault);
+    idt.page_fault.set_handler_fn(page_fault);
+    idt.x87_floating_point.set_handler_fn(x87_floating_point);
+    idt.alignment_check.set_handler_fn(alignment_check);
+    idt.machine_check.set_handler_fn(machine_check);
+    idt.simd_floating_point.set_handler_fn(simd_floating_point);
+    idt.virtualization.set_handler_fn(virtualization);
+    unsafe {
+        reserved_entry::<HandlerFuncWithErrCode>(&mut idt, CP_PROTECTION_VECTOR).set_handler_fn(cp_protection_exception);
+        reserved_entry::<HandlerFunc>(&mut idt, HV_INJECTION_VECTOR).set_handler_fn(hv_injection_exception);
+    }
+    idt.vmm_communication_exception.set_handler_fn(vmm_communication_exception);
+    idt.security_exception.set_handler_fn(security_exception);
+    idt
+});
+
+/// Vectors of #CP and #HV, which the x86_64 crate before 0.14.11 declares as reserved.
+const CP_PROTECTION_VECTOR: usize = 21;
+const HV_INJECTION_VECTOR: usize = 28;
+
+/// Entry of a vector without a field in `InterruptDescriptorTable`, which lays out its
+/// entries in vector order.
+///
+/// # Safety
+///
+/// `F` must match whether the exception pushes an error code.
+unsafe fn reserved_entry<F>(idt: &mut InterruptDescriptorTable, vector: usize) -> &mut Entry<F> {
+    &mut *(idt as *mut InterruptDescriptorTable as *mut Entry<F>).add(vector)
+}
+
+/// Loads the IDT on the bootstrap processor, `gdt::init` has to be called first for the
+/// double fault stack.
+pub fn init() {
+    IDT.load();
+}
+
+/// Handlers of fatal exceptions, which report through the panic screen with the interrupted frame.
+macro_rules! fatal_exception {
+    ($name:ident, $description:literal) => {
+        extern "x86-interrupt" fn $name(stack_frame: InterruptStackFrame) {
+            panic_screen::set_interrupted_frame(&stack_frame);
+            panic!("EXCEPTION: {}", $description);
+        }
+    };
+    ($name:ident, $description:literal, error_code) => {
+        extern "x86-interrupt" fn $name(stack_frame: InterruptStackFrame, error_code: u64) {
+            panic_screen::set_interrupted_frame(&stack_frame);
+            panic!("EXCEPTION: {} (error code {:#x})", $description, error_code);
+        }
+    };
+}
+
+fatal_exception!(divide_error, "DIVIDE ERROR");
+fatal_exception!(non_maskable_interrupt, "NON-MASKABLE INTERRUPT");
+fatal_exception!(overflow, "OVERFLOW");
+fatal_exception!(bound_range_exceeded, "BOUND RANGE EXCEEDED");
+fatal_exception!(invalid_opcode, "INVALID OPCODE");
+fatal_exception!(device_not_available, "DEVICE NOT AVAILABLE");
+fatal_exception!(invalid_tss, "INVALID TSS", error_code);
+fatal_exception!(segment_not_present, "SEGMENT NOT PRESENT", error_code);
+fatal_exception!(stack_segment_fault, "STACK SEGMENT FAULT", error_code);
+fatal_exception!(general_protection_fault, "GENERAL PROTECTION FAULT", error_code);
+fatal_exception!(x87_floating_point, "X87 FLOATING POINT");
+fatal_exception!(alignment_check, "ALIGNMENT CHECK", error_code);
+fatal_exception!(simd_floating_point, "SIMD FLOATING POINT");
+fatal_exception!(virtualization, "VIRTUALIZATION");
+fatal_exception!(cp_protection_exception, "CONTROL PROTECTION", error_code);
+fatal_exception!(hv_injection_exception, "HYPERVISOR INJECTION");
+fatal_exception!(vmm_communication_exception, "VMM COMMUNICATION", error_code);
+fatal_exception!(security_exception, "SECURITY", error_code);
+
+/// Reports a non-fatal exception on the serial port. Exceptions are not masked while the port is
+/// locked, so the report is dropped if the interrupted code holds the lock.
+fn report(args: fmt::Arguments) {
+    if let Some(mut serial) = SERIAL.try_lock() {
+        let _ = writeln!(serial, "{args}");
+    }
+}
+
+extern "x86-interrupt" fn debug(stack_frame: InterruptStackFrame) {
+    report(format_args!("debug exception at {:#x}", stack_frame.instruction_pointer.as_u64()));
+}
+
+extern "x86-interrupt" fn breakpoint(stack_frame: InterruptStackFrame) {
+    report(format_args!("breakpoint at {:#x}", stack_frame.instruction_pointer.as_u64()));
+}
+
+extern "x86-interrupt" fn page_fault(stack_frame: InterruptStackFrame, error_code: PageFaultErrorCode) {
+    let address = Cr2::read();
+
+    let access = if error_code.contains(PageFaultErrorCode::INSTRUCTION_FETCH) {
+        "executing"
+    } else if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+        "writing"
+    } else {
+        "reading"
+    };
+    let cause = if error_code.contains(PageFaultErrorCode::MALFORMED_TABLE) {
+        "a reserved bit is set in the page table"
+    } else if error_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+        "the page does not allow it"
+    } else {
+        "the page is not present"
+    };
+    let mode = if error_code.contains(PageFaultErrorCode::USER_MODE) { "user" } else { "kernel" };
+
+    panic_screen::set_interrupted_frame(&stack_frame);
+    panic!(
+        "EXCEPTION: PAGE FAULT {access} {:#x} in {mode} mode, {cause} (error code {:#x})",
+        address.as_u64(),
+        error_code.bits()
+    );
+}
+
+extern "x86-interrupt" fn double_fault(stack_frame: InterruptStackFrame, error_code: u64) -> ! {
+    panic_screen::set_interrupted_frame(&stack_frame);
+    panic!("EXCEPTION: DOUBLE FAULT (error code {:#x})", error_code);
+}
+
+extern "x86-interrupt" fn machine_check(stack_frame: InterruptStackFrame) -> ! {
+    panic_screen::set_interrupted_frame(&stack_frame);
+    panic!("EXCEPTION: MACHINE CHECK");
+}
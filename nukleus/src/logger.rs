@@ -0,0 +1,76 @@
+use core::arch::x86_64::_rdtsc;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use synapse::timing::BootTimings;
+
+static LOGGER: KernelLogger = KernelLogger {
+    tsc_frequency: AtomicU64::new(0),
+    tsc_start: AtomicU64::new(0),
+};
+
+/// Writes log records to the serial port, prefixed with the seconds since initium was entered.
+struct KernelLogger {
+    tsc_frequency: AtomicU64,
+    tsc_start: AtomicU64,
+}
+
+impl Log for KernelLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let level = match record.level() {
+            Level::Error => "\x1b[31mERROR\x1b[0m",
+            Level::Warn => "\x1b[33mWARN \x1b[0m",
+            Level::Info => "\x1b[32mINFO \x1b[0m",
+            Level::Debug => "\x1b[36mDEBUG\x1b[0m",
+            Level::Trace => "\x1b[90mTRACE\x1b[0m",
+        };
+
+        let frequency = self.tsc_frequency.load(Ordering::Relaxed);
+        let ticks = unsafe { _rdtsc() }.saturating_sub(self.tsc_start.load(Ordering::Relaxed));
+        if frequency == 0 {
+            crate::println!("[{ticks:>16}] {level} {}: {}", record.target(), record.args());
+        } else {
+            let micros = (ticks as u128 * 1_000_000 / frequency as u128) as u64;
+            crate::println!(
+                "[{:>5}.{:06}] {level} {}: {}",
+                micros / 1_000_000,
+                micros % 1_000_000,
+                record.target(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger, timestamps are raw TSC ticks if initium could not calibrate the TSC.
+pub fn init(timings: &BootTimings, level: LevelFilter) {
+    LOGGER.tsc_frequency.store(timings.tsc_frequency, Ordering::Relaxed);
+    LOGGER.tsc_start.store(timings.bootloader_entry, Ordering::Relaxed);
+
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Logs how long each boot phase of initium took.
+pub fn log_boot_timings(timings: &BootTimings) {
+    if timings.tsc_frequency == 0 {
+        log::info!("boot phase durations are unknown, initium could not calibrate the TSC");
+        return;
+    }
+
+    for phase in timings.phases() {
+        log::info!("{:<20} {:>10} us", phase.phase.name(), timings.duration_us(phase));
+    }
+    log::info!("{:<20} {:>10} us", "total", timings.total_us());
+}
@@ -1,10 +1,19 @@
 #![no_std]
 #![no_main]
 #![feature(core_intrinsics)]
+#![feature(panic_info_message)]
+#![feature(abi_x86_interrupt)]
 
 extern crate alloc;
 
+mod serial;
+mod dump;
+mod gdt;
+mod interrupts;
+mod logger;
 mod memory;
+mod panic_screen;
+mod smp;
 mod text_based_interface;
 
 use x86_64::VirtAddr;
@@ -18,31 +27,74 @@ use crate::memory::NukleusFrameAllocator;
 use crate::text_based_interface::framebuffer_writer::FramebufferWriter;
 use crate::text_based_interface::primitive::{Point, Primitive};
 
-#[panic_handler]
-fn panic(_info: &core::panic::PanicInfo) -> ! {
-    loop {}
-}
-
 fn main(boot_info: &'static mut BootInfo) -> ! {
+    /* Set up the serial console and the panic screen */
+
+    serial::init();
+    logger::init(&boot_info.timings, log::LevelFilter::Info);
+    log::info!("nukleus started by {}", boot_info.extensions.bootloader_name().unwrap_or("an unknown bootloader"));
+    log::info!(
+        "firmware {} revision {:#x}, UEFI {}.{}",
+        boot_info.firmware.vendor(),
+        boot_info.firmware.revision,
+        boot_info.firmware.uefi_major,
+        boot_info.firmware.uefi_minor
+    );
+    match (boot_info.smbios3_address.as_ref(), boot_info.smbios_address.as_ref()) {
+        (Some(address), _) => log::info!("SMBIOS 3 entry point at {address:#x}"),
+        (None, Some(address)) => log::info!("SMBIOS entry point at {address:#x}"),
+        (None, None) => log::info!("no SMBIOS entry point"),
+    }
+    logger::log_boot_timings(&boot_info.timings);
+
+    panic_screen::init(boot_info);
+
+    /* Handle CPU exceptions */
+
+    gdt::init();
+    interrupts::init();
+
+    /* Dump what initium handed over, decoded with `life dump` */
+
+    dump::write(boot_info);
+
     /* retrieve data from BootInfo */
 
-    let physical_memory_offset = VirtAddr::new(core::mem::replace(&mut boot_info.physical_memory_offset, Optional::None).into_option().unwrap());
+    let physical_memory_offset = core::mem::replace(&mut boot_info.physical_memory_offset, Optional::None).into_option();
     let framebuffer = core::mem::replace(&mut boot_info.framebuffer, Optional::None).into_option().unwrap();
 
     /* Manage the memory for the Kernel */
 
-    let mut mapper = unsafe { memory::init(physical_memory_offset) };
-    let mut frame_allocator = unsafe { NukleusFrameAllocator::init(&boot_info.memory_regions) };
+    // the page tables are only reachable through the physical memory mapping
+    match physical_memory_offset {
+        Some(physical_memory_offset) => {
+            let mut mapper = unsafe { memory::init(VirtAddr::new(physical_memory_offset), boot_info.paging_mode) };
+            let mut frame_allocator = unsafe { NukleusFrameAllocator::init(&boot_info.memory_regions) };
+
+            memory::allocator::init_heap(&mut mapper, &mut frame_allocator).expect("failed to initialize the kernel heap");
+        }
+        None => log::warn!("no physical memory mapping, running without a heap"),
+    }
 
-    memory::allocator::init_heap(&mut mapper, &mut frame_allocator).expect("");
+    /* Release the application processors */
+
+    if let Some(smp_info) = boot_info.smp.as_ref() {
+        let started = smp::start_application_processors(smp_info);
+        log::info!("started {started} of {} application processors", smp_info.mailbox_count);
+    }
 
     /* Write to Framebuffer */
 
     let info = framebuffer.info;
     let buffer = framebuffer.into_buffer();
-    let writer = FramebufferWriter::new(info);
+    let font_size = text_based_interface::font_size(&info, boot_info.edid.as_ref());
+    let writer = FramebufferWriter::new(info, font_size);
 
+    if boot_info.splash_shown {
+        text_based_interface::fade_to_background(buffer, &writer, boot_info.timings.tsc_frequency);
+    }
     text_based_interface::draw_background(buffer, &writer);
+    text_based_interface::draw_boot_timings(buffer, &writer, &boot_info.timings);
 
     let quad = Primitive::Quad(Point { x: info.width / 2 - 100, y: info.height / 2 - 50 }, Point { x: info.width / 2 + 100, y: info.height / 2 + 50 });
     writer.draw_primitive(buffer, quad, Color {
@@ -78,6 +130,14 @@ fn main(boot_info: &'static mut BootInfo) -> ! {
         blue: 0,
     });
 
+    /* Keep booting this kernel slot, everything is up */
+
+    if let Some(slot) = boot_info.boot_slot.as_ref() {
+        if let Err(status) = unsafe { slot.mark_successful() } {
+            log::warn!("failed to mark the kernel slot as successful: status {status:#x}");
+        }
+    }
+
     loop {}
 }
 
@@ -1,5 +1,6 @@
 use x86_64::{PhysAddr, structures::paging::PageTable, VirtAddr};
 use x86_64::structures::paging::{FrameAllocator, OffsetPageTable, PhysFrame, Size4KiB};
+use synapse::boot::PagingMode;
 use synapse::memory::{MemoryRegionKind, MemoryRegions};
 
 pub mod allocator;
@@ -39,21 +40,29 @@ unsafe impl FrameAllocator<Size4KiB> for NukleusFrameAllocator {
     }
 }
 
-unsafe fn active_level_4_table(physical_memory_offset: VirtAddr)
+pub unsafe fn active_level_4_table(physical_memory_offset: VirtAddr, paging_mode: PagingMode)
                                -> &'static mut PageTable
 {
     use x86_64::registers::control::Cr3;
 
-    let (level_4_table_frame, _) = Cr3::read();
+    let (root_table_frame, _) = Cr3::read();
 
-    let phys = level_4_table_frame.start_address();
+    // with 5-level paging, the bootloader points the first level 5 entry to the level 4 table
+    let phys = match paging_mode {
+        PagingMode::Level4 => root_table_frame.start_address(),
+        PagingMode::Level5 => {
+            let virt = physical_memory_offset + root_table_frame.start_address().as_u64();
+            let level_5_table: &PageTable = &*virt.as_ptr();
+            level_5_table[0].addr()
+        }
+    };
     let virt = physical_memory_offset + phys.as_u64();
     let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
 
     &mut *page_table_ptr
 }
 
-pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
-    let level_4_table = active_level_4_table(physical_memory_offset);
+pub unsafe fn init(physical_memory_offset: VirtAddr, paging_mode: PagingMode) -> OffsetPageTable<'static> {
+    let level_4_table = active_level_4_table(physical_memory_offset, paging_mode);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
\ No newline at end of file
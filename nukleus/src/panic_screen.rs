@@ -0,0 +1,310 @@
+use core::arch::asm;
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use core::slice;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spin::Once;
+use x86_64::instructions::{hlt, interrupts};
+use x86_64::structures::idt::InterruptStackFrameValue;
+
+use synapse::boot::{BootInfo, PagingMode};
+use synapse::framebuffer::Color;
+use synapse::memory::MemoryRegionKind;
+
+use crate::serial::SERIAL;
+use crate::smp::ONLINE_PROCESSORS;
+use crate::text_based_interface::font_size;
+use crate::text_based_interface::framebuffer_writer::FramebufferWriter;
+use crate::text_based_interface::primitive::{Point, Primitive};
+
+const BACKGROUND: Color = Color { red: 150, green: 20, blue: 20 };
+const FOREGROUND: Color = Color { red: 255, green: 255, blue: 255 };
+const BOOTLOADER_NAME_LEN: usize = 64;
+
+static SCREEN: Once<Screen> = Once::new();
+static HIGHLIGHTS: Once<Highlights> = Once::new();
+static PANICKING: AtomicBool = AtomicBool::new(false);
+/// Where a fatal exception interrupted the kernel, the registers captured by the panic handler are
+/// those of the exception handler.
+static INTERRUPTED: Once<InterruptStackFrameValue> = Once::new();
+
+struct Screen {
+    address: u64,
+    writer: FramebufferWriter,
+}
+
+/// Parts of the boot info that are shown on the panic screen.
+struct Highlights {
+    bootloader: [u8; BOOTLOADER_NAME_LEN],
+    bootloader_len: usize,
+    paging_mode: PagingMode,
+    physical_memory_offset: Option<u64>,
+    rsdp_address: Option<u64>,
+    usable_memory: u64,
+}
+
+/// Registers of the panic handler itself when it was entered, in the order they are stored by
+/// `capture`.
+#[derive(Default)]
+#[repr(C)]
+struct Registers {
+    rax: u64,
+    rbx: u64,
+    rcx: u64,
+    rdx: u64,
+    rsi: u64,
+    rdi: u64,
+    rbp: u64,
+    rsp: u64,
+    r8: u64,
+    r9: u64,
+    r10: u64,
+    r11: u64,
+    r12: u64,
+    r13: u64,
+    r14: u64,
+    r15: u64,
+    rip: u64,
+    rflags: u64,
+    cr0: u64,
+    cr2: u64,
+    cr3: u64,
+    cr4: u64,
+}
+
+impl Registers {
+    #[inline(always)]
+    fn capture() -> Self {
+        let mut registers = Registers::default();
+        unsafe {
+            asm!(
+                "mov [{r} + 0x00], rax",
+                "mov [{r} + 0x08], rbx",
+                "mov [{r} + 0x10], rcx",
+                "mov [{r} + 0x18], rdx",
+                "mov [{r} + 0x20], rsi",
+                "mov [{r} + 0x28], rdi",
+                "mov [{r} + 0x30], rbp",
+                "mov [{r} + 0x38], rsp",
+                "mov [{r} + 0x40], r8",
+                "mov [{r} + 0x48], r9",
+                "mov [{r} + 0x50], r10",
+                "mov [{r} + 0x58], r11",
+                "mov [{r} + 0x60], r12",
+                "mov [{r} + 0x68], r13",
+                "mov [{r} + 0x70], r14",
+                "mov [{r} + 0x78], r15",
+                "lea {t}, [rip]",
+                "mov [{r} + 0x80], {t}",
+                "pushfq",
+                "pop {t}",
+                "mov [{r} + 0x88], {t}",
+                "mov {t}, cr0",
+                "mov [{r} + 0x90], {t}",
+                "mov {t}, cr2",
+                "mov [{r} + 0x98], {t}",
+                "mov {t}, cr3",
+                "mov [{r} + 0xa0], {t}",
+                "mov {t}, cr4",
+                "mov [{r} + 0xa8], {t}",
+                r = in(reg) &mut registers as *mut Registers,
+                t = out(reg) _,
+            );
+        }
+        registers
+    }
+}
+
+/// Shows where an exception interrupted the kernel on the panic screen, exception handlers call
+/// this before they panic.
+pub fn set_interrupted_frame(frame: &InterruptStackFrameValue) {
+    INTERRUPTED.call_once(|| *frame);
+}
+
+/// Remembers the framebuffer and the boot info for the panic screen, until then or without a
+/// framebuffer panics are only reported on the serial port.
+pub fn init(boot_info: &BootInfo) {
+    if let Some(framebuffer) = boot_info.framebuffer.as_ref() {
+        SCREEN.call_once(|| Screen {
+            address: framebuffer.start_address,
+            writer: FramebufferWriter::new(framebuffer.info, font_size(&framebuffer.info, boot_info.edid.as_ref())),
+        });
+    }
+
+    HIGHLIGHTS.call_once(|| {
+        let mut bootloader = [0; BOOTLOADER_NAME_LEN];
+        let name = boot_info.extensions.bootloader_name().unwrap_or("unknown bootloader");
+        let mut bootloader_len = name.len().min(BOOTLOADER_NAME_LEN);
+        while !name.is_char_boundary(bootloader_len) {
+            bootloader_len -= 1;
+        }
+        bootloader[..bootloader_len].copy_from_slice(&name.as_bytes()[..bootloader_len]);
+
+        Highlights {
+            bootloader,
+            bootloader_len,
+            paging_mode: boot_info.paging_mode,
+            physical_memory_offset: boot_info.physical_memory_offset.as_ref().copied(),
+            rsdp_address: boot_info.rsdp_address.as_ref().copied(),
+            usable_memory: boot_info
+                .memory_regions
+                .iter()
+                .filter(|region| region.kind == MemoryRegionKind::Usable)
+                .map(|region| region.end - region.start)
+                .sum(),
+        }
+    });
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    let registers = Registers::capture();
+    interrupts::disable();
+
+    // a panic while reporting a panic only gets a line on the serial port
+    if PANICKING.swap(true, Ordering::SeqCst) {
+        let mut serial = unsafe { force_lock_serial() };
+        let _ = writeln!(serial, "\npanicked while panicking: {info}");
+        halt();
+    }
+
+    let mut serial = unsafe { force_lock_serial() };
+    let _ = writeln!(serial);
+    let _ = report(&mut *serial, info, &registers);
+    drop(serial);
+
+    if let Some(screen) = SCREEN.get() {
+        // the kernel is stopped for good, the buffer is not accessed anywhere else anymore
+        let buffer = unsafe {
+            slice::from_raw_parts_mut(screen.address as *mut u8, screen.writer.info.byte_len)
+        };
+        let screen_info = screen.writer.info;
+        let quad = Primitive::Quad(Point { x: 0, y: 0 }, Point { x: screen_info.width, y: screen_info.height });
+        screen.writer.draw_primitive(buffer, quad, BACKGROUND);
+
+        let mut text = ScreenText {
+            writer: &screen.writer,
+            buffer,
+            column: 0,
+            line: 0,
+        };
+        let _ = report(&mut text, info, &registers);
+    }
+
+    halt();
+}
+
+/// The serial port may have been locked by the panicking code, which never runs again, or not be
+/// initialized yet if the kernel panics early.
+unsafe fn force_lock_serial() -> spin::MutexGuard<'static, crate::serial::SerialPort> {
+    if SERIAL.is_locked() {
+        SERIAL.force_unlock();
+    }
+    let mut serial = SERIAL.lock();
+    if !serial.is_present() {
+        serial.init();
+    }
+    serial
+}
+
+fn halt() -> ! {
+    interrupts::disable();
+    loop {
+        hlt();
+    }
+}
+
+fn report(out: &mut impl Write, info: &PanicInfo, registers: &Registers) -> fmt::Result {
+    writeln!(out, "KERNEL PANIC")?;
+    writeln!(out)?;
+    match info.message() {
+        Some(message) => writeln!(out, "{message}")?,
+        None => writeln!(out, "no message")?,
+    }
+    if let Some(location) = info.location() {
+        writeln!(out, "at {}:{}:{}", location.file(), location.line(), location.column())?;
+    }
+    writeln!(out)?;
+
+    if let Some(frame) = INTERRUPTED.get() {
+        writeln!(out, "interrupted at")?;
+        writeln!(
+            out,
+            "rip {:016x}  rsp {:016x}  rflags {:016x}",
+            frame.instruction_pointer.as_u64(),
+            frame.stack_pointer.as_u64(),
+            frame.cpu_flags
+        )?;
+        writeln!(out)?;
+    }
+
+    let r = registers;
+    writeln!(out, "registers at panic handler")?;
+    writeln!(out, "rax {:016x}  rbx {:016x}  rcx {:016x}  rdx {:016x}", r.rax, r.rbx, r.rcx, r.rdx)?;
+    writeln!(out, "rsi {:016x}  rdi {:016x}  rbp {:016x}  rsp {:016x}", r.rsi, r.rdi, r.rbp, r.rsp)?;
+    writeln!(out, "r8  {:016x}  r9  {:016x}  r10 {:016x}  r11 {:016x}", r.r8, r.r9, r.r10, r.r11)?;
+    writeln!(out, "r12 {:016x}  r13 {:016x}  r14 {:016x}  r15 {:016x}", r.r12, r.r13, r.r14, r.r15)?;
+    writeln!(out, "rip {:016x}  rflags {:016x}", r.rip, r.rflags)?;
+    writeln!(out, "cr0 {:016x}  cr2 {:016x}  cr3 {:016x}  cr4 {:016x}", r.cr0, r.cr2, r.cr3, r.cr4)?;
+    writeln!(out)?;
+
+    if let Some(highlights) = HIGHLIGHTS.get() {
+        let bootloader = core::str::from_utf8(&highlights.bootloader[..highlights.bootloader_len]).unwrap_or("");
+        let paging = match highlights.paging_mode {
+            PagingMode::Level4 => "4-level",
+            PagingMode::Level5 => "5-level",
+        };
+        writeln!(out, "booted by {bootloader}, {paging} paging")?;
+        writeln!(
+            out,
+            "{} MiB usable memory, {} processors online",
+            highlights.usable_memory >> 20,
+            ONLINE_PROCESSORS.load(Ordering::SeqCst)
+        )?;
+        if let Some(offset) = highlights.physical_memory_offset {
+            writeln!(out, "physical memory offset {offset:#x}")?;
+        }
+        if let Some(address) = highlights.rsdp_address {
+            writeln!(out, "rsdp {address:#x}")?;
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "the processor has been halted")
+}
+
+/// Writes text line by line from the top left corner, wrapping at the right edge of the screen.
+struct ScreenText<'a> {
+    writer: &'a FramebufferWriter,
+    buffer: &'a mut [u8],
+    column: usize,
+    line: usize,
+}
+
+impl Write for ScreenText<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let (width, height) = self.writer.char_size();
+        // one cell of margin on each side
+        let columns = (self.writer.info.width / width).saturating_sub(2);
+        let lines = (self.writer.info.height / height).saturating_sub(2);
+
+        for c in s.chars() {
+            if c == '\n' || self.column >= columns {
+                self.column = 0;
+                self.line += 1;
+            }
+            if c == '\n' || self.line >= lines {
+                continue;
+            }
+
+            let top_left = Point {
+                x: (self.column + 1) * width,
+                y: self.writer.info.height - 1 - (self.line + 1) * height,
+            };
+            self.writer.draw_char(self.buffer, top_left, c, FOREGROUND);
+            self.column += 1;
+        }
+        Ok(())
+    }
+}
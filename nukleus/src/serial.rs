@@ -0,0 +1,118 @@
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::instructions::port::{Port, PortReadOnly, PortWriteOnly};
+
+const COM1: u16 = 0x3f8;
+/// Divisor of the 115200 Hz base clock, 1 for 115200 baud.
+const BAUD_DIVISOR: u16 = 1;
+
+const LINE_CONTROL_DLAB: u8 = 1 << 7;
+const LINE_CONTROL_8N1: u8 = 0b11;
+const FIFO_ENABLE_AND_CLEAR: u8 = 0b111;
+/// Data terminal ready, request to send and the auxiliary output that gates interrupts.
+const MODEM_CONTROL_READY: u8 = 0b1011;
+const MODEM_CONTROL_LOOPBACK: u8 = 1 << 4;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+pub static SERIAL: Mutex<SerialPort> = Mutex::new(SerialPort::new(COM1));
+
+/// Polled driver for a 16550 UART.
+pub struct SerialPort {
+    data: Port<u8>,
+    interrupt_enable: PortWriteOnly<u8>,
+    fifo_control: PortWriteOnly<u8>,
+    line_control: PortWriteOnly<u8>,
+    modem_control: PortWriteOnly<u8>,
+    line_status: PortReadOnly<u8>,
+    present: bool,
+}
+
+impl SerialPort {
+    pub const fn new(base: u16) -> Self {
+        Self {
+            data: Port::new(base),
+            interrupt_enable: PortWriteOnly::new(base + 1),
+            fifo_control: PortWriteOnly::new(base + 2),
+            line_control: PortWriteOnly::new(base + 3),
+            modem_control: PortWriteOnly::new(base + 4),
+            line_status: PortReadOnly::new(base + 5),
+            present: false,
+        }
+    }
+
+    /// Configures 115200 baud 8N1 without interrupts, returns `false` if no UART answers in
+    /// loopback mode, writes are dropped then.
+    pub fn init(&mut self) -> bool {
+        unsafe {
+            self.interrupt_enable.write(0);
+            self.line_control.write(LINE_CONTROL_DLAB);
+            self.data.write(BAUD_DIVISOR as u8);
+            self.interrupt_enable.write((BAUD_DIVISOR >> 8) as u8);
+            self.line_control.write(LINE_CONTROL_8N1);
+            self.fifo_control.write(FIFO_ENABLE_AND_CLEAR);
+
+            self.modem_control.write(MODEM_CONTROL_READY | MODEM_CONTROL_LOOPBACK);
+            self.data.write(0xae);
+            self.present = self.data.read() == 0xae;
+            self.modem_control.write(MODEM_CONTROL_READY);
+        }
+        self.present
+    }
+
+    /// Whether `init` found a UART, `false` before it was called.
+    pub fn is_present(&self) -> bool {
+        self.present
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        if !self.present {
+            return;
+        }
+
+        unsafe {
+            while self.line_status.read() & LINE_STATUS_TRANSMIT_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.data.write(byte);
+        }
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+pub fn init() -> bool {
+    SERIAL.lock().init()
+}
+
+#[doc(hidden)]
+pub fn _print(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    // an interrupt handler that prints would deadlock on the lock
+    interrupts::without_interrupts(|| {
+        let _ = SERIAL.lock().write_fmt(args);
+    });
+}
+
+#[macro_export]
+macro_rules! print {
+    ($($arg:tt)*) => ($crate::serial::_print(format_args!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! println {
+    () => ($crate::print!("\n"));
+    ($($arg:tt)*) => ($crate::print!("{}\n", format_args!($($arg)*)));
+}
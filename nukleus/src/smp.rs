@@ -0,0 +1,21 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use synapse::smp::{ApMailbox, SmpInfo};
+
+/// Number of processors running kernel code, including the bootstrap processor.
+pub static ONLINE_PROCESSORS: AtomicUsize = AtomicUsize::new(1);
+
+extern "C" fn ap_main(_mailbox: &'static ApMailbox) -> ! {
+    ONLINE_PROCESSORS.fetch_add(1, Ordering::SeqCst);
+
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+pub fn start_application_processors(smp: &SmpInfo) -> usize {
+    smp.mailboxes()
+        .iter()
+        .filter(|mailbox| mailbox.start(ap_main, 0))
+        .count()
+}
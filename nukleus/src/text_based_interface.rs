@@ -1,17 +1,163 @@
-use synapse::framebuffer::Color;
+use core::arch::x86_64::_rdtsc;
+use core::fmt::{self, Write};
+
+use synapse::framebuffer::{Color, Edid, FramebufferInfo, PixelFormat};
+use synapse::timing::{BootTimings, MAX_BOOT_PHASES};
 use crate::text_based_interface::framebuffer_writer::FramebufferWriter;
 use crate::text_based_interface::primitive::{Point, Primitive};
 
 pub mod primitive;
 pub mod framebuffer_writer;
+pub mod font;
+
+const BACKGROUND: Color = Color {
+    red: 221,
+    green: 232,
+    blue: 242,
+};
 
 pub fn draw_background(buffer: &mut [u8], writer: &FramebufferWriter) {
     let info = writer.info;
 
     let quad = Primitive::Quad(Point { x: 0, y: 0 }, Point { x: info.width, y: info.height });
-    writer.draw_primitive(buffer, quad, Color {
-        red: 221,
-        green: 232,
-        blue: 242,
-    });
-}
\ No newline at end of file
+    writer.draw_primitive(buffer, quad, BACKGROUND);
+}
+
+/// Fades whatever the framebuffer shows, e.g. the splash of the bootloader, into the background.
+///
+/// The framebuffer is write-combining, so reading it back is slow. Instead of blending, every
+/// frame switches another sixteenth of the pixels to the background in a 4x4 ordered dither
+/// pattern, which writes each pixel once and never reads. Frames are paced with the time stamp
+/// counter, `tsc_frequency` is 0 if it is unknown.
+pub fn fade_to_background(buffer: &mut [u8], writer: &FramebufferWriter, tsc_frequency: u64) {
+    const FRAME_US: u64 = 20_000;
+    const ORDER: [[usize; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+    let info = writer.info;
+    let target = match info.pixel_format {
+        PixelFormat::Rgb => [BACKGROUND.red, BACKGROUND.green, BACKGROUND.blue],
+        PixelFormat::Bgr => [BACKGROUND.blue, BACKGROUND.green, BACKGROUND.red],
+    };
+
+    let frame_ticks = tsc_frequency / 1_000_000 * FRAME_US;
+    for frame in 0..16 {
+        let start = unsafe { _rdtsc() };
+        for y in 0..info.height {
+            let Some(column) = ORDER[y % 4].iter().position(|&rank| rank == frame) else {
+                continue;
+            };
+            for x in (column..info.width).step_by(4) {
+                let offset = (y * info.stride + x) * info.bytes_per_pixel;
+                buffer[offset..offset + 3].copy_from_slice(&target);
+            }
+        }
+
+        while unsafe { _rdtsc() }.wrapping_sub(start) < frame_ticks {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Draws the boot phases as a timeline along the bottom edge of the screen, each phase
+/// being a segment as wide as its share of the time spent in the bootloader.
+///
+/// Every segment is labeled with the name and the duration of its phase, labels that would
+/// overlap are moved up into another row.
+pub fn draw_boot_timings(buffer: &mut [u8], writer: &FramebufferWriter, timings: &BootTimings) {
+    const HEIGHT: usize = 8;
+    const COLORS: [Color; 4] = [
+        Color { red: 231, green: 76, blue: 60 },
+        Color { red: 241, green: 196, blue: 15 },
+        Color { red: 46, green: 204, blue: 113 },
+        Color { red: 52, green: 152, blue: 219 },
+    ];
+    const LABEL: Color = Color { red: 40, green: 40, blue: 40 };
+
+    let total = timings.kernel_entry.saturating_sub(timings.bootloader_entry) as u128;
+    if total == 0 {
+        return;
+    }
+
+    let width = writer.info.width as u128;
+    let to_x = |timestamp: u64| {
+        let elapsed = timestamp.saturating_sub(timings.bootloader_entry) as u128;
+        (elapsed.min(total) * width / total) as usize
+    };
+
+    let (char_width, char_height) = writer.char_size();
+    // right end of the last label in each row
+    let mut rows = [0; MAX_BOOT_PHASES];
+
+    for (i, phase) in timings.phases().iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let start = to_x(phase.start);
+        let end = to_x(phase.end).max(start + 1);
+
+        let quad = Primitive::Quad(Point { x: start, y: 0 }, Point { x: end, y: HEIGHT });
+        writer.draw_primitive(buffer, quad, color);
+
+        let mut label = Label { buf: [0; 48], len: 0 };
+        let _ = write!(label, "{} {} us", phase.phase.name(), timings.duration_us(phase));
+        let label = label.as_str();
+        let label_start = start.min(writer.info.width.saturating_sub((label.len() + 1) * char_width));
+        let Some(row) = rows.iter().position(|&row_end| row_end <= label_start) else {
+            continue;
+        };
+        rows[row] = label_start + (label.len() + 2) * char_width;
+
+        // a marker in the color of the segment, then the text
+        let top = HEIGHT + (row + 1) * char_height;
+        let marker = Primitive::Quad(
+            Point { x: label_start, y: top - char_height + 2 },
+            Point { x: label_start + char_width / 2, y: top - 2 },
+        );
+        writer.draw_primitive(buffer, marker, color);
+        for (column, c) in label.chars().enumerate() {
+            let top_left = Point { x: label_start + (column + 1) * char_width, y: top - 1 };
+            writer.draw_char(buffer, top_left, c, LABEL);
+        }
+    }
+}
+
+/// Text of a timeline label, formatted without the heap, which depends on the physical
+/// memory mapping.
+struct Label {
+    buf: [u8; 48],
+    len: usize,
+}
+
+impl Label {
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for Label {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.buf.get_mut(self.len..end).ok_or(fmt::Error)?.copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Height in pixels of an 11pt font on the attached monitor.
+///
+/// Without a usable EDID the monitor is assumed to be around 96 dpi.
+pub fn font_size(info: &FramebufferInfo, edid: Option<&Edid>) -> f32 {
+    const POINT_SIZE_MM: f32 = 11.0 * 25.4 / 72.0;
+    const DEFAULT_PIXELS_PER_MM: f32 = 96.0 / 25.4;
+
+    let pixels_per_mm = match edid.and_then(Edid::physical_size_mm) {
+        Some((width_mm, height_mm)) => {
+            let horizontal = info.width as f32 / width_mm as f32;
+            let vertical = info.height as f32 / height_mm as f32;
+
+            (horizontal + vertical) / 2.0
+        }
+        None => DEFAULT_PIXELS_PER_MM,
+    };
+
+    let size = POINT_SIZE_MM * pixels_per_mm;
+    if size < 12.0 { 12.0 } else { size }
+}
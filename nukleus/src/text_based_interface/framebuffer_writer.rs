@@ -3,16 +3,19 @@ use synapse::framebuffer::FramebufferInfo;
 use synapse::framebuffer::PixelFormat;
 use synapse::framebuffer::Color;
 
+use crate::text_based_interface::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
 use crate::text_based_interface::primitive::{Point, Primitive};
 
 pub struct FramebufferWriter {
     pub info: FramebufferInfo,
+    pub font_size: f32,
 }
 
 impl FramebufferWriter {
-    pub fn new(info: FramebufferInfo) -> Self {
+    pub fn new(info: FramebufferInfo, font_size: f32) -> Self {
         Self {
-            info
+            info,
+            font_size,
         }
     }
 
@@ -28,6 +31,32 @@ impl FramebufferWriter {
         }
     }
 
+    /// Pixels per font pixel of the bitmap font, so that text has about `font_size`.
+    pub fn text_scale(&self) -> usize {
+        ((self.font_size / GLYPH_HEIGHT as f32) as usize).max(1)
+    }
+
+    /// Size of a character cell of the bitmap font.
+    pub fn char_size(&self) -> (usize, usize) {
+        (GLYPH_WIDTH * self.text_scale(), GLYPH_HEIGHT * self.text_scale())
+    }
+
+    /// Draws a character of the bitmap font, `top_left` is the top left corner of its cell.
+    pub fn draw_char(&self, buffer: &mut [u8], top_left: Point, c: char, color: Color) {
+        let scale = self.text_scale();
+
+        for (row, bits) in font::glyph(c).iter().enumerate() {
+            for column in (0..GLYPH_WIDTH).filter(|column| bits & (0x80 >> column) != 0) {
+                for dy in 0..scale {
+                    let Some(y) = top_left.y.checked_sub(row * scale + dy) else { continue };
+                    for dx in 0..scale {
+                        self.draw_pixel(buffer, top_left.x + column * scale + dx, y, color);
+                    }
+                }
+            }
+        }
+    }
+
     fn draw_pixel(&self, buffer: &mut [u8], x: usize, y: usize, color: Color) {
         if x < self.info.width && y < self.info.height {
             if self.info.pixel_format == PixelFormat::Rgb {
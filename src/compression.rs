@@ -0,0 +1,28 @@
+use anyhow::Context;
+
+use synapse::compression::{Compression, CompressionHeader};
+
+use crate::file_data::FileDataSource;
+
+pub fn compress(source: &FileDataSource, compression: Compression) -> anyhow::Result<FileDataSource> {
+    let mut data = Vec::new();
+    source
+        .copy_to(&mut data)
+        .with_context(|| format!("failed to read {source:?} for compression"))?;
+
+    let compressed = match compression {
+        Compression::Lz4 => lz4_flex::block::compress(&data),
+        Compression::Deflate => miniz_oxide::deflate::compress_to_vec(&data, 10),
+    };
+
+    let header = CompressionHeader {
+        compression,
+        uncompressed_len: data.len() as u64,
+        compressed_len: compressed.len() as u64,
+    };
+
+    let mut contents = header.to_bytes().to_vec();
+    contents.extend_from_slice(&compressed);
+
+    Ok(FileDataSource::Data(contents))
+}
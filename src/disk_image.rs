@@ -5,18 +5,27 @@ use std::{
 };
 
 use anyhow::Context;
+use ed25519_compact::KeyPair;
+use synapse::boot::{BootConfig, BOOT_CONFIG_FILE_NAME};
+use synapse::compression::Compression;
+use synapse::manifest::SIGNATURE_SUFFIX;
+use synapse::slot::BootSlot;
 use tempfile::NamedTempFile;
 
+use crate::compression::compress;
 use crate::file_data::FileDataSource;
-use crate::fat_fs::create_fat_filesystem;
-use crate::gpt_part::create_gpt_disk;
+use crate::fat_fs::{self, create_fat_filesystem};
+use crate::gpt_part::{self, create_gpt_disk};
+use crate::signing::sign;
 
-pub const KERNEL_FILE_NAME: &str = "kernel-x86_64";
+pub const KERNEL_FILE_NAME: &str = BootSlot::A.kernel_file_name();
 pub const BOOTLOADER_FILE_NAME: &str = "efi/boot/bootx64.efi";
-pub const RAMDISK_FILE_NAME: &str = "ramdisk";
 
 pub struct DiskImageBuilder {
     files: BTreeMap<Cow<'static, str>, FileDataSource>,
+    compression: Option<Compression>,
+    signing_key: Option<KeyPair>,
+    signed_files: Vec<String>,
 }
 
 impl DiskImageBuilder {
@@ -29,6 +38,9 @@ impl DiskImageBuilder {
     pub fn empty() -> Self {
         Self {
             files: BTreeMap::new(),
+            compression: None,
+            signing_key: None,
+            signed_files: Vec::new(),
         }
     }
 
@@ -37,7 +49,39 @@ impl DiskImageBuilder {
     }
 
     pub fn set_ramdisk(&mut self, path: PathBuf) -> &mut Self {
-        self.set_file_source(RAMDISK_FILE_NAME.into(), FileDataSource::File(path))
+        self.set_slot_ramdisk(BootSlot::A, path)
+    }
+
+    /// Sets the kernel of an A/B slot, `set_kernel` sets slot A.
+    pub fn set_slot_kernel(&mut self, slot: BootSlot, path: PathBuf) -> &mut Self {
+        self.set_file_source(slot.kernel_file_name().into(), FileDataSource::File(path))
+    }
+
+    pub fn set_slot_ramdisk(&mut self, slot: BootSlot, path: PathBuf) -> &mut Self {
+        self.set_file_source(slot.ramdisk_file_name().into(), FileDataSource::File(path))
+    }
+
+    /// Compresses the kernel and the ramdisk with the given algorithm when creating the image.
+    pub fn set_compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    pub fn set_boot_config(&mut self, config: &BootConfig) -> &mut Self {
+        self.set_file_source(BOOT_CONFIG_FILE_NAME.into(), FileDataSource::Data(config.to_string().into_bytes()))
+    }
+
+    /// Signs the kernel and the ramdisk with the given key, initium refuses unsigned images.
+    pub fn set_signing_key(&mut self, key_pair: KeyPair) -> &mut Self {
+        self.signing_key = Some(key_pair);
+        self
+    }
+
+    /// Signs a file with a detached manifest next to it, for files that initium verifies outside of
+    /// the kernel slots like Multiboot2 kernels and modules.
+    pub fn sign_file(&mut self, destination: String) -> &mut Self {
+        self.signed_files.push(destination);
+        self
     }
 
     pub fn set_file_contents(&mut self, destination: String, data: Vec<u8>) -> &mut Self {
@@ -62,10 +106,59 @@ impl DiskImageBuilder {
         &self,
         internal_files: BTreeMap<&str, FileDataSource>,
     ) -> anyhow::Result<NamedTempFile> {
+        let mut compressed_files = BTreeMap::new();
+
+        if let Some(compression) = self.compression {
+            let names = BootSlot::ALL
+                .into_iter()
+                .flat_map(|slot| [slot.kernel_file_name(), slot.ramdisk_file_name()]);
+            for name in names {
+                if let Some(source) = self.files.get(name) {
+                    let compressed = compress(source, compression)
+                        .with_context(|| format!("failed to compress `{name}`"))?;
+                    compressed_files.insert(name, compressed);
+                }
+            }
+        }
+
         let mut local_map: BTreeMap<&str, _> = BTreeMap::new();
 
         for (name, source) in &self.files {
-            local_map.insert(name, source);
+            local_map.insert(name, compressed_files.get(name.as_ref()).unwrap_or(source));
+        }
+
+        let key_pair = self
+            .signing_key
+            .as_ref()
+            .context("no signing key set, initium refuses to boot unsigned images")?;
+        if !local_map.contains_key(KERNEL_FILE_NAME) {
+            return Err(anyhow::Error::msg("no kernel set"));
+        }
+
+        let mut manifests = Vec::new();
+        for slot in BootSlot::ALL {
+            let Some(kernel) = local_map.get(slot.kernel_file_name()) else {
+                continue;
+            };
+            let manifest = sign(key_pair, kernel, local_map.get(slot.ramdisk_file_name()).copied())
+                .with_context(|| format!("failed to sign the boot files of slot {slot:?}"))?;
+            manifests.push((slot.manifest_file_name().to_owned(), manifest));
+        }
+        for name in &self.signed_files {
+            let file = local_map
+                .get(name.as_str())
+                .with_context(|| format!("cannot sign `{name}`, it is not in the image"))?;
+            let manifest = sign(key_pair, file, None).with_context(|| format!("failed to sign `{name}`"))?;
+            manifests.push((format!("{name}{SIGNATURE_SUFFIX}"), manifest));
+        }
+
+        for (name, manifest) in &manifests {
+            if local_map.insert(name, manifest).is_some() {
+                return Err(anyhow::Error::msg(format!(
+                    "Attempted to overwrite internal file: {}",
+                    name
+                )));
+            }
         }
 
         for k in &internal_files {
@@ -104,4 +197,11 @@ impl DiskImageBuilder {
 
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Reads a file from the boot partition of a UEFI disk image, e.g. the report of the
+/// diagnostics entry.
+pub fn read_file_from_uefi_image(image_path: &Path, name: &str) -> anyhow::Result<Vec<u8>> {
+    let fat_partition = gpt_part::read_boot_partition(image_path)?;
+    fat_fs::read_file(fat_partition, name)
+}
@@ -0,0 +1,395 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use anyhow::{anyhow, bail, Context};
+use synapse::boot::PagingMode;
+use synapse::dump::{memory_kind_name, virtual_kind_name, PageRange, Record, DUMP_BEGIN, DUMP_END};
+use synapse::memory::{MemoryRegion, MemoryRegionKind, VirtualRegion, VirtualRegionKind};
+
+/// Cells per row of the ASCII maps.
+const MAP_WIDTH: usize = 64;
+/// Height of a map cell in the SVG.
+const SVG_CELL_HEIGHT: u64 = 14;
+
+const PAGE_WRITABLE: u64 = 1 << 1;
+const PAGE_USER: u64 = 1 << 2;
+const PAGE_WRITE_THROUGH: u64 = 1 << 3;
+const PAGE_NO_CACHE: u64 = 1 << 4;
+/// Only for 4 KiB pages, nukleus drops the huge page bit of larger pages.
+const PAGE_ATTRIBUTE_TABLE: u64 = 1 << 7;
+const PAGE_GLOBAL: u64 = 1 << 8;
+const PAGE_NO_EXECUTE: u64 = 1 << 63;
+
+#[derive(Debug, Default)]
+pub struct Dump {
+    pub version: u32,
+    pub size: u32,
+    pub bootloader: Option<String>,
+    /// Vendor, vendor revision and UEFI revision.
+    pub firmware: Option<(String, u32, (u16, u16))>,
+    pub smbios: Option<u64>,
+    pub smbios3: Option<u64>,
+    pub paging: Option<PagingMode>,
+    pub physical_memory_offset: Option<u64>,
+    /// Address, length, width and height.
+    pub framebuffer: Option<(u64, u64, u64, u64)>,
+    pub rsdp: Option<u64>,
+    pub ramdisk: Option<(u64, u64)>,
+    pub memory_regions: Vec<MemoryRegion>,
+    pub virtual_regions: Vec<VirtualRegion>,
+    pub pages: Vec<PageRange>,
+}
+
+impl Dump {
+    /// Decodes the last dump in a serial log, other output around it is ignored.
+    pub fn parse(log: &str) -> anyhow::Result<Self> {
+        let lines: Vec<_> = log.lines().map(|line| line.trim_end_matches('\r')).collect();
+        let begin = lines
+            .iter()
+            .rposition(|&line| line == DUMP_BEGIN)
+            .ok_or_else(|| anyhow!("no `{DUMP_BEGIN}` line, the kernel did not write a dump"))?;
+
+        let mut dump = Dump::default();
+        for (number, &line) in lines.iter().enumerate().skip(begin + 1) {
+            if line == DUMP_END {
+                return Ok(dump);
+            }
+
+            let record = Record::parse(line)
+                .map_err(|err| anyhow!("line {}: {err}: `{line}`", number + 1))?;
+            match record {
+                Record::BootInfo { version, size } => {
+                    dump.version = version;
+                    dump.size = size;
+                }
+                Record::Bootloader(name) => dump.bootloader = Some(name.to_owned()),
+                Record::Firmware { revision, uefi_major, uefi_minor, vendor } => {
+                    dump.firmware = Some((vendor.to_owned(), revision, (uefi_major, uefi_minor)))
+                }
+                Record::Smbios(address) => dump.smbios = Some(address),
+                Record::Smbios3(address) => dump.smbios3 = Some(address),
+                Record::Paging(mode) => dump.paging = Some(mode),
+                Record::PhysicalMemoryOffset(offset) => dump.physical_memory_offset = Some(offset),
+                Record::Framebuffer { address, len, width, height } => {
+                    dump.framebuffer = Some((address, len, width, height))
+                }
+                Record::Rsdp(address) => dump.rsdp = Some(address),
+                Record::Ramdisk { address, len } => dump.ramdisk = Some((address, len)),
+                Record::Memory(region) => dump.memory_regions.push(region),
+                Record::Virtual(region) => dump.virtual_regions.push(region),
+                Record::Page(range) => dump.pages.push(range),
+            }
+        }
+
+        bail!("the dump is truncated, there is no `{DUMP_END}` line")
+    }
+
+    /// Summary and tables of the regions and page ranges.
+    pub fn tables(&self) -> String {
+        let mut out = String::new();
+
+        let bootloader = self.bootloader.as_deref().unwrap_or("unknown bootloader");
+        writeln!(out, "{bootloader}, boot info version {} ({} bytes)", self.version, self.size).unwrap();
+        let paging = match self.paging {
+            Some(PagingMode::Level4) => "4-level",
+            Some(PagingMode::Level5) => "5-level",
+            None => "unknown",
+        };
+        match &self.firmware {
+            Some((vendor, revision, (major, minor))) => {
+                writeln!(out, "firmware:               {vendor} revision {revision:#x}, UEFI {major}.{minor}").unwrap()
+            }
+            None => writeln!(out, "firmware:               unknown").unwrap(),
+        }
+        writeln!(out, "smbios:                 {}", optional_address(self.smbios)).unwrap();
+        writeln!(out, "smbios 3:               {}", optional_address(self.smbios3)).unwrap();
+        writeln!(out, "paging:                 {paging}").unwrap();
+        writeln!(out, "physical memory offset: {}", optional_address(self.physical_memory_offset)).unwrap();
+        match self.framebuffer {
+            Some((address, len, width, height)) => {
+                writeln!(out, "framebuffer:            {address:#018x}, {width}x{height}, {}", size(len)).unwrap()
+            }
+            None => writeln!(out, "framebuffer:            none").unwrap(),
+        }
+        writeln!(out, "rsdp:                   {}", optional_address(self.rsdp)).unwrap();
+        match self.ramdisk {
+            Some((address, len)) => writeln!(out, "ramdisk:                {address:#018x}, {}", size(len)).unwrap(),
+            None => writeln!(out, "ramdisk:                none").unwrap(),
+        }
+
+        writeln!(out, "\nphysical memory").unwrap();
+        writeln!(out, "{:<18} {:<18} {:>10}  kind", "start", "end", "size").unwrap();
+        for region in &self.memory_regions {
+            let kind = match region.kind {
+                MemoryRegionKind::UnknownUefi(memory_type) => format!("uefi ({memory_type})"),
+                kind => memory_kind_name(kind).to_owned(),
+            };
+            writeln!(out, "{:#018x} {:#018x} {:>10}  {kind}", region.start, region.end, size(region.end - region.start)).unwrap();
+        }
+        let usable: u64 = self
+            .memory_regions
+            .iter()
+            .filter(|region| region.kind == MemoryRegionKind::Usable)
+            .map(|region| region.end - region.start)
+            .sum();
+        writeln!(out, "{} usable", size(usable)).unwrap();
+
+        writeln!(out, "\nvirtual regions").unwrap();
+        writeln!(out, "{:<18} {:<18} {:>10} {:>10}  kind", "start", "end", "size", "mapped").unwrap();
+        for region in &self.virtual_regions {
+            let mapped: u64 = self
+                .pages
+                .iter()
+                .map(|range| overlap(range.start, range.end, region.start, region.end))
+                .sum();
+            let len = region.end.wrapping_sub(region.start);
+            let kind = virtual_kind_name(region.kind);
+            writeln!(out, "{:#018x} {:#018x} {:>10} {:>10}  {kind}", region.start, region.end, size(len), size(mapped)).unwrap();
+        }
+
+        writeln!(out, "\npage tables").unwrap();
+        writeln!(out, "{:<18} {:<18} {:<18} {:>10}  flags", "start", "end", "physical", "size").unwrap();
+        for range in &self.pages {
+            let len = range.end.wrapping_sub(range.start);
+            writeln!(
+                out,
+                "{:#018x} {:#018x} {:#018x} {:>10}  {}",
+                range.start, range.end, range.physical_start, size(len), flags(range.flags)
+            )
+            .unwrap();
+        }
+        let unreserved = self
+            .pages
+            .iter()
+            .filter(|range| !self.virtual_regions.iter().any(|region| region.start <= range.start && range.end <= region.end))
+            .count();
+        if unreserved > 0 {
+            writeln!(out, "{unreserved} page ranges are outside of the virtual regions").unwrap();
+        }
+
+        out
+    }
+
+    /// ASCII maps of physical and virtual memory, regions take space by the logarithm of
+    /// their size so that small regions next to large ones stay visible.
+    pub fn ascii_map(&self) -> String {
+        let mut out = String::new();
+
+        writeln!(out, "physical memory").unwrap();
+        write_ascii_map(&mut out, &self.physical_spans());
+        writeln!(out, "  # usable  b bootloader  X bad  u uefi  . hole").unwrap();
+
+        writeln!(out, "\nvirtual memory").unwrap();
+        write_ascii_map(&mut out, &self.virtual_spans());
+        writeln!(
+            out,
+            "  I identity  K kernel  S kernel stack  A ap stack  T tls block  F framebuffer\n  \
+             R ramdisk  P physical memory  B boot info  . unreserved"
+        )
+        .unwrap();
+
+        out
+    }
+
+    /// Both maps side by side as an SVG image.
+    pub fn svg(&self) -> String {
+        let columns = [("physical memory", self.physical_spans()), ("virtual memory", self.virtual_spans())];
+        let column_width = 360;
+        let height = columns
+            .iter()
+            .map(|(_, spans)| layout(spans).iter().map(|(_, weight)| weight).sum::<u64>())
+            .max()
+            .unwrap_or(0)
+            * SVG_CELL_HEIGHT
+            + 40;
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{height}" font-family="monospace" font-size="11">"#,
+            columns.len() * column_width
+        )
+        .unwrap();
+        for (column, (title, spans)) in columns.iter().enumerate() {
+            let x = column * column_width + 10;
+            writeln!(out, r#"<text x="{x}" y="16" font-weight="bold">{title}</text>"#).unwrap();
+
+            let mut y = 28;
+            for (span, weight) in layout(spans) {
+                let h = weight * SVG_CELL_HEIGHT;
+                let (color, label) = match span {
+                    Some(span) => (span.color, span.label),
+                    None => ("#ffffff", "hole"),
+                };
+                let (start, end) = span.map_or((0, 0), |span| (span.start, span.end));
+                writeln!(
+                    out,
+                    r##"<rect x="{x}" y="{y}" width="120" height="{h}" fill="{color}" stroke="#000000"/>"##
+                )
+                .unwrap();
+                if span.is_some() {
+                    writeln!(
+                        out,
+                        r#"<text x="{}" y="{}">{start:#x}-{end:#x} {label}</text>"#,
+                        x + 126,
+                        y + SVG_CELL_HEIGHT.min(h) - 3
+                    )
+                    .unwrap();
+                }
+                y += h;
+            }
+        }
+        writeln!(out, "</svg>").unwrap();
+
+        out
+    }
+
+    fn physical_spans(&self) -> Vec<Span> {
+        self.memory_regions
+            .iter()
+            .map(|region| {
+                let (symbol, color) = match region.kind {
+                    MemoryRegionKind::Usable => ('#', "#8fd694"),
+                    MemoryRegionKind::Bootloader => ('b', "#f2c14e"),
+                    MemoryRegionKind::Bad => ('X', "#e05a47"),
+                    _ => ('u', "#b0b0b0"),
+                };
+                Span {
+                    start: region.start,
+                    end: region.end,
+                    symbol,
+                    color,
+                    label: memory_kind_name(region.kind),
+                }
+            })
+            .collect()
+    }
+
+    fn virtual_spans(&self) -> Vec<Span> {
+        self.virtual_regions
+            .iter()
+            .map(|region| {
+                let (symbol, color) = match region.kind {
+                    VirtualRegionKind::Identity => ('I', "#d0d0d0"),
+                    VirtualRegionKind::Kernel => ('K', "#e05a47"),
+                    VirtualRegionKind::KernelStack => ('S', "#f29e4c"),
+                    VirtualRegionKind::ApStack => ('A', "#f2c14e"),
+                    VirtualRegionKind::TlsBlock => ('T', "#c879ff"),
+                    VirtualRegionKind::Framebuffer => ('F', "#4cb5f5"),
+                    VirtualRegionKind::Ramdisk => ('R', "#8fd694"),
+                    VirtualRegionKind::PhysicalMemory => ('P', "#7f8fa6"),
+                    VirtualRegionKind::BootInfo => ('B', "#ff8fab"),
+                };
+                Span {
+                    start: region.start,
+                    end: region.end,
+                    symbol,
+                    color,
+                    label: virtual_kind_name(region.kind),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reads the serial log at `log`, prints the tables and maps and writes the SVG to `svg` if given.
+pub fn run(log: &Path, svg: Option<&Path>) -> anyhow::Result<()> {
+    let text = fs::read(log).with_context(|| format!("failed to read serial log {}", log.display()))?;
+    let dump = Dump::parse(&String::from_utf8_lossy(&text))?;
+    print!("{}\n{}", dump.tables(), dump.ascii_map());
+
+    if let Some(svg) = svg {
+        fs::write(svg, dump.svg()).with_context(|| format!("failed to write {}", svg.display()))?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: u64,
+    end: u64,
+    symbol: char,
+    color: &'static str,
+    label: &'static str,
+}
+
+/// Sorts the spans and inserts holes, each with a weight of 1 for a page up to 53 for the whole
+/// address space.
+fn layout(spans: &[Span]) -> Vec<(Option<Span>, u64)> {
+    let mut spans = spans.to_vec();
+    spans.sort_by_key(|span| span.start);
+
+    let weight = |len: u64| u64::from(64 - len.leading_zeros()).saturating_sub(12).max(1);
+    let mut layout = Vec::new();
+    let mut end = spans.first().map_or(0, |span| span.start);
+    for span in spans {
+        if span.start > end {
+            layout.push((None, weight(span.start - end)));
+        }
+        layout.push((Some(span), weight(span.end.wrapping_sub(span.start))));
+        end = end.max(span.end);
+    }
+    layout
+}
+
+fn write_ascii_map(out: &mut String, spans: &[Span]) {
+    let mut row = String::new();
+    let mut row_start = None;
+    for (span, weight) in layout(spans) {
+        let symbol = span.map_or('.', |span| span.symbol);
+        for _ in 0..weight {
+            if row.is_empty() {
+                row_start = span.map(|span| span.start);
+            }
+            row.push(symbol);
+            if row.len() == MAP_WIDTH {
+                writeln!(out, "  {} {row}", row_address(row_start)).unwrap();
+                row.clear();
+            }
+        }
+    }
+    if !row.is_empty() {
+        writeln!(out, "  {} {row}", row_address(row_start)).unwrap();
+    }
+}
+
+/// Start of the first region in a row of a map, blank if the row starts with a hole.
+fn row_address(address: Option<u64>) -> String {
+    address.map_or_else(|| " ".repeat(18), |address| format!("{address:#018x}"))
+}
+
+fn overlap(start: u64, end: u64, other_start: u64, other_end: u64) -> u64 {
+    let end = if end == 0 { u64::MAX } else { end };
+    let other_end = if other_end == 0 { u64::MAX } else { other_end };
+    end.min(other_end).saturating_sub(start.max(other_start))
+}
+
+fn optional_address(address: Option<u64>) -> String {
+    address.map_or_else(|| format!("{:<18}", "none"), |address| format!("{address:#018x}"))
+}
+
+fn size(len: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut unit = 0;
+    let mut value = len as f64;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{len} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+fn flags(flags: u64) -> String {
+    let mut out = String::from("R");
+    out.push(if flags & PAGE_WRITABLE != 0 { 'W' } else { '-' });
+    out.push(if flags & PAGE_NO_EXECUTE == 0 { 'X' } else { '-' });
+    for (bit, name) in [(PAGE_USER, " user"), (PAGE_GLOBAL, " global"), (PAGE_WRITE_THROUGH, " write-through"), (PAGE_NO_CACHE, " uncached"), (PAGE_ATTRIBUTE_TABLE, " pat")] {
+        if flags & bit != 0 {
+            out.push_str(name);
+        }
+    }
+    out
+}
@@ -1,6 +1,7 @@
 use anyhow::Context;
 use fatfs::Dir;
 use std::fs::File;
+use std::io::{self, Read};
 use std::{collections::BTreeMap, fs, path::Path};
 
 use crate::file_data::FileDataSource;
@@ -90,4 +91,21 @@ pub fn create_fat_filesystem(
     let root_dir = filesystem.root_dir();
 
     add_files_to_image(&root_dir, files)
-}
\ No newline at end of file
+}
+
+/// Reads a file from a FAT filesystem image held in memory.
+pub fn read_file(fat: Vec<u8>, path: &str) -> anyhow::Result<Vec<u8>> {
+    let fs = fatfs::FileSystem::new(io::Cursor::new(fat), fatfs::FsOptions::new())
+        .context("failed to open FAT filesystem")?;
+
+    let mut file = fs
+        .root_dir()
+        .open_file(path)
+        .with_context(|| format!("failed to open `{path}` on FAT filesystem"))?;
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)
+        .with_context(|| format!("failed to read `{path}` from FAT filesystem"))?;
+
+    Ok(data)
+}
@@ -1,7 +1,7 @@
 use anyhow::Context;
 use std::{
     fs::{self, File},
-    io::{self, Seek},
+    io::{self, Read, Seek},
     path::Path,
 };
 
@@ -67,4 +67,38 @@ pub fn create_gpt_disk(fat_image: &Path, out_gpt_path: &Path) -> anyhow::Result<
         .context("failed to copy FAT image to GPT disk")?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Reads the EFI system partition of a disk image created by `create_gpt_disk`.
+pub fn read_boot_partition(image_path: &Path) -> anyhow::Result<Vec<u8>> {
+    let block_size = disk::LogicalBlockSize::Lb512;
+
+    let gpt = GptConfig::new()
+        .writable(false)
+        .logical_block_size(block_size)
+        .open(image_path)
+        .with_context(|| format!("failed to read GPT of `{}`", image_path.display()))?;
+
+    let partition = gpt
+        .partitions()
+        .values()
+        .find(|partition| partition.part_type_guid == partition_types::EFI)
+        .context("disk image has no EFI system partition")?;
+    let start_offset = partition
+        .bytes_start(block_size)
+        .context("failed to get start offset of boot partition")?;
+    let len = partition
+        .bytes_len(block_size)
+        .context("failed to get length of boot partition")?;
+
+    let mut disk = File::open(image_path)
+        .with_context(|| format!("failed to open `{}`", image_path.display()))?;
+    disk.seek(io::SeekFrom::Start(start_offset))
+        .context("failed to seek to start offset")?;
+
+    let mut data = vec![0; usize::try_from(len)?];
+    disk.read_exact(&mut data)
+        .context("failed to read boot partition")?;
+
+    Ok(data)
+}
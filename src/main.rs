@@ -2,20 +2,120 @@ mod uefi;
 mod file_data;
 mod fat_fs;
 mod gpt_part;
+mod compression;
+mod signing;
+mod dump;
 
 mod disk_image;
 
 use std::path::{Path, PathBuf};
 
+use anyhow::{anyhow, bail, Context};
+use synapse::boot::{BootConfig, DIAGNOSTICS_FILE_NAME};
+use synapse::compression::Compression;
 use uefi::UefiBoot;
 
+const USAGE: &str = "\
+usage: life [--config FILE] [--file NAME=PATH]... [--slot-b-kernel PATH [--slot-b-ramdisk PATH]]
+                                     build the disk image and boot it in QEMU, with a boot config,
+                                     additional files on the boot partition, which are signed for
+                                     Multiboot2 entries, and a fallback kernel in slot B
+       life diagnostics              print the report of the diagnostics entry from the last image
+       life dump [LOG] [--svg FILE]  decode the boot info dump of nukleus from a serial log";
+
+enum Command {
+    Run {
+        config: Option<PathBuf>,
+        files: Vec<(String, PathBuf)>,
+        slot_b_kernel: Option<PathBuf>,
+        slot_b_ramdisk: Option<PathBuf>,
+    },
+    Diagnostics,
+    /// The serial log defaults to the one of the last run.
+    Dump { log: Option<PathBuf>, svg: Option<PathBuf> },
+}
+
+impl Command {
+    fn parse(args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut args = args.peekable();
+        let mut command = match args.peek().map(String::as_str) {
+            Some("diagnostics") => Command::Diagnostics,
+            Some("dump") => Command::Dump { log: None, svg: None },
+            Some(command) if !command.starts_with('-') => bail!("unknown command `{command}`"),
+            _ => Command::Run { config: None, files: Vec::new(), slot_b_kernel: None, slot_b_ramdisk: None },
+        };
+        if !matches!(command, Command::Run { .. }) {
+            args.next();
+        }
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().with_context(|| format!("{arg} needs a value"));
+            match (&mut command, arg.as_str()) {
+                (Command::Run { config, .. }, "--config") => *config = Some(value()?.into()),
+                (Command::Run { files, .. }, "--file") => {
+                    let value = value()?;
+                    let (name, path) = value.split_once('=').context("--file needs `NAME=PATH`")?;
+                    files.push((name.to_owned(), path.into()));
+                }
+                (Command::Run { slot_b_kernel, .. }, "--slot-b-kernel") => *slot_b_kernel = Some(value()?.into()),
+                (Command::Run { slot_b_ramdisk, .. }, "--slot-b-ramdisk") => *slot_b_ramdisk = Some(value()?.into()),
+                (Command::Dump { svg, .. }, "--svg") => *svg = Some(value()?.into()),
+                (Command::Dump { log: log @ None, .. }, _) if !arg.starts_with('-') => *log = Some(arg.into()),
+                _ => bail!("unexpected argument `{arg}`"),
+            }
+        }
+
+        if let Command::Run { slot_b_kernel: None, slot_b_ramdisk: Some(_), .. } = command {
+            bail!("--slot-b-ramdisk needs --slot-b-kernel");
+        }
+
+        Ok(command)
+    }
+}
+
 fn main() {
     let out_dir = PathBuf::from(env!("OUT_DIR"));
     let nukleus = PathBuf::from(env!("CARGO_BIN_FILE_NUKLEUS_nukleus"));
     let initium = PathBuf::from(env!("CARGO_BIN_FILE_INITIUM_initium"));
 
     let uefi_path = out_dir.join("uefi.img");
-    let uefi_boot = UefiBoot::new(&nukleus);
+    let serial_log_path = out_dir.join("serial.log");
+
+    let command = match Command::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(err) => {
+            eprintln!("{err:#}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    let (config, files, slot_b_kernel, slot_b_ramdisk) = match command {
+        Command::Run { config, files, slot_b_kernel, slot_b_ramdisk } => (config, files, slot_b_kernel, slot_b_ramdisk),
+        Command::Diagnostics => {
+            let report = disk_image::read_file_from_uefi_image(&uefi_path, DIAGNOSTICS_FILE_NAME).unwrap();
+            print!("{}", String::from_utf8_lossy(&report));
+            return;
+        }
+        Command::Dump { log, svg } => {
+            dump::run(log.as_deref().unwrap_or(&serial_log_path), svg.as_deref()).unwrap();
+            return;
+        }
+    };
+
+    let key_pair = signing::load_key_pair(Path::new(env!("LIFE_SIGNING_KEY"))).unwrap();
+    let mut uefi_boot = UefiBoot::new(&nukleus);
+    uefi_boot.set_signing_key(key_pair);
+    // LZ4 decompresses fast enough to make up for the smaller reads from the boot partition
+    uefi_boot.set_compression(Compression::Lz4);
+    if let Some(config) = config {
+        uefi_boot.set_boot_config(&load_boot_config(&config).unwrap());
+    }
+    for (name, path) in &files {
+        uefi_boot.set_signed_file(name, path);
+    }
+    if let Some(kernel) = &slot_b_kernel {
+        uefi_boot.set_slot_b(kernel, slot_b_ramdisk.as_deref());
+    }
 
     uefi_boot.create_disk_image(initium.as_path(), &uefi_path).unwrap();
 
@@ -25,7 +125,17 @@ fn main() {
 
     cmd.arg("-bios").arg(ovmf_prebuilt::ovmf_pure_efi());
     cmd.arg("-drive").arg(format!("format=raw,file={uefi_path}"));
+    cmd.arg("-smp").arg("4");
+    cmd.arg("-chardev").arg(format!("stdio,id=serial,logfile={}", serial_log_path.display()));
+    cmd.arg("-serial").arg("chardev:serial");
 
     let mut child = cmd.spawn().unwrap();
     child.wait().unwrap();
-}
\ No newline at end of file
+}
+
+/// Reads a boot config and checks it like initium does, which would fall back to the defaults.
+fn load_boot_config(path: &Path) -> anyhow::Result<BootConfig> {
+    let config = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read boot config {}", path.display()))?;
+    BootConfig::parse(&config).map_err(|err| anyhow!("invalid boot config {}: {err}", path.display()))
+}
@@ -0,0 +1,46 @@
+use std::path::Path;
+
+use anyhow::Context;
+use ed25519_compact::{sha512, KeyPair, Noise, Seed};
+
+use synapse::manifest::Manifest;
+
+use crate::file_data::FileDataSource;
+
+/// Loads a key pair from a file containing a raw 32 byte Ed25519 seed.
+pub fn load_key_pair(path: &Path) -> anyhow::Result<KeyPair> {
+    let seed = std::fs::read(path)
+        .with_context(|| format!("failed to read signing key `{}`", path.display()))?;
+    let seed = Seed::from_slice(&seed)
+        .with_context(|| format!("`{}` is not a raw 32 byte Ed25519 seed", path.display()))?;
+
+    Ok(KeyPair::from_seed(seed))
+}
+
+fn hash(source: &FileDataSource) -> anyhow::Result<[u8; 64]> {
+    let mut data = Vec::new();
+    source
+        .copy_to(&mut data)
+        .with_context(|| format!("failed to read {source:?} for signing"))?;
+
+    Ok(sha512::Hash::hash(data))
+}
+
+pub fn sign(
+    key_pair: &KeyPair,
+    kernel: &FileDataSource,
+    ramdisk: Option<&FileDataSource>,
+) -> anyhow::Result<FileDataSource> {
+    let mut manifest = Manifest {
+        kernel_hash: hash(kernel)?,
+        ramdisk_hash: ramdisk.map(hash).transpose()?,
+        signature: [0; 64],
+    };
+
+    let signature = key_pair
+        .sk
+        .sign(manifest.signed_bytes(), Some(Noise::generate()));
+    manifest.signature = *signature;
+
+    Ok(FileDataSource::Data(manifest.to_bytes().to_vec()))
+}
@@ -1,5 +1,10 @@
 use std::path::Path;
 
+use ed25519_compact::KeyPair;
+use synapse::boot::BootConfig;
+use synapse::compression::Compression;
+use synapse::slot::BootSlot;
+
 use crate::disk_image::DiskImageBuilder;
 
 pub struct UefiBoot {
@@ -18,6 +23,44 @@ impl UefiBoot {
         self
     }
 
+    /// Populates slot B, initium falls back to it when slot A fails to boot.
+    pub fn set_slot_b(&mut self, kernel_path: &Path, ramdisk_path: Option<&Path>) -> &mut Self {
+        self.image_builder.set_slot_kernel(BootSlot::B, kernel_path.to_owned());
+        if let Some(ramdisk_path) = ramdisk_path {
+            self.image_builder.set_slot_ramdisk(BootSlot::B, ramdisk_path.to_owned());
+        }
+        self
+    }
+
+    pub fn set_boot_config(&mut self, config: &BootConfig) -> &mut Self {
+        self.image_builder.set_boot_config(config);
+        self
+    }
+
+    /// Adds a file to the boot partition, e.g. an EFI application referenced by a boot entry.
+    pub fn set_file(&mut self, destination: &str, file_path: &Path) -> &mut Self {
+        self.image_builder.set_file(destination.to_owned(), file_path.to_owned());
+        self
+    }
+
+    /// Adds a file with a detached signature, which initium requires for Multiboot2 kernels and
+    /// modules.
+    pub fn set_signed_file(&mut self, destination: &str, file_path: &Path) -> &mut Self {
+        self.set_file(destination, file_path);
+        self.image_builder.sign_file(destination.to_owned());
+        self
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) -> &mut Self {
+        self.image_builder.set_compression(compression);
+        self
+    }
+
+    pub fn set_signing_key(&mut self, key_pair: KeyPair) -> &mut Self {
+        self.image_builder.set_signing_key(key_pair);
+        self
+    }
+
     pub fn create_disk_image(&self, bootloader_path: &Path, out_path: &Path) -> anyhow::Result<()> {
         self.image_builder.create_uefi_image(bootloader_path, out_path)
     }
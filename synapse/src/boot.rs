@@ -1,34 +1,495 @@
+use core::{fmt, mem::size_of};
+
+use crate::extension::Extensions;
 use crate::optional::Optional;
-use crate::framebuffer::Framebuffer;
-use crate::memory::MemoryRegions;
-use crate::tls_template::TlsTemplate;
+use crate::firmware::FirmwareInfo;
+use crate::framebuffer::{Edid, Framebuffer};
+use crate::memory::{MemoryRegions, VirtualRegions};
+use crate::slot::BootSlotInfo;
+use crate::smp::SmpInfo;
+use crate::timing::BootTimings;
+use crate::tls_template::{TlsBlock, TlsTemplate};
+
+pub const BOOT_CONFIG_FILE_NAME: &str = "boot.cfg";
+/// Report of the diagnostics entry on the boot partition.
+pub const DIAGNOSTICS_FILE_NAME: &str = "diagnostics.txt";
+
+pub const MAX_BOOT_ENTRIES: usize = 8;
+pub const BOOT_ENTRY_NAME_LEN: usize = 32;
+pub const BOOT_ENTRY_PATH_LEN: usize = 128;
+pub const BOOT_ENTRY_CMDLINE_LEN: usize = 128;
+pub const MAX_BOOT_MODULES: usize = 8;
+pub const SPLASH_PATH_LEN: usize = 128;
+pub const KERNEL_DIR_LEN: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum PagingMode {
+    Level4,
+    /// 5-level paging, the level 4 table of the kernel is shared by the first and the last
+    /// level 5 entry, so addresses of the 4-level layout stay valid.
+    Level5,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PhysicalMemoryMapping {
+    /// Maps everything up to the end of the highest memory region, including MMIO.
+    Full,
+    /// Maps everything up to the end of the highest region that is usable RAM after boot.
+    Usable,
+    /// No mapping, `BootInfo::physical_memory_offset` is `None`.
+    Disabled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootEntryKind {
+    /// EFI application on the boot partition, started with `LoadImage`/`StartImage`.
+    EfiApplication,
+    /// Memory test of the bootloader, without a path.
+    MemoryTest,
+    /// Firmware diagnostics report of the bootloader, without a path.
+    Diagnostics,
+    /// Multiboot2 kernel on the boot partition, booted with its command line and modules.
+    Multiboot2,
+}
+
+/// Additional entry of the boot menu, the kernel is always the first entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootEntry {
+    pub kind: BootEntryKind,
+    name: [u8; BOOT_ENTRY_NAME_LEN],
+    name_len: usize,
+    path: [u8; BOOT_ENTRY_PATH_LEN],
+    path_len: usize,
+    cmdline: [u8; BOOT_ENTRY_CMDLINE_LEN],
+    cmdline_len: usize,
+}
+
+impl BootEntry {
+    /// `path` is relative to the root of the boot partition.
+    pub fn new(kind: BootEntryKind, name: &str, path: &str) -> Result<Self, &'static str> {
+        if name.is_empty() || name.len() > BOOT_ENTRY_NAME_LEN {
+            return Err("boot entry name must be 1 to 32 bytes long");
+        }
+        let needs_path = matches!(kind, BootEntryKind::EfiApplication | BootEntryKind::Multiboot2);
+        if (needs_path && path.is_empty()) || path.len() > BOOT_ENTRY_PATH_LEN {
+            return Err("boot entry path must be 1 to 128 bytes long");
+        }
+
+        let mut entry = Self {
+            kind,
+            name: [0; BOOT_ENTRY_NAME_LEN],
+            name_len: name.len(),
+            path: [0; BOOT_ENTRY_PATH_LEN],
+            path_len: path.len(),
+            cmdline: [0; BOOT_ENTRY_CMDLINE_LEN],
+            cmdline_len: 0,
+        };
+        entry.name[..name.len()].copy_from_slice(name.as_bytes());
+        entry.path[..path.len()].copy_from_slice(path.as_bytes());
+
+        Ok(entry)
+    }
+
+    pub fn with_cmdline(mut self, cmdline: &str) -> Result<Self, &'static str> {
+        if cmdline.len() > BOOT_ENTRY_CMDLINE_LEN {
+            return Err("boot entry command line must be at most 128 bytes long");
+        }
+        self.cmdline[..cmdline.len()].copy_from_slice(cmdline.as_bytes());
+        self.cmdline_len = cmdline.len();
+        Ok(self)
+    }
+
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len]).unwrap()
+    }
 
+    pub fn path(&self) -> &str {
+        core::str::from_utf8(&self.path[..self.path_len]).unwrap()
+    }
+
+    /// Command line passed to a Multiboot2 kernel.
+    pub fn cmdline(&self) -> &str {
+        core::str::from_utf8(&self.cmdline[..self.cmdline_len]).unwrap()
+    }
+}
+
+/// Module loaded for a Multiboot2 entry, e.g. an initrd.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootModule {
+    /// Index of the entry in `BootConfig::entries`.
+    entry: usize,
+    path: [u8; BOOT_ENTRY_PATH_LEN],
+    path_len: usize,
+    string: [u8; BOOT_ENTRY_CMDLINE_LEN],
+    string_len: usize,
+}
+
+impl BootModule {
+    const EMPTY: Self = Self {
+        entry: 0,
+        path: [0; BOOT_ENTRY_PATH_LEN],
+        path_len: 0,
+        string: [0; BOOT_ENTRY_CMDLINE_LEN],
+        string_len: 0,
+    };
+
+    pub fn path(&self) -> &str {
+        core::str::from_utf8(&self.path[..self.path_len]).unwrap()
+    }
+
+    /// String the kernel receives with the module, usually its name or arguments.
+    pub fn string(&self) -> &str {
+        core::str::from_utf8(&self.string[..self.string_len]).unwrap()
+    }
+}
+
+/// Boot configuration, stored as `key = value` lines in `BOOT_CONFIG_FILE_NAME`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BootConfig {
     pub framebuffer_width: usize,
     pub framebuffer_height: usize,
+    /// Requested paging mode, 5-level paging is only used if the processor supports it.
+    pub paging_mode: PagingMode,
+    pub physical_memory: PhysicalMemoryMapping,
+    /// Seconds until the boot menu starts the kernel, `0` waits for a key.
+    pub menu_timeout: u64,
+    /// Failed boot attempts of a kernel slot before falling back to the other slot,
+    /// `0` disables the A/B slots.
+    pub boot_attempts: u8,
+    /// Allocate the pages that fail the memory test, so the kernel does not use them.
+    pub memtest_mark_bad: bool,
+    entries: [BootEntry; MAX_BOOT_ENTRIES],
+    entries_len: usize,
+    splash: [u8; SPLASH_PATH_LEN],
+    splash_len: usize,
+    kernel_dir: [u8; KERNEL_DIR_LEN],
+    kernel_dir_len: usize,
+    modules: [BootModule; MAX_BOOT_MODULES],
+    modules_len: usize,
 }
 
+impl Default for BootConfig {
+    fn default() -> Self {
+        Self {
+            framebuffer_width: 1280,
+            framebuffer_height: 720,
+            paging_mode: PagingMode::Level4,
+            physical_memory: PhysicalMemoryMapping::Full,
+            menu_timeout: 5,
+            boot_attempts: 3,
+            memtest_mark_bad: false,
+            entries: [BootEntry {
+                kind: BootEntryKind::EfiApplication,
+                name: [0; BOOT_ENTRY_NAME_LEN],
+                name_len: 0,
+                path: [0; BOOT_ENTRY_PATH_LEN],
+                path_len: 0,
+                cmdline: [0; BOOT_ENTRY_CMDLINE_LEN],
+                cmdline_len: 0,
+            }; MAX_BOOT_ENTRIES],
+            entries_len: 0,
+            splash: [0; SPLASH_PATH_LEN],
+            splash_len: 0,
+            kernel_dir: [0; KERNEL_DIR_LEN],
+            kernel_dir_len: 0,
+            modules: [BootModule::EMPTY; MAX_BOOT_MODULES],
+            modules_len: 0,
+        }
+    }
+}
+
+impl BootConfig {
+    /// Entries of the boot menu besides the kernel, the menu is skipped if there are none.
+    pub fn entries(&self) -> &[BootEntry] {
+        &self.entries[..self.entries_len]
+    }
+
+    pub fn push_entry(&mut self, entry: BootEntry) -> Result<(), &'static str> {
+        let slot = self.entries.get_mut(self.entries_len).ok_or("too many boot entries")?;
+        *slot = entry;
+        self.entries_len += 1;
+        Ok(())
+    }
+
+    /// Modules of `entry`, which must be one of `entries`.
+    pub fn modules<'a>(&'a self, entry: &BootEntry) -> impl Iterator<Item = &'a BootModule> {
+        let index = self.entries().iter().position(|e| core::ptr::eq(e, entry));
+        self.modules[..self.modules_len]
+            .iter()
+            .filter(move |module| Some(module.entry) == index)
+    }
+
+    /// Adds a module to the last entry, which must be a Multiboot2 entry.
+    pub fn push_module(&mut self, path: &str, string: &str) -> Result<(), &'static str> {
+        let entry = self
+            .entries_len
+            .checked_sub(1)
+            .filter(|&index| self.entries[index].kind == BootEntryKind::Multiboot2)
+            .ok_or("multiboot_module must follow a multiboot_entry")?;
+        if path.is_empty() || path.len() > BOOT_ENTRY_PATH_LEN {
+            return Err("module path must be 1 to 128 bytes long");
+        }
+        if string.len() > BOOT_ENTRY_CMDLINE_LEN {
+            return Err("module string must be at most 128 bytes long");
+        }
+
+        let module = self.modules.get_mut(self.modules_len).ok_or("too many modules")?;
+        *module = BootModule::EMPTY;
+        module.entry = entry;
+        module.path[..path.len()].copy_from_slice(path.as_bytes());
+        module.path_len = path.len();
+        module.string[..string.len()].copy_from_slice(string.as_bytes());
+        module.string_len = string.len();
+        self.modules_len += 1;
+        Ok(())
+    }
+
+    /// BMP or QOI image on the boot partition that is shown while the kernel loads.
+    pub fn splash(&self) -> Option<&str> {
+        (self.splash_len > 0).then(|| core::str::from_utf8(&self.splash[..self.splash_len]).unwrap())
+    }
+
+    pub fn set_splash(&mut self, path: &str) -> Result<(), &'static str> {
+        if path.is_empty() || path.len() > SPLASH_PATH_LEN {
+            return Err("splash path must be 1 to 128 bytes long");
+        }
+        self.splash[..path.len()].copy_from_slice(path.as_bytes());
+        self.splash_len = path.len();
+        Ok(())
+    }
+
+    /// Directory of the kernel, ramdisk and manifest of both slots, empty for the root of the
+    /// boot partition. A `(label:NAME)` or `(guid:PARTITION-GUID)` prefix selects another volume.
+    pub fn kernel_dir(&self) -> &str {
+        core::str::from_utf8(&self.kernel_dir[..self.kernel_dir_len]).unwrap()
+    }
+
+    pub fn set_kernel_dir(&mut self, dir: &str) -> Result<(), &'static str> {
+        if dir.len() > KERNEL_DIR_LEN {
+            return Err("kernel_dir must be at most 128 bytes long");
+        }
+        self.kernel_dir[..dir.len()].copy_from_slice(dir.as_bytes());
+        self.kernel_dir_len = dir.len();
+        Ok(())
+    }
+
+    /// Parses a configuration file, missing keys keep their default value.
+    pub fn parse(config: &str) -> Result<Self, &'static str> {
+        let mut parsed = Self::default();
+
+        for line in config.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = line.split_once('=').ok_or("expected `key = value`")?;
+            let value = value.trim();
+
+            match key.trim() {
+                "framebuffer_width" => {
+                    parsed.framebuffer_width = value.parse().map_err(|_| "invalid framebuffer_width")?
+                }
+                "framebuffer_height" => {
+                    parsed.framebuffer_height = value.parse().map_err(|_| "invalid framebuffer_height")?
+                }
+                "paging" => {
+                    parsed.paging_mode = match value {
+                        "4-level" => PagingMode::Level4,
+                        "5-level" => PagingMode::Level5,
+                        _ => return Err("paging must be `4-level` or `5-level`"),
+                    }
+                }
+                "physical_memory" => {
+                    parsed.physical_memory = match value {
+                        "full" => PhysicalMemoryMapping::Full,
+                        "usable" => PhysicalMemoryMapping::Usable,
+                        "none" => PhysicalMemoryMapping::Disabled,
+                        _ => return Err("physical_memory must be `full`, `usable` or `none`"),
+                    }
+                }
+                "menu_timeout" => {
+                    parsed.menu_timeout = value.parse().map_err(|_| "invalid menu_timeout")?
+                }
+                "boot_attempts" => {
+                    parsed.boot_attempts = value.parse().map_err(|_| "invalid boot_attempts")?
+                }
+                "efi_entry" => {
+                    let (name, path) = value
+                        .split_once(',')
+                        .ok_or("efi_entry must be `name, path`")?;
+                    parsed.push_entry(BootEntry::new(BootEntryKind::EfiApplication, name.trim(), path.trim())?)?;
+                }
+                "multiboot_entry" => {
+                    let (name, rest) = value
+                        .split_once(',')
+                        .ok_or("multiboot_entry must be `name, path` or `name, path, command line`")?;
+                    let (path, cmdline) = rest.split_once(',').unwrap_or((rest, ""));
+                    let entry = BootEntry::new(BootEntryKind::Multiboot2, name.trim(), path.trim())?
+                        .with_cmdline(cmdline.trim())?;
+                    parsed.push_entry(entry)?;
+                }
+                "multiboot_module" => {
+                    let (path, string) = value.split_once(',').unwrap_or((value, ""));
+                    parsed.push_module(path.trim(), string.trim())?;
+                }
+                "memtest_entry" => {
+                    parsed.push_entry(BootEntry::new(BootEntryKind::MemoryTest, value, "")?)?;
+                }
+                "diagnostics_entry" => {
+                    parsed.push_entry(BootEntry::new(BootEntryKind::Diagnostics, value, "")?)?;
+                }
+                "memtest_mark_bad" => {
+                    parsed.memtest_mark_bad = value.parse().map_err(|_| "memtest_mark_bad must be `true` or `false`")?
+                }
+                "splash" => parsed.set_splash(value)?,
+                "kernel_dir" => parsed.set_kernel_dir(value)?,
+                _ => return Err("unknown key"),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl fmt::Display for BootConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "framebuffer_width = {}", self.framebuffer_width)?;
+        writeln!(f, "framebuffer_height = {}", self.framebuffer_height)?;
+        let paging = match self.paging_mode {
+            PagingMode::Level4 => "4-level",
+            PagingMode::Level5 => "5-level",
+        };
+        writeln!(f, "paging = {paging}")?;
+        let physical_memory = match self.physical_memory {
+            PhysicalMemoryMapping::Full => "full",
+            PhysicalMemoryMapping::Usable => "usable",
+            PhysicalMemoryMapping::Disabled => "none",
+        };
+        writeln!(f, "physical_memory = {physical_memory}")?;
+        writeln!(f, "menu_timeout = {}", self.menu_timeout)?;
+        writeln!(f, "boot_attempts = {}", self.boot_attempts)?;
+        writeln!(f, "memtest_mark_bad = {}", self.memtest_mark_bad)?;
+        if !self.kernel_dir().is_empty() {
+            writeln!(f, "kernel_dir = {}", self.kernel_dir())?;
+        }
+        if let Some(splash) = self.splash() {
+            writeln!(f, "splash = {splash}")?;
+        }
+        for entry in self.entries() {
+            match entry.kind {
+                BootEntryKind::EfiApplication => writeln!(f, "efi_entry = {}, {}", entry.name(), entry.path())?,
+                BootEntryKind::MemoryTest => writeln!(f, "memtest_entry = {}", entry.name())?,
+                BootEntryKind::Diagnostics => writeln!(f, "diagnostics_entry = {}", entry.name())?,
+                BootEntryKind::Multiboot2 => {
+                    write!(f, "multiboot_entry = {}, {}", entry.name(), entry.path())?;
+                    if !entry.cmdline().is_empty() {
+                        write!(f, ", {}", entry.cmdline())?;
+                    }
+                    writeln!(f)?;
+                    for module in self.modules(entry) {
+                        write!(f, "multiboot_module = {}", module.path())?;
+                        if !module.string().is_empty() {
+                            write!(f, ", {}", module.string())?;
+                        }
+                        writeln!(f)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `LIFEBOOT` in ASCII.
+pub const BOOT_INFO_MAGIC: u64 = u64::from_le_bytes(*b"LIFEBOOT");
+/// Changed whenever the layout of the existing `BootInfo` fields changes, new information is
+/// passed in `BootInfo::extensions` instead.
+pub const BOOT_INFO_VERSION: u32 = 1;
+
+/// Start of `BootInfo`, which kernels check before using anything else.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct BootInfoHeader {
+    pub magic: u64,
+    pub version: u32,
+    /// Size of the `BootInfo` the bootloader was built with.
+    pub size: u32,
+}
+
+impl BootInfoHeader {
+    pub const fn current() -> Self {
+        Self {
+            magic: BOOT_INFO_MAGIC,
+            version: BOOT_INFO_VERSION,
+            size: size_of::<BootInfo>() as u32,
+        }
+    }
+
+    /// Checks that the boot info was written for the layout this crate was built with.
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.magic != BOOT_INFO_MAGIC {
+            Err("boot info has no valid magic number, the kernel was not started by initium")
+        } else if self.version != BOOT_INFO_VERSION {
+            Err("boot info version differs, initium and the kernel were built with different versions of synapse")
+        } else if (self.size as usize) < size_of::<BootInfo>() {
+            Err("boot info is smaller than expected, initium and the kernel were built with different versions of synapse")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[repr(C)]
 pub struct BootInfo {
+    pub header: BootInfoHeader,
     pub memory_regions: MemoryRegions,
+    /// Layout of the kernel address space as set up by the bootloader.
+    pub virtual_regions: VirtualRegions,
     pub framebuffer: Optional<Framebuffer>,
     pub physical_memory_offset: Optional<u64>,
+    pub paging_mode: PagingMode,
     pub rsdp_address: Optional<u64>,
+    pub smbios_address: Optional<u64>,
+    pub smbios3_address: Optional<u64>,
+    pub firmware: FirmwareInfo,
+    pub edid: Optional<Edid>,
     pub tls_template: Optional<TlsTemplate>,
+    pub tls_block: Optional<TlsBlock>,
     pub ramdisk_address: Optional<u64>,
     pub ramdisk_len: u64,
+    pub smp: Optional<SmpInfo>,
+    pub timings: BootTimings,
+    /// A/B slot the kernel was loaded from, `None` if slots are disabled in the boot config.
+    pub boot_slot: Optional<BootSlotInfo>,
+    /// The framebuffer still shows the splash image of the bootloader.
+    pub splash_shown: bool,
+    pub extensions: Extensions,
 }
 
 impl BootInfo {
-    pub fn new(memory_regions: MemoryRegions) -> Self {
+    pub fn new(memory_regions: MemoryRegions, virtual_regions: VirtualRegions) -> Self {
         Self {
+            header: BootInfoHeader::current(),
             memory_regions,
+            virtual_regions,
             framebuffer: Optional::None,
             physical_memory_offset: Optional::None,
+            paging_mode: PagingMode::Level4,
             rsdp_address: Optional::None,
+            smbios_address: Optional::None,
+            smbios3_address: Optional::None,
+            firmware: FirmwareInfo::empty(),
+            edid: Optional::None,
             tls_template: Optional::None,
+            tls_block: Optional::None,
             ramdisk_address: Optional::None,
             ramdisk_len: 0,
+            smp: Optional::None,
+            timings: BootTimings::new(0),
+            boot_slot: Optional::None,
+            splash_shown: false,
+            extensions: Extensions::empty(),
         }
     }
 }
-
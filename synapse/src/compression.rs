@@ -0,0 +1,58 @@
+pub const COMPRESSION_MAGIC: [u8; 8] = *b"LIFE.CMP";
+pub const COMPRESSION_HEADER_LEN: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Compression {
+    /// LZ4 block format, without the frame.
+    Lz4 = 1,
+    /// Raw deflate stream, without zlib or gzip header.
+    Deflate = 2,
+}
+
+/// Header prepended by the host tool to compressed files of the boot partition.
+///
+/// All fields are little-endian:
+/// magic (8 bytes), compression (u32), reserved (u32), uncompressed length (u64),
+/// compressed length (u64).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionHeader {
+    pub compression: Compression,
+    pub uncompressed_len: u64,
+    pub compressed_len: u64,
+}
+
+impl CompressionHeader {
+    /// Returns `None` if `data` does not start with a compression header.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < COMPRESSION_HEADER_LEN || data[..8] != COMPRESSION_MAGIC {
+            return None;
+        }
+
+        let u32_at = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let u64_at = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        let compression = match u32_at(8) {
+            1 => Compression::Lz4,
+            2 => Compression::Deflate,
+            _ => return None,
+        };
+
+        Some(Self {
+            compression,
+            uncompressed_len: u64_at(16),
+            compressed_len: u64_at(24),
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; COMPRESSION_HEADER_LEN] {
+        let mut bytes = [0; COMPRESSION_HEADER_LEN];
+
+        bytes[..8].copy_from_slice(&COMPRESSION_MAGIC);
+        bytes[8..12].copy_from_slice(&(self.compression as u32).to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.uncompressed_len.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.compressed_len.to_le_bytes());
+
+        bytes
+    }
+}
@@ -0,0 +1,209 @@
+use core::fmt;
+
+use crate::boot::PagingMode;
+use crate::memory::{MemoryRegion, MemoryRegionKind, VirtualRegion, VirtualRegionKind};
+
+/// First line of a dump, the number is changed whenever a record changes its meaning.
+pub const DUMP_BEGIN: &str = "life-dump 1";
+pub const DUMP_END: &str = "life-dump end";
+
+/// Contiguous virtual range mapped to contiguous physical memory with the same flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PageRange {
+    pub start: u64,
+    pub end: u64,
+    pub physical_start: u64,
+    /// Raw x86_64 page table entry flags of the last level.
+    pub flags: u64,
+}
+
+/// One line of a dump of the `BootInfo` and the page tables a kernel was started with.
+///
+/// Records are written with `Display` and read with `parse`, numbers are hexadecimal except
+/// for sizes of the boot info and the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Record<'a> {
+    BootInfo { version: u32, size: u32 },
+    Bootloader(&'a str),
+    Firmware { revision: u32, uefi_major: u16, uefi_minor: u16, vendor: &'a str },
+    Smbios(u64),
+    Smbios3(u64),
+    Paging(PagingMode),
+    PhysicalMemoryOffset(u64),
+    Framebuffer { address: u64, len: u64, width: u64, height: u64 },
+    Rsdp(u64),
+    Ramdisk { address: u64, len: u64 },
+    Memory(MemoryRegion),
+    Virtual(VirtualRegion),
+    Page(PageRange),
+}
+
+pub fn memory_kind_name(kind: MemoryRegionKind) -> &'static str {
+    match kind {
+        MemoryRegionKind::Usable => "usable",
+        MemoryRegionKind::Bootloader => "bootloader",
+        MemoryRegionKind::Bad => "bad",
+        MemoryRegionKind::UnknownUefi(_) => "uefi",
+    }
+}
+
+pub fn virtual_kind_name(kind: VirtualRegionKind) -> &'static str {
+    match kind {
+        VirtualRegionKind::Identity => "identity",
+        VirtualRegionKind::Kernel => "kernel",
+        VirtualRegionKind::KernelStack => "kernel-stack",
+        VirtualRegionKind::ApStack => "ap-stack",
+        VirtualRegionKind::TlsBlock => "tls-block",
+        VirtualRegionKind::Framebuffer => "framebuffer",
+        VirtualRegionKind::Ramdisk => "ramdisk",
+        VirtualRegionKind::PhysicalMemory => "physical-memory",
+        VirtualRegionKind::BootInfo => "boot-info",
+    }
+}
+
+fn parse_memory_kind(name: &str) -> Result<MemoryRegionKind, &'static str> {
+    Ok(match name {
+        "usable" => MemoryRegionKind::Usable,
+        "bootloader" => MemoryRegionKind::Bootloader,
+        "bad" => MemoryRegionKind::Bad,
+        _ => {
+            let memory_type = name.strip_prefix("uefi:").ok_or("unknown memory region kind")?;
+            MemoryRegionKind::UnknownUefi(memory_type.parse().map_err(|_| "invalid UEFI memory type")?)
+        }
+    })
+}
+
+fn parse_virtual_kind(name: &str) -> Result<VirtualRegionKind, &'static str> {
+    Ok(match name {
+        "identity" => VirtualRegionKind::Identity,
+        "kernel" => VirtualRegionKind::Kernel,
+        "kernel-stack" => VirtualRegionKind::KernelStack,
+        "ap-stack" => VirtualRegionKind::ApStack,
+        "tls-block" => VirtualRegionKind::TlsBlock,
+        "framebuffer" => VirtualRegionKind::Framebuffer,
+        "ramdisk" => VirtualRegionKind::Ramdisk,
+        "physical-memory" => VirtualRegionKind::PhysicalMemory,
+        "boot-info" => VirtualRegionKind::BootInfo,
+        _ => return Err("unknown virtual region kind"),
+    })
+}
+
+fn hex(value: Option<&str>) -> Result<u64, &'static str> {
+    let value = value.ok_or("missing value")?;
+    let digits = value.strip_prefix("0x").ok_or("expected a hexadecimal number")?;
+    u64::from_str_radix(digits, 16).map_err(|_| "invalid hexadecimal number")
+}
+
+fn decimal<T: core::str::FromStr>(value: Option<&str>) -> Result<T, &'static str> {
+    value.ok_or("missing value")?.parse().map_err(|_| "invalid number")
+}
+
+impl<'a> Record<'a> {
+    /// Parses a line without its line break.
+    pub fn parse(line: &'a str) -> Result<Self, &'static str> {
+        let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let mut values = rest.split(' ');
+
+        let record = match key {
+            "boot_info" => Record::BootInfo {
+                version: decimal(values.next())?,
+                size: decimal(values.next())?,
+            },
+            // the name may contain spaces
+            "bootloader" => return Ok(Record::Bootloader(rest)),
+            "firmware" => {
+                // the vendor comes last, it may contain spaces
+                let mut values = rest.splitn(3, ' ');
+                let revision = hex(values.next())?;
+                let (major, minor) = values
+                    .next()
+                    .and_then(|uefi| uefi.split_once('.'))
+                    .ok_or("expected a UEFI revision like `2.70`")?;
+                return Ok(Record::Firmware {
+                    revision: u32::try_from(revision).map_err(|_| "firmware revision is too large")?,
+                    uefi_major: decimal(Some(major))?,
+                    uefi_minor: decimal(Some(minor))?,
+                    vendor: values.next().unwrap_or(""),
+                });
+            }
+            "smbios" => Record::Smbios(hex(values.next())?),
+            "smbios3" => Record::Smbios3(hex(values.next())?),
+            "paging" => Record::Paging(match values.next() {
+                Some("4-level") => PagingMode::Level4,
+                Some("5-level") => PagingMode::Level5,
+                _ => return Err("paging must be `4-level` or `5-level`"),
+            }),
+            "physical_memory_offset" => Record::PhysicalMemoryOffset(hex(values.next())?),
+            "framebuffer" => Record::Framebuffer {
+                address: hex(values.next())?,
+                len: hex(values.next())?,
+                width: decimal(values.next())?,
+                height: decimal(values.next())?,
+            },
+            "rsdp" => Record::Rsdp(hex(values.next())?),
+            "ramdisk" => Record::Ramdisk {
+                address: hex(values.next())?,
+                len: hex(values.next())?,
+            },
+            "memory" => Record::Memory(MemoryRegion {
+                start: hex(values.next())?,
+                end: hex(values.next())?,
+                kind: parse_memory_kind(values.next().ok_or("missing value")?)?,
+            }),
+            "virtual" => Record::Virtual(VirtualRegion {
+                start: hex(values.next())?,
+                end: hex(values.next())?,
+                kind: parse_virtual_kind(values.next().ok_or("missing value")?)?,
+            }),
+            "page" => Record::Page(PageRange {
+                start: hex(values.next())?,
+                end: hex(values.next())?,
+                physical_start: hex(values.next())?,
+                flags: hex(values.next())?,
+            }),
+            _ => return Err("unknown record"),
+        };
+
+        match values.next() {
+            None => Ok(record),
+            Some(_) => Err("unexpected value after the record"),
+        }
+    }
+}
+
+impl fmt::Display for Record<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Record::BootInfo { version, size } => write!(f, "boot_info {version} {size}"),
+            Record::Bootloader(name) => write!(f, "bootloader {name}"),
+            Record::Firmware { revision, uefi_major, uefi_minor, vendor } => {
+                write!(f, "firmware {revision:#x} {uefi_major}.{uefi_minor} {vendor}")
+            }
+            Record::Smbios(address) => write!(f, "smbios {address:#x}"),
+            Record::Smbios3(address) => write!(f, "smbios3 {address:#x}"),
+            Record::Paging(PagingMode::Level4) => write!(f, "paging 4-level"),
+            Record::Paging(PagingMode::Level5) => write!(f, "paging 5-level"),
+            Record::PhysicalMemoryOffset(offset) => write!(f, "physical_memory_offset {offset:#x}"),
+            Record::Framebuffer { address, len, width, height } => {
+                write!(f, "framebuffer {address:#x} {len:#x} {width} {height}")
+            }
+            Record::Rsdp(address) => write!(f, "rsdp {address:#x}"),
+            Record::Ramdisk { address, len } => write!(f, "ramdisk {address:#x} {len:#x}"),
+            Record::Memory(region) => {
+                write!(f, "memory {:#x} {:#x} ", region.start, region.end)?;
+                match region.kind {
+                    MemoryRegionKind::UnknownUefi(memory_type) => write!(f, "uefi:{memory_type}"),
+                    kind => f.write_str(memory_kind_name(kind)),
+                }
+            }
+            Record::Virtual(region) => {
+                write!(f, "virtual {:#x} {:#x} {}", region.start, region.end, virtual_kind_name(region.kind))
+            }
+            Record::Page(range) => write!(
+                f,
+                "page {:#x} {:#x} {:#x} {:#x}",
+                range.start, range.end, range.physical_start, range.flags
+            ),
+        }
+    }
+}
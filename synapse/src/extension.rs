@@ -0,0 +1,122 @@
+use core::{mem::size_of, slice, str};
+
+/// Name and version of the bootloader as UTF-8, e.g. `initium 0.1.0`.
+pub const TAG_BOOTLOADER_NAME: u32 = 1;
+
+/// Records are padded to this alignment.
+const RECORD_ALIGN: usize = 8;
+
+/// Header of a tagged record, followed by `size` bytes of data and padding to 8 bytes.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct ExtensionHeader {
+    pub tag: u32,
+    pub size: u32,
+}
+
+/// Tagged records after the fixed part of `BootInfo`.
+///
+/// Fields added after version 1 of the boot info are passed as records, kernels skip tags they
+/// do not know and find out about missing records instead of reading garbage.
+#[derive(Debug)]
+#[repr(C)]
+pub struct Extensions {
+    pub ptr: *const u8,
+    pub len: usize,
+}
+
+impl Extensions {
+    pub const fn empty() -> Self {
+        Self {
+            ptr: core::ptr::null(),
+            len: 0,
+        }
+    }
+
+    /// Bytes needed for a record with `data_len` bytes of data.
+    pub const fn record_size(data_len: usize) -> usize {
+        size_of::<ExtensionHeader>() + (data_len + RECORD_ALIGN - 1) / RECORD_ALIGN * RECORD_ALIGN
+    }
+
+    pub fn iter(&self) -> ExtensionIter<'_> {
+        let bytes = if self.ptr.is_null() {
+            &[]
+        } else {
+            unsafe { slice::from_raw_parts(self.ptr, self.len) }
+        };
+        ExtensionIter { bytes }
+    }
+
+    /// Data of the first record with `tag`.
+    pub fn get(&self, tag: u32) -> Option<&[u8]> {
+        self.iter().find(|extension| extension.tag == tag).map(|extension| extension.data)
+    }
+
+    pub fn bootloader_name(&self) -> Option<&str> {
+        self.get(TAG_BOOTLOADER_NAME).and_then(|data| str::from_utf8(data).ok())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Extension<'a> {
+    pub tag: u32,
+    pub data: &'a [u8],
+}
+
+pub struct ExtensionIter<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for ExtensionIter<'a> {
+    type Item = Extension<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.bytes.get(..size_of::<ExtensionHeader>())?;
+        let tag = u32::from_ne_bytes(header[..4].try_into().unwrap());
+        let size = u32::from_ne_bytes(header[4..].try_into().unwrap()) as usize;
+
+        // a truncated record ends the list
+        let record_size = Extensions::record_size(size);
+        let Some(data) = self.bytes.get(size_of::<ExtensionHeader>()..size_of::<ExtensionHeader>() + size) else {
+            self.bytes = &[];
+            return None;
+        };
+        self.bytes = self.bytes.get(record_size..).unwrap_or(&[]);
+
+        Some(Extension { tag, data })
+    }
+}
+
+/// Writes records into the buffer that `Extensions` points to.
+pub struct ExtensionWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> ExtensionWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Appends a record, returns `false` if it does not fit.
+    pub fn push(&mut self, tag: u32, data: &[u8]) -> bool {
+        let record_size = Extensions::record_size(data.len());
+        let Some(record) = self.buf.get_mut(self.len..self.len + record_size) else {
+            return false;
+        };
+
+        record[..4].copy_from_slice(&tag.to_ne_bytes());
+        record[4..8].copy_from_slice(&(data.len() as u32).to_ne_bytes());
+        record[8..8 + data.len()].copy_from_slice(data);
+        record[8 + data.len()..].fill(0);
+        self.len += record_size;
+        true
+    }
+
+    pub fn finish(self) -> Extensions {
+        Extensions {
+            ptr: self.buf.as_ptr(),
+            len: self.len,
+        }
+    }
+}
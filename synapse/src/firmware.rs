@@ -0,0 +1,33 @@
+use core::str;
+
+pub const FIRMWARE_VENDOR_MAX_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct FirmwareInfo {
+    /// UTF-8 encoded vendor string, truncated to `FIRMWARE_VENDOR_MAX_LEN` bytes.
+    pub vendor: [u8; FIRMWARE_VENDOR_MAX_LEN],
+    pub vendor_len: usize,
+
+    /// Vendor specific revision of the firmware.
+    pub revision: u32,
+
+    pub uefi_major: u16,
+    pub uefi_minor: u16,
+}
+
+impl FirmwareInfo {
+    pub const fn empty() -> Self {
+        Self {
+            vendor: [0; FIRMWARE_VENDOR_MAX_LEN],
+            vendor_len: 0,
+            revision: 0,
+            uefi_major: 0,
+            uefi_minor: 0,
+        }
+    }
+
+    pub fn vendor(&self) -> &str {
+        str::from_utf8(&self.vendor[..self.vendor_len]).unwrap_or("")
+    }
+}
@@ -7,6 +7,16 @@ pub enum PixelFormat {
     Bgr,
 }
 
+/// Memory type the bootloader mapped the framebuffer with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum CachingMode {
+    /// Left to the memory type range registers of the firmware, usually uncached.
+    Default,
+    /// Write-combining through the page attribute table.
+    WriteCombining,
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Color {
     pub red: u8,
@@ -25,6 +35,8 @@ pub struct FramebufferInfo {
     pub byte_len: usize,
     pub bytes_per_pixel: usize,
     pub stride: usize,
+
+    pub caching: CachingMode,
 }
 
 #[repr(C)]
@@ -62,3 +74,54 @@ impl Framebuffer {
         self.info
     }
 }
+
+pub const EDID_BLOCK_LEN: usize = 128;
+
+/// Base block of the EDID reported by the monitor attached to the framebuffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct Edid {
+    pub bytes: [u8; EDID_BLOCK_LEN],
+}
+
+impl Edid {
+    const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+
+    pub fn new(bytes: [u8; EDID_BLOCK_LEN]) -> Self {
+        Self { bytes }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        let checksum = self.bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+
+        self.bytes[..8] == Self::HEADER && checksum == 0
+    }
+
+    /// Physical size of the display area as `(width, height)` in millimeters.
+    ///
+    /// The preferred detailed timing descriptor is used when present since it is
+    /// precise to the millimeter, otherwise the centimeter sizes of the basic
+    /// display parameters are used.
+    pub fn physical_size_mm(&self) -> Option<(usize, usize)> {
+        if !self.is_valid() {
+            return None;
+        }
+
+        let timing = &self.bytes[54..72];
+        let pixel_clock = u16::from_le_bytes([timing[0], timing[1]]);
+
+        if pixel_clock != 0 {
+            let width = timing[12] as usize | ((timing[14] as usize & 0xf0) << 4);
+            let height = timing[13] as usize | ((timing[14] as usize & 0x0f) << 8);
+
+            if width != 0 && height != 0 {
+                return Some((width, height));
+            }
+        }
+
+        match (self.bytes[21] as usize, self.bytes[22] as usize) {
+            (0, _) | (_, 0) => None,
+            (width, height) => Some((width * 10, height * 10)),
+        }
+    }
+}
@@ -1,19 +1,52 @@
 #![no_std]
 
+use core::fmt::{self, Write};
+
 pub mod optional;
 pub mod tls_template;
 pub mod framebuffer;
 pub mod memory;
+pub mod compression;
+pub mod manifest;
+pub mod firmware;
+pub mod smp;
+pub mod timing;
+pub mod slot;
 pub mod boot;
+pub mod extension;
+pub mod dump;
+pub mod serial;
 
 #[macro_export]
 macro_rules! entry_point {
     ($path:path) => {
         #[export_name = "_start"]
-        pub extern "C" fn __impl_start(boot_info: &'static mut $crate::boot::BootInfo) -> ! {
+        extern "C" fn __impl_start(boot_info: *mut $crate::boot::BootInfo) -> ! {
             let f: fn(&'static mut $crate::boot::BootInfo) -> ! = $path;
 
-            f(boot_info)
+            // only the header is known to be valid until it was checked
+            let header = unsafe { *boot_info.cast::<$crate::boot::BootInfoHeader>() };
+            if let Err(err) = header.check() {
+                $crate::__boot_info_mismatch(format_args!(
+                    "{err} (magic {:#x}, version {}, size {}, expected version {}, size {})",
+                    header.magic,
+                    header.version,
+                    header.size,
+                    $crate::boot::BOOT_INFO_VERSION,
+                    core::mem::size_of::<$crate::boot::BootInfo>(),
+                ));
+            }
+
+            f(unsafe { &mut *boot_info })
         }
     };
-}
\ No newline at end of file
+}
+
+/// Reports a boot info that does not match this crate and panics, the message goes to COM1 first
+/// because the kernel cannot set up its own logging without a valid boot info.
+#[doc(hidden)]
+pub fn __boot_info_mismatch(message: fmt::Arguments) -> ! {
+    let mut serial = unsafe { serial::EarlySerial::init() };
+    let _ = writeln!(serial, "{message}");
+    panic!("{message}");
+}
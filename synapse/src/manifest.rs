@@ -0,0 +1,69 @@
+pub const MANIFEST_MAGIC: [u8; 8] = *b"LIFE.SIG";
+pub const MANIFEST_LEN: usize = 208;
+pub const MANIFEST_SIGNED_LEN: usize = 144;
+
+/// Suffix of the detached manifest of a single file outside of the kernel slots, e.g. a Multiboot2
+/// kernel or module, which only uses the kernel hash.
+pub const SIGNATURE_SUFFIX: &str = ".sig";
+
+pub const HASH_LEN: usize = 64;
+pub const SIGNATURE_LEN: usize = 64;
+
+const RAMDISK_PRESENT: u32 = 1;
+
+/// SHA-512 hashes of the boot files, signed with Ed25519 by the host tool.
+///
+/// The hashes cover the files as stored on the boot partition, so compressed
+/// files are verified before they are decompressed.
+///
+/// All fields are little-endian:
+/// magic (8 bytes), flags (u32), reserved (u32), kernel hash (64 bytes),
+/// ramdisk hash (64 bytes), signature over the preceding 144 bytes (64 bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Manifest {
+    pub kernel_hash: [u8; HASH_LEN],
+    pub ramdisk_hash: Option<[u8; HASH_LEN]>,
+    pub signature: [u8; SIGNATURE_LEN],
+}
+
+impl Manifest {
+    /// Returns `None` if `data` is not a manifest.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() != MANIFEST_LEN || data[..8] != MANIFEST_MAGIC {
+            return None;
+        }
+
+        let flags = u32::from_le_bytes(data[8..12].try_into().unwrap());
+
+        Some(Self {
+            kernel_hash: data[16..80].try_into().unwrap(),
+            ramdisk_hash: (flags & RAMDISK_PRESENT != 0).then(|| data[80..144].try_into().unwrap()),
+            signature: data[144..208].try_into().unwrap(),
+        })
+    }
+
+    /// The part of the manifest covered by the signature.
+    pub fn signed_bytes(&self) -> [u8; MANIFEST_SIGNED_LEN] {
+        let mut bytes = [0; MANIFEST_SIGNED_LEN];
+
+        let flags = if self.ramdisk_hash.is_some() { RAMDISK_PRESENT } else { 0 };
+
+        bytes[..8].copy_from_slice(&MANIFEST_MAGIC);
+        bytes[8..12].copy_from_slice(&flags.to_le_bytes());
+        bytes[16..80].copy_from_slice(&self.kernel_hash);
+        if let Some(ramdisk_hash) = &self.ramdisk_hash {
+            bytes[80..144].copy_from_slice(ramdisk_hash);
+        }
+
+        bytes
+    }
+
+    pub fn to_bytes(&self) -> [u8; MANIFEST_LEN] {
+        let mut bytes = [0; MANIFEST_LEN];
+
+        bytes[..MANIFEST_SIGNED_LEN].copy_from_slice(&self.signed_bytes());
+        bytes[MANIFEST_SIGNED_LEN..].copy_from_slice(&self.signature);
+
+        bytes
+    }
+}
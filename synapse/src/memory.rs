@@ -6,6 +6,8 @@ use core::{ops, slice};
 pub enum MemoryRegionKind {
     Usable,
     Bootloader,
+    /// Failed the memory test of the bootloader.
+    Bad,
     UnknownUefi(u32),
 }
 
@@ -62,3 +64,54 @@ impl From<MemoryRegions> for &'static mut [MemoryRegion] {
         unsafe { slice::from_raw_parts_mut(regions.ptr, regions.len) }
     }
 }
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub enum VirtualRegionKind {
+    /// Reserved for identity mappings of the bootloader, such as the GDT and the context switch.
+    Identity,
+    Kernel,
+    KernelStack,
+    ApStack,
+    TlsBlock,
+    Framebuffer,
+    Ramdisk,
+    PhysicalMemory,
+    BootInfo,
+}
+
+/// A range of the kernel address space reserved by the bootloader.
+///
+/// Ranges are page-aligned and sorted by start address, unused guard gaps
+/// are left between them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(C)]
+pub struct VirtualRegion {
+    pub start: u64,
+    pub end: u64,
+    pub kind: VirtualRegionKind,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct VirtualRegions {
+    pub ptr: *mut VirtualRegion,
+    pub len: usize,
+}
+
+impl ops::Deref for VirtualRegions {
+    type Target = [VirtualRegion];
+
+    fn deref(&self) -> &Self::Target {
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl From<&'static mut [VirtualRegion]> for VirtualRegions {
+    fn from(regions: &'static mut [VirtualRegion]) -> Self {
+        VirtualRegions {
+            ptr: regions.as_mut_ptr(),
+            len: regions.len(),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use core::{arch::asm, fmt};
+
+const COM1: u16 = 0x3f8;
+const LINE_STATUS_TRANSMIT_EMPTY: u8 = 1 << 5;
+
+/// Polled COM1 output for kernels that cannot use anything from the boot info yet.
+pub struct EarlySerial(());
+
+impl EarlySerial {
+    /// Programs COM1 for 115200 baud 8N1 without interrupts.
+    ///
+    /// # Safety
+    ///
+    /// Needs I/O privilege and nothing else may use COM1 at the same time.
+    pub unsafe fn init() -> Self {
+        // interrupts off, divisor 1 with DLAB set, 8N1, FIFO enabled and cleared, DTR, RTS and OUT2
+        for (offset, value) in [(1, 0), (3, 0x80), (0, 1), (1, 0), (3, 0x03), (2, 0x07), (4, 0x0b)] {
+            outb(COM1 + offset, value);
+        }
+        Self(())
+    }
+}
+
+impl fmt::Write for EarlySerial {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            unsafe {
+                // a missing UART reads as 0xff, so this does not hang without one
+                while inb(COM1 + 5) & LINE_STATUS_TRANSMIT_EMPTY == 0 {}
+                outb(COM1, byte);
+            }
+        }
+        Ok(())
+    }
+}
+
+unsafe fn outb(port: u16, value: u8) {
+    asm!("out dx, al", in("dx") port, in("al") value, options(nomem, nostack, preserves_flags));
+}
+
+unsafe fn inb(port: u16) -> u8 {
+    let value;
+    asm!("in al, dx", out("al") value, in("dx") port, options(nomem, nostack, preserves_flags));
+    value
+}
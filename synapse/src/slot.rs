@@ -0,0 +1,150 @@
+/// UEFI variable holding the `BootState`, as a null-terminated UCS-2 string.
+pub const BOOT_STATE_VARIABLE_NAME: [u16; 14] = ucs2("LifeBootState");
+
+/// Vendor GUID of `BOOT_STATE_VARIABLE_NAME` (5c7a3e1d-9b42-4f6e-8a1d-3f0b6c2e7d94) in EFI byte order.
+pub const BOOT_STATE_VENDOR_GUID: [u8; 16] = [
+    0x1d, 0x3e, 0x7a, 0x5c, 0x42, 0x9b, 0x6e, 0x4f, 0x8a, 0x1d, 0x3f, 0x0b, 0x6c, 0x2e, 0x7d, 0x94,
+];
+
+/// `EFI_VARIABLE_NON_VOLATILE | EFI_VARIABLE_BOOTSERVICE_ACCESS | EFI_VARIABLE_RUNTIME_ACCESS`
+pub const BOOT_STATE_VARIABLE_ATTRIBUTES: u32 = 0x7;
+
+pub const BOOT_STATE_LEN: usize = 2;
+
+const fn ucs2<const N: usize>(s: &str) -> [u16; N] {
+    let bytes = s.as_bytes();
+    let mut out = [0; N];
+    let mut i = 0;
+    while i < bytes.len() {
+        out[i] = bytes[i] as u16;
+        i += 1;
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum BootSlot {
+    A = 0,
+    B = 1,
+}
+
+impl BootSlot {
+    pub const ALL: [BootSlot; 2] = [BootSlot::A, BootSlot::B];
+
+    pub const fn other(self) -> Self {
+        match self {
+            BootSlot::A => BootSlot::B,
+            BootSlot::B => BootSlot::A,
+        }
+    }
+
+    pub const fn kernel_file_name(self) -> &'static str {
+        match self {
+            BootSlot::A => "kernel-x86_64",
+            BootSlot::B => "kernel-x86_64.b",
+        }
+    }
+
+    pub const fn ramdisk_file_name(self) -> &'static str {
+        match self {
+            BootSlot::A => "ramdisk",
+            BootSlot::B => "ramdisk.b",
+        }
+    }
+
+    pub const fn manifest_file_name(self) -> &'static str {
+        match self {
+            BootSlot::A => "manifest",
+            BootSlot::B => "manifest.b",
+        }
+    }
+}
+
+/// Content of the boot state variable: the slot to boot and the attempts left in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootState {
+    pub slot: BootSlot,
+    pub attempts_left: u8,
+}
+
+impl BootState {
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let slot = match data.first()? {
+            0 => BootSlot::A,
+            1 => BootSlot::B,
+            _ => return None,
+        };
+
+        Some(Self {
+            slot,
+            attempts_left: *data.get(1)?,
+        })
+    }
+
+    pub fn to_bytes(&self) -> [u8; BOOT_STATE_LEN] {
+        [self.slot as u8, self.attempts_left]
+    }
+}
+
+type SetVariableFn = unsafe extern "win64" fn(
+    name: *const u16,
+    vendor: *const [u8; 16],
+    attributes: u32,
+    data_size: usize,
+    data: *const u8,
+) -> usize;
+
+/// Offset of `SetVariable` in the UEFI runtime services table.
+const SET_VARIABLE_OFFSET: u64 = 88;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootSlotInfo {
+    /// The slot the kernel was loaded from.
+    pub slot: BootSlot,
+    /// Attempts left in `slot` before the bootloader falls back to the other slot.
+    pub attempts_left: u8,
+    pub max_attempts: u8,
+    /// Physical address of the UEFI runtime services table, 0 if the runtime regions could not
+    /// be identity mapped.
+    pub runtime_services: u64,
+}
+
+impl BootSlotInfo {
+    /// Marks the boot as successful by resetting the attempts of `slot` in the boot state
+    /// variable, through the UEFI `SetVariable` runtime service.
+    ///
+    /// Returns the UEFI status on failure.
+    ///
+    /// # Safety
+    ///
+    /// The page tables set up by the bootloader must still be active, as the runtime services
+    /// run in physical mode on the identity mapped runtime regions. Must not be called
+    /// concurrently with other runtime services.
+    pub unsafe fn mark_successful(&self) -> Result<(), usize> {
+        if self.runtime_services == 0 {
+            return Err(usize::MAX);
+        }
+
+        let state = BootState {
+            slot: self.slot,
+            attempts_left: self.max_attempts,
+        }
+        .to_bytes();
+
+        let set_variable = *((self.runtime_services + SET_VARIABLE_OFFSET) as *const SetVariableFn);
+        let status = set_variable(
+            BOOT_STATE_VARIABLE_NAME.as_ptr(),
+            &BOOT_STATE_VENDOR_GUID,
+            BOOT_STATE_VARIABLE_ATTRIBUTES,
+            state.len(),
+            state.as_ptr(),
+        );
+
+        match status {
+            0 => Ok(()),
+            status => Err(status),
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use core::slice;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// Entry routine of an application processor, called with its own mailbox.
+pub type ApEntry = extern "C" fn(&'static ApMailbox) -> !;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ApState {
+    /// The processor did not come up when the bootloader started it.
+    Offline = 0,
+    /// The processor runs the startup code of the bootloader, on its way to the kernel page table.
+    Parked = 1,
+    /// The processor runs on the kernel page table, waiting for an entry point.
+    Waiting = 2,
+    /// The processor has been released into the kernel.
+    Running = 3,
+}
+
+/// Spin-wait mailbox of an application processor started by the bootloader.
+///
+/// The layout is shared with the startup code of the bootloader, which is
+/// written in assembly, so fields must not be reordered.
+#[derive(Debug)]
+#[repr(C, align(64))]
+pub struct ApMailbox {
+    pub apic_id: u32,
+    pub state: AtomicU32,
+    pub page_table: AtomicU64,
+    pub code_selector: u16,
+    pub data_selector: u16,
+    pub _reserved: u16,
+    pub gdt_limit: u16,
+    pub gdt_base: u64,
+    pub stack_top: u64,
+    pub entry_point: AtomicU64,
+    pub argument: AtomicU64,
+    /// Physical address of the 5-level paging switch, zero if the kernel uses 4-level paging.
+    pub la57_switch: u64,
+}
+
+impl ApMailbox {
+    pub const fn new(apic_id: u32) -> Self {
+        Self {
+            apic_id,
+            state: AtomicU32::new(ApState::Offline as u32),
+            page_table: AtomicU64::new(0),
+            code_selector: 0,
+            data_selector: 0,
+            _reserved: 0,
+            gdt_limit: 0,
+            gdt_base: 0,
+            stack_top: 0,
+            entry_point: AtomicU64::new(0),
+            argument: AtomicU64::new(0),
+            la57_switch: 0,
+        }
+    }
+
+    pub fn state(&self) -> ApState {
+        match self.state.load(Ordering::Acquire) {
+            1 => ApState::Parked,
+            2 => ApState::Waiting,
+            3 => ApState::Running,
+            _ => ApState::Offline,
+        }
+    }
+
+    /// Releases the processor into `entry` on its own stack.
+    ///
+    /// `argument` is visible to the processor through `ApMailbox::argument`.
+    /// Returns `false` if the processor is not waiting for an entry point.
+    pub fn start(&self, entry: ApEntry, argument: u64) -> bool {
+        if self.state() != ApState::Waiting {
+            return false;
+        }
+
+        self.argument.store(argument, Ordering::Relaxed);
+        self.entry_point.store(entry as usize as u64, Ordering::Release);
+        true
+    }
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct SmpInfo {
+    pub bsp_apic_id: u32,
+    pub mailboxes: *const ApMailbox,
+    pub mailbox_count: usize,
+}
+
+impl SmpInfo {
+    /// Mailboxes of all application processors, the bootstrap processor has none.
+    pub fn mailboxes(&self) -> &'static [ApMailbox] {
+        unsafe { slice::from_raw_parts(self.mailboxes, self.mailbox_count) }
+    }
+}
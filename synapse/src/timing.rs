@@ -0,0 +1,104 @@
+pub const MAX_BOOT_PHASES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub enum BootPhase {
+    CalibrateTsc,
+    /// Loading the boot config and the menu, including entries that return to it.
+    BootMenu,
+    LoadKernel,
+    LoadFramebuffer,
+    LoadRamdisk,
+    FindProcessors,
+    ExitBootServices,
+    CreatePageTables,
+    SetUpMappings,
+    CreateBootInfo,
+    StartProcessors,
+}
+
+impl BootPhase {
+    pub fn name(self) -> &'static str {
+        match self {
+            BootPhase::CalibrateTsc => "calibrate TSC",
+            BootPhase::BootMenu => "boot menu",
+            BootPhase::LoadKernel => "load kernel",
+            BootPhase::LoadFramebuffer => "load framebuffer",
+            BootPhase::LoadRamdisk => "load ramdisk",
+            BootPhase::FindProcessors => "find processors",
+            BootPhase::ExitBootServices => "exit boot services",
+            BootPhase::CreatePageTables => "create page tables",
+            BootPhase::SetUpMappings => "set up mappings",
+            BootPhase::CreateBootInfo => "create boot info",
+            BootPhase::StartProcessors => "start processors",
+        }
+    }
+}
+
+/// Time stamp counter values at the start and the end of a boot phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct PhaseTimestamp {
+    pub phase: BootPhase,
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootTimings {
+    /// Time stamp counter frequency in Hz, calibrated against the UEFI `Stall` service.
+    pub tsc_frequency: u64,
+    /// Time stamp counter value when the bootloader was entered.
+    pub bootloader_entry: u64,
+    /// Time stamp counter value right before the jump to the kernel.
+    pub kernel_entry: u64,
+    pub phases: [PhaseTimestamp; MAX_BOOT_PHASES],
+    pub len: usize,
+}
+
+impl BootTimings {
+    pub const fn new(bootloader_entry: u64) -> Self {
+        Self {
+            tsc_frequency: 0,
+            bootloader_entry,
+            kernel_entry: 0,
+            phases: [PhaseTimestamp {
+                phase: BootPhase::CalibrateTsc,
+                start: 0,
+                end: 0,
+            }; MAX_BOOT_PHASES],
+            len: 0,
+        }
+    }
+
+    /// Records a phase, phases beyond `MAX_BOOT_PHASES` are dropped.
+    pub fn push(&mut self, phase: BootPhase, start: u64, end: u64) {
+        if let Some(slot) = self.phases.get_mut(self.len) {
+            *slot = PhaseTimestamp { phase, start, end };
+            self.len += 1;
+        }
+    }
+
+    pub fn phases(&self) -> &[PhaseTimestamp] {
+        &self.phases[..self.len]
+    }
+
+    /// Converts a time stamp counter delta to microseconds.
+    pub fn ticks_to_us(&self, ticks: u64) -> u64 {
+        if self.tsc_frequency == 0 {
+            return 0;
+        }
+
+        (u128::from(ticks) * 1_000_000 / u128::from(self.tsc_frequency)) as u64
+    }
+
+    pub fn duration_us(&self, phase: &PhaseTimestamp) -> u64 {
+        self.ticks_to_us(phase.end.saturating_sub(phase.start))
+    }
+
+    /// Time spent between the bootloader entry and the jump to the kernel.
+    pub fn total_us(&self) -> u64 {
+        self.ticks_to_us(self.kernel_entry.saturating_sub(self.bootloader_entry))
+    }
+}
@@ -4,4 +4,17 @@ pub struct TlsTemplate {
     pub start_address: u64,
     pub file_size: u64,
     pub mem_size: u64,
-}
\ No newline at end of file
+    pub align: u64,
+}
+
+/// Thread local storage block of the bootstrap processor, set up from the `TlsTemplate`.
+///
+/// Uses the x86-64 variant II layout: the initialised data ends right before the thread
+/// pointer, which `FS_BASE` points to and whose first word points to itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+pub struct TlsBlock {
+    pub start_address: u64,
+    pub len: u64,
+    pub thread_pointer: u64,
+}
@@ -0,0 +1,27 @@
+use synapse::boot::{BootInfo, BootInfoHeader, BOOT_INFO_MAGIC, BOOT_INFO_VERSION};
+
+#[test]
+fn accepts_current_header() {
+    assert_eq!(BootInfoHeader::current().check(), Ok(()));
+
+    // a newer bootloader may pass a larger boot info
+    let header = BootInfoHeader {
+        size: BootInfoHeader::current().size + 64,
+        ..BootInfoHeader::current()
+    };
+    assert_eq!(header.check(), Ok(()));
+}
+
+#[test]
+fn rejects_mismatched_header() {
+    let current = BootInfoHeader::current();
+    let headers = [
+        BootInfoHeader { magic: !BOOT_INFO_MAGIC, ..current },
+        BootInfoHeader { version: BOOT_INFO_VERSION + 1, ..current },
+        BootInfoHeader { size: std::mem::size_of::<BootInfo>() as u32 - 8, ..current },
+    ];
+
+    for header in headers {
+        assert!(header.check().is_err(), "{header:?}");
+    }
+}
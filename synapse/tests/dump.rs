@@ -0,0 +1,82 @@
+use synapse::boot::PagingMode;
+use synapse::dump::{PageRange, Record};
+use synapse::memory::{MemoryRegion, MemoryRegionKind, VirtualRegion, VirtualRegionKind};
+
+#[test]
+fn records_round_trip() {
+    let records = [
+        Record::BootInfo { version: 1, size: 4096 },
+        Record::Bootloader("initium 0.1.0"),
+        Record::Firmware { revision: 0x10000, uefi_major: 2, uefi_minor: 70, vendor: "EDK II" },
+        Record::Firmware { revision: 0, uefi_major: 2, uefi_minor: 0, vendor: "" },
+        Record::Smbios(0xf0000),
+        Record::Smbios3(0x7f8e_0000),
+        Record::Paging(PagingMode::Level4),
+        Record::Paging(PagingMode::Level5),
+        Record::PhysicalMemoryOffset(0xffff_8000_0000_0000),
+        Record::Framebuffer { address: 0x8000_0000, len: 0x30_0000, width: 1280, height: 800 },
+        Record::Rsdp(0x7fb7_e014),
+        Record::Ramdisk { address: 0x1_0000_0000, len: 0x1234 },
+        Record::Memory(MemoryRegion { start: 0, end: 0xa0000, kind: MemoryRegionKind::Usable }),
+        Record::Memory(MemoryRegion { start: 0x10_0000, end: 0x20_0000, kind: MemoryRegionKind::Bootloader }),
+        Record::Memory(MemoryRegion { start: 0x20_0000, end: 0x20_1000, kind: MemoryRegionKind::Bad }),
+        Record::Memory(MemoryRegion { start: 0xa0000, end: 0x10_0000, kind: MemoryRegionKind::UnknownUefi(11) }),
+        Record::Virtual(VirtualRegion {
+            start: 0xffff_ffff_8000_0000,
+            end: 0xffff_ffff_8020_0000,
+            kind: VirtualRegionKind::Kernel,
+        }),
+        Record::Page(PageRange {
+            start: 0xffff_ffff_ffff_f000,
+            // the last page of the address space ends at 0
+            end: 0,
+            physical_start: 0x1000,
+            flags: 0x8000_0000_0000_0083,
+        }),
+    ];
+
+    for record in records {
+        let line = record.to_string();
+        assert_eq!(Record::parse(&line), Ok(record), "{line}");
+    }
+}
+
+#[test]
+fn virtual_region_kinds_round_trip() {
+    let kinds = [
+        VirtualRegionKind::Identity,
+        VirtualRegionKind::Kernel,
+        VirtualRegionKind::KernelStack,
+        VirtualRegionKind::ApStack,
+        VirtualRegionKind::TlsBlock,
+        VirtualRegionKind::Framebuffer,
+        VirtualRegionKind::Ramdisk,
+        VirtualRegionKind::PhysicalMemory,
+        VirtualRegionKind::BootInfo,
+    ];
+
+    for kind in kinds {
+        let record = Record::Virtual(VirtualRegion { start: 0x1000, end: 0x2000, kind });
+        assert_eq!(Record::parse(&record.to_string()), Ok(record));
+    }
+}
+
+#[test]
+fn rejects_malformed_records() {
+    let lines = [
+        "",
+        "unknown 0x0",
+        "rsdp 1000",
+        "rsdp 0x1000 0x2000",
+        "smbios",
+        "paging 3-level",
+        "firmware 0x1 2",
+        "firmware 0x100000000 2.70 vendor",
+        "memory 0x0 0x1000 free",
+        "virtual 0x0 0x1000",
+    ];
+
+    for line in lines {
+        assert!(Record::parse(line).is_err(), "{line}");
+    }
+}
@@ -0,0 +1,50 @@
+use synapse::extension::{ExtensionWriter, Extensions, TAG_BOOTLOADER_NAME};
+
+fn tags(extensions: &Extensions) -> Vec<(u32, Vec<u8>)> {
+    extensions.iter().map(|extension| (extension.tag, extension.data.to_vec())).collect()
+}
+
+#[test]
+fn iterates_records() {
+    let mut buf = [0xff; 64];
+    let mut writer = ExtensionWriter::new(&mut buf);
+    assert!(writer.push(TAG_BOOTLOADER_NAME, b"initium 0.1.0"));
+    assert!(writer.push(7, &[]));
+    assert!(writer.push(8, &[1, 2, 3]));
+    let extensions = writer.finish();
+
+    assert_eq!(extensions.len, 24 + 8 + 16);
+    assert_eq!(
+        tags(&extensions),
+        [(TAG_BOOTLOADER_NAME, b"initium 0.1.0".to_vec()), (7, Vec::new()), (8, vec![1, 2, 3])]
+    );
+    assert_eq!(extensions.bootloader_name(), Some("initium 0.1.0"));
+    assert_eq!(extensions.get(8), Some(&[1, 2, 3][..]));
+    assert_eq!(extensions.get(9), None);
+}
+
+#[test]
+fn rejects_records_that_do_not_fit() {
+    let mut buf = [0; 16];
+    let mut writer = ExtensionWriter::new(&mut buf);
+    assert!(!writer.push(1, &[0; 9]));
+    assert!(writer.push(1, &[0; 8]));
+    assert!(!writer.push(2, &[]));
+    assert_eq!(tags(&writer.finish()), [(1, vec![0; 8])]);
+}
+
+#[test]
+fn truncated_record_ends_the_list() {
+    let mut buf = [0; 32];
+    let mut writer = ExtensionWriter::new(&mut buf);
+    assert!(writer.push(1, &[1]));
+    assert!(writer.push(2, &[2; 8]));
+    let extensions = writer.finish();
+
+    let truncated = Extensions {
+        ptr: extensions.ptr,
+        len: extensions.len - 1,
+    };
+    assert_eq!(tags(&truncated), [(1, vec![1])]);
+    assert!(Extensions::empty().iter().next().is_none());
+}
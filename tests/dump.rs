@@ -0,0 +1,121 @@
+// the host tool has no library target, so the module is built into the test directly
+#[path = "../src/dump.rs"]
+mod dump;
+
+use std::fs;
+
+use dump::Dump;
+use synapse::boot::PagingMode;
+use synapse::dump::{PageRange, Record, DUMP_BEGIN, DUMP_END};
+use synapse::memory::{MemoryRegion, MemoryRegionKind, VirtualRegion, VirtualRegionKind};
+
+fn write_dump(log: &mut String, bootloader: &str, records: &[Record]) {
+    log.push_str(DUMP_BEGIN);
+    log.push_str("\r\n");
+    for record in [Record::BootInfo { version: 1, size: 4096 }, Record::Bootloader(bootloader)]
+        .iter()
+        .chain(records)
+    {
+        log.push_str(&format!("{record}\r\n"));
+    }
+    log.push_str(DUMP_END);
+    log.push_str("\r\n");
+}
+
+fn sample_log() -> String {
+    let mut log = String::from("BdsDxe: starting Boot0001\r\n");
+    write_dump(&mut log, "initium 0.0.9", &[]);
+    log.push_str("[INFO]: rebooting\r\n");
+    write_dump(
+        &mut log,
+        "initium 0.1.0",
+        &[
+            Record::Firmware { revision: 0x10000, uefi_major: 2, uefi_minor: 70, vendor: "EDK II" },
+            Record::Paging(PagingMode::Level4),
+            Record::Rsdp(0x7fb7_e014),
+            Record::Memory(MemoryRegion { start: 0, end: 0xa0000, kind: MemoryRegionKind::Usable }),
+            Record::Memory(MemoryRegion { start: 0x10_0000, end: 0x20_0000, kind: MemoryRegionKind::Bootloader }),
+            Record::Memory(MemoryRegion { start: 0x20_0000, end: 0x20_1000, kind: MemoryRegionKind::UnknownUefi(11) }),
+            Record::Virtual(VirtualRegion {
+                start: 0xffff_ffff_8000_0000,
+                end: 0xffff_ffff_8020_0000,
+                kind: VirtualRegionKind::Kernel,
+            }),
+            Record::Page(PageRange {
+                start: 0xffff_ffff_8000_0000,
+                end: 0xffff_ffff_8000_2000,
+                physical_start: 0x10_0000,
+                flags: 0x8000_0000_0000_0003,
+            }),
+            Record::Page(PageRange {
+                start: 0xffff_8000_0000_0000,
+                end: 0xffff_8000_0000_1000,
+                physical_start: 0,
+                flags: 0x1,
+            }),
+        ],
+    );
+    log.push_str("[INFO]: dump written\r\n");
+    log
+}
+
+#[test]
+fn parses_the_last_dump() {
+    let dump = Dump::parse(&sample_log()).unwrap();
+
+    assert_eq!((dump.version, dump.size), (1, 4096));
+    assert_eq!(dump.bootloader.as_deref(), Some("initium 0.1.0"));
+    assert_eq!(dump.firmware, Some(("EDK II".to_owned(), 0x10000, (2, 70))));
+    assert_eq!(dump.paging, Some(PagingMode::Level4));
+    assert_eq!(dump.rsdp, Some(0x7fb7_e014));
+    assert_eq!(dump.smbios, None);
+    assert_eq!(dump.memory_regions.len(), 3);
+    assert_eq!(dump.virtual_regions.len(), 1);
+    assert_eq!(dump.pages.len(), 2);
+}
+
+#[test]
+fn rejects_missing_and_truncated_dumps() {
+    assert!(Dump::parse("BdsDxe: starting Boot0001\n").is_err());
+
+    let log = sample_log();
+    let truncated = &log[..log.rfind(DUMP_END).unwrap()];
+    assert!(Dump::parse(truncated).is_err());
+
+    let malformed = log.replace("rsdp 0x7fb7e014", "rsdp 7fb7e014");
+    assert!(Dump::parse(&malformed).is_err());
+}
+
+#[test]
+fn tables_show_regions_and_pages() {
+    let tables = Dump::parse(&sample_log()).unwrap().tables();
+
+    assert!(tables.starts_with("initium 0.1.0, boot info version 1 (4096 bytes)\n"), "{tables}");
+    assert!(tables.contains("EDK II revision 0x10000, UEFI 2.70"), "{tables}");
+    assert!(tables.contains("paging:                 4-level"), "{tables}");
+    assert!(tables.contains("uefi (11)"), "{tables}");
+    assert!(tables.contains("640.0 KiB usable"), "{tables}");
+    // the kernel region has 8 KiB of its 2 MiB mapped
+    assert!(tables.contains("   2.0 MiB    8.0 KiB  kernel"), "{tables}");
+    assert!(tables.contains("8.0 KiB  RW-"), "{tables}");
+    assert!(tables.contains("1 page ranges are outside of the virtual regions"), "{tables}");
+}
+
+#[test]
+fn writes_maps_and_svg() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("serial.log");
+    let svg = dir.path().join("map.svg");
+    fs::write(&log, sample_log()).unwrap();
+
+    dump::run(&log, Some(&svg)).unwrap();
+
+    let svg = fs::read_to_string(svg).unwrap();
+    assert!(svg.starts_with("<svg "), "{svg}");
+    assert!(svg.ends_with("</svg>\n"), "{svg}");
+    assert!(svg.contains("0xffffffff80000000-0xffffffff80200000 kernel"), "{svg}");
+    assert!(svg.contains("0x200000-0x201000 uefi"), "{svg}");
+
+    let map = Dump::parse(&sample_log()).unwrap().ascii_map();
+    assert!(map.contains('K') && map.contains('#'), "{map}");
+}